@@ -0,0 +1,80 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Measures how fast memory is acquired from a file-backed source with each
+//! read size and reader count, to catch throughput regressions.
+//!
+//! ```text
+//! cargo run --release --example read_throughput -- [MiB]
+//! ```
+//!
+//! The source is a temporary file of the given size (256 MiB by default),
+//! read as `/dev/crash` and `/dev/mem` are, in page-aligned reads. The
+//! snapshot is discarded, so only reading and encoding are measured.
+
+use avml::{
+    ONE_MIB, Source,
+    image::{Block, Format, Image, READ_SIZES},
+    source::FileSource,
+};
+use core::num::NonZeroUsize;
+use std::{
+    env::args,
+    error::Error,
+    fs::File,
+    io::{Write as _, sink},
+    path::Path,
+    time::Instant,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let size_mib: u32 = args()
+        .nth(1)
+        .map(|arg| arg.parse())
+        .transpose()?
+        .unwrap_or(256);
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("mem");
+    let mut src = File::create(&path)?;
+    // no page is all zero, so none is left out of the snapshot
+    let chunk = (0..ONE_MIB)
+        .map(|i| u8::try_from(i % 251).unwrap_or_default().wrapping_add(1))
+        .collect::<Vec<_>>();
+    for _ in 0..size_mib {
+        src.write_all(&chunk)?;
+    }
+    src.sync_all()?;
+
+    let len = u64::from(size_mib).saturating_mul(u64::try_from(ONE_MIB)?);
+    let blocks = [Block {
+        offset: 0,
+        range: 0..len,
+    }];
+    let read_sizes = [0x1000].into_iter().chain(READ_SIZES.into_iter().rev());
+    for read_size in read_sizes.filter_map(NonZeroUsize::new) {
+        for readers in [1, 4].into_iter().filter_map(NonZeroUsize::new) {
+            let start = Instant::now();
+            acquire(&path, &blocks, read_size, readers)?;
+            let rate = f64::from(size_mib) / start.elapsed().as_secs_f64();
+            println!("read size {read_size:>8}  readers {readers}  {rate:>8.1} MiB/s");
+        }
+    }
+    Ok(())
+}
+
+fn acquire(
+    path: &Path,
+    blocks: &[Block],
+    read_size: NonZeroUsize,
+    readers: NonZeroUsize,
+) -> avml::Result<()> {
+    let mut image = Image::<File, File>::with_dst(Format::Lime, path, sink())?.read_size(read_size);
+    if readers.get() > 1 {
+        let source = FileSource::open(Source::Raw(path.to_path_buf()), &[])?;
+        image.write_blocks_parallel(&source, blocks, readers)?;
+        return Ok(());
+    }
+    image.write_blocks(blocks)?;
+    Ok(())
+}
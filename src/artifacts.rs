@@ -0,0 +1,436 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Collecting live-system artifacts alongside a snapshot.
+//!
+//! Memory is far easier to analyse, particularly without symbols, given
+//! the loaded modules, kernel symbols, mounts, sockets and processes of the
+//! host it was acquired from. [`collect`] archives these from `/proc` and
+//! `/sys` as a tar archive, compressed with snappy framing as compressed
+//! snapshots are, that never grows beyond a given size.
+
+use crate::errors::FailureKind;
+use core::ops::Range;
+use snap::write::FrameEncoder;
+use std::{
+    fs::{read, read_dir},
+    io::{Error as IoError, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Files describing the host as a whole, most useful first.
+const SYSTEM_FILES: [&str; 11] = [
+    "proc/version",
+    "proc/cmdline",
+    "proc/modules",
+    "proc/iomem",
+    "proc/meminfo",
+    "proc/mounts",
+    "proc/self/mountinfo",
+    "sys/kernel/notes",
+    "sys/kernel/vmcoreinfo",
+    "proc/kallsyms",
+    "proc/swaps",
+];
+
+/// Files describing each process. Every process's status and command line
+/// is archived before any process's maps, as the maps are far larger.
+const PROCESS_FILES: [&[&str]; 2] = [&["status", "cmdline"], &["maps"]];
+
+const BLOCK_SIZE: usize = 512;
+
+// fields of a ustar header
+const NAME: Range<usize> = 0..100;
+const MODE: Range<usize> = 100..108;
+const UID: Range<usize> = 108..116;
+const GID: Range<usize> = 116..124;
+const SIZE: Range<usize> = 124..136;
+const MTIME: Range<usize> = 136..148;
+const CHECKSUM: Range<usize> = 148..156;
+const TYPE: Range<usize> = 156..157;
+const MAGIC: Range<usize> = 257..265;
+const UNAME: Range<usize> = 265..297;
+const GNAME: Range<usize> = 297..329;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error: {context}")]
+    Io {
+        context: &'static str,
+        #[source]
+        source: IoError,
+    },
+
+    #[error("a size limit of {max_size} bytes is too small for an archive")]
+    TooSmall { max_size: u64 },
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Io { .. } => "artifacts_io",
+            Self::TooSmall { .. } => "artifacts_too_small",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io { ref source, .. } => FailureKind::from_io(source),
+            Self::TooSmall { .. } => FailureKind::Usage,
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// What [`collect`] archived.
+#[derive(Debug, Default)]
+pub struct Collection {
+    /// The files archived.
+    pub archived: Vec<PathBuf>,
+    /// Files left out because they would have taken the archive over its
+    /// size limit.
+    pub skipped: Vec<PathBuf>,
+    /// Bytes written, which is never more than the size limit.
+    pub bytes_written: u64,
+}
+
+/// Archive the live-system artifacts of this host to `dst`, writing at
+/// most `max_size` bytes.
+///
+/// Files that cannot be read, such as those of processes that exit during
+/// collection, are left out, as is `/proc/kallsyms` when the kernel hides
+/// its addresses. Files that would take the archive over `max_size` are
+/// left out and listed in [`Collection::skipped`], and smaller files after
+/// them are still archived.
+///
+/// # Errors
+/// Returns an error if `max_size` cannot hold an empty archive, or `dst`
+/// cannot be written.
+pub fn collect<W: Write>(dst: W, max_size: u64) -> Result<Collection> {
+    collect_from(Path::new("/"), dst, max_size)
+}
+
+fn collect_from<W: Write>(root: &Path, dst: W, max_size: u64) -> Result<Collection> {
+    let mut archive = Archive::new(dst, max_size)?;
+    let mut collection = Collection::default();
+
+    let mut files = SYSTEM_FILES.iter().map(PathBuf::from).collect::<Vec<_>>();
+    files.extend(net_files(root));
+    let pids = pids(root);
+    for names in PROCESS_FILES {
+        for pid in &pids {
+            files.extend(
+                names
+                    .iter()
+                    .map(|name| Path::new("proc").join(pid.to_string()).join(name)),
+            );
+        }
+    }
+
+    for file in files {
+        let Ok(data) = read(root.join(&file)) else {
+            continue;
+        };
+        if file == Path::new("proc/kallsyms") && hides_addresses(&data) {
+            continue;
+        }
+        if archive.add(&file.to_string_lossy(), &data)? {
+            collection.archived.push(Path::new("/").join(file));
+        } else {
+            collection.skipped.push(Path::new("/").join(file));
+        }
+    }
+
+    collection.bytes_written = archive.finish()?;
+    Ok(collection)
+}
+
+// The files in /proc/net, such as the socket tables, sorted by name
+fn net_files(root: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(root.join("proc/net")) else {
+        return Vec::new();
+    };
+    let mut files = entries
+        .filter_map(core::result::Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| Path::new("proc/net").join(entry.file_name()))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+// The processes running, in ascending order
+fn pids(root: &Path) -> Vec<u32> {
+    let Ok(entries) = read_dir(root.join("proc")) else {
+        return Vec::new();
+    };
+    let mut pids = entries
+        .filter_map(core::result::Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect::<Vec<u32>>();
+    pids.sort_unstable();
+    pids
+}
+
+// Without CAP_SYSLOG, or with kernel.kptr_restrict set, every address in
+// /proc/kallsyms reads as zero, which is no use for analysis.
+fn hides_addresses(kallsyms: &[u8]) -> bool {
+    kallsyms.starts_with(b"0000000000000000 ")
+}
+
+/// A tar archive in which each file is compressed as a separate snappy
+/// stream, so the size of the archive is known as each file is added.
+struct Archive<W: Write> {
+    dst: W,
+    max_size: u64,
+    written: u64,
+    trailer: Vec<u8>,
+    mtime: u64,
+}
+
+impl<W: Write> Archive<W> {
+    fn new(dst: W, max_size: u64) -> Result<Self> {
+        // two zero blocks mark the end of the archive
+        let trailer = compress(&[0; 2 * BLOCK_SIZE])?;
+        if len(&trailer) > max_size {
+            return Err(Error::TooSmall { max_size });
+        }
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Ok(Self {
+            dst,
+            max_size,
+            written: 0,
+            trailer,
+            mtime,
+        })
+    }
+
+    /// Add `data` as `name`, unless the archive could then not be finished
+    /// within its size limit.
+    fn add(&mut self, name: &str, data: &[u8]) -> Result<bool> {
+        let mut entry = header(name, len(data), self.mtime).to_vec();
+        entry.extend_from_slice(data);
+        entry.resize(entry.len().next_multiple_of(BLOCK_SIZE), 0);
+        let compressed = compress(&entry)?;
+
+        let size = self
+            .written
+            .saturating_add(len(&compressed))
+            .saturating_add(len(&self.trailer));
+        if size > self.max_size {
+            return Ok(false);
+        }
+        self.write(&compressed)?;
+        Ok(true)
+    }
+
+    /// End the archive, returning its size.
+    fn finish(mut self) -> Result<u64> {
+        let trailer = core::mem::take(&mut self.trailer);
+        self.write(&trailer)?;
+        self.dst.flush().map_err(|source| Error::Io {
+            context: "unable to write artifacts",
+            source,
+        })?;
+        Ok(self.written)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.dst.write_all(data).map_err(|source| Error::Io {
+            context: "unable to write artifacts",
+            source,
+        })?;
+        self.written = self.written.saturating_add(len(data));
+        Ok(())
+    }
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = FrameEncoder::new(Vec::new());
+    encoder.write_all(data).map_err(|source| Error::Io {
+        context: "unable to compress artifacts",
+        source,
+    })?;
+    encoder.into_inner().map_err(|e| Error::Io {
+        context: "unable to compress artifacts",
+        source: e.into_error(),
+    })
+}
+
+fn len(data: &[u8]) -> u64 {
+    u64::try_from(data.len()).unwrap_or(u64::MAX)
+}
+
+/// The ustar header of a regular, world-readable file owned by root.
+fn header(name: &str, size: u64, mtime: u64) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+    let mut set = |field: Range<usize>, value: &[u8]| {
+        if let Some(field) = header.get_mut(field) {
+            for (dst, src) in field.iter_mut().zip(value) {
+                *dst = *src;
+            }
+        }
+    };
+    set(NAME, name.as_bytes());
+    set(MODE, b"0000444\0");
+    set(UID, b"0000000\0");
+    set(GID, b"0000000\0");
+    set(SIZE, format!("{size:011o}\0").as_bytes());
+    set(MTIME, format!("{mtime:011o}\0").as_bytes());
+    // the checksum is computed with its own field as spaces
+    set(CHECKSUM, b"        ");
+    set(TYPE, b"0");
+    set(MAGIC, b"ustar\x0000");
+    set(UNAME, b"root");
+    set(GNAME, b"root");
+
+    let checksum = header
+        .iter()
+        .fold(0_u32, |sum, byte| sum.saturating_add(u32::from(*byte)));
+    if let Some(field) = header.get_mut(CHECKSUM) {
+        for (dst, src) in field
+            .iter_mut()
+            .zip(format!("{checksum:06o}\0 ").as_bytes())
+        {
+            *dst = *src;
+        }
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BLOCK_SIZE, Error, collect_from};
+    use rand::{Rng as _, SeedableRng as _, rngs::SmallRng};
+    use snap::read::FrameDecoder;
+    use std::{
+        fs::{create_dir_all, write},
+        io::Read as _,
+        path::{Path, PathBuf},
+    };
+
+    // The files of a tar archive, by name
+    fn untar(tar: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut rest = tar;
+        while let Some((header, tail)) = rest.split_at_checked(BLOCK_SIZE) {
+            if header.iter().all(|byte| *byte == 0) {
+                break;
+            }
+            let field = |start: usize, end: usize| {
+                let field = header.get(start..end).unwrap_or_default();
+                String::from_utf8_lossy(field)
+                    .trim_end_matches(['\0', ' '])
+                    .to_string()
+            };
+            let size = usize::from_str_radix(&field(124, 135), 8).unwrap_or_default();
+            files.push((field(0, 100), tail.get(..size).unwrap_or_default().to_vec()));
+            rest = tail
+                .get(size.next_multiple_of(BLOCK_SIZE)..)
+                .unwrap_or_default();
+        }
+        files
+    }
+
+    fn fake_root(root: &Path) -> std::io::Result<()> {
+        for dir in [
+            "proc/net/stat",
+            "proc/1",
+            "proc/42",
+            "proc/self",
+            "sys/kernel",
+        ] {
+            create_dir_all(root.join(dir))?;
+        }
+        write(root.join("proc/version"), "Linux version 6.1.0\n")?;
+        write(root.join("proc/modules"), "ext4 1003520 1 - Live 0x0\n")?;
+        write(
+            root.join("proc/kallsyms"),
+            "0000000000000000 T _text\n0000000000000000 T _stext\n",
+        )?;
+        write(root.join("proc/net/tcp"), "  sl  local_address\n")?;
+        write(root.join("proc/net/stat/nf"), "ignored\n")?;
+        write(root.join("proc/1/status"), "Name:\tinit\n")?;
+        write(root.join("proc/1/maps"), vec![b'1'; 0x1000])?;
+        write(root.join("proc/42/cmdline"), "sshd\0-D\0")?;
+        write(root.join("proc/42/maps"), vec![b'2'; 0x1000])?;
+        Ok(())
+    }
+
+    #[test]
+    fn archives_artifacts() -> Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        fake_root(dir.path())?;
+
+        let mut archive = Vec::new();
+        let collection = collect_from(dir.path(), &mut archive, 1024 * 1024)?;
+        assert!(collection.skipped.is_empty());
+        assert_eq!(collection.bytes_written, u64::try_from(archive.len())?);
+
+        let mut tar = Vec::new();
+        FrameDecoder::new(archive.as_slice()).read_to_end(&mut tar)?;
+        let files = untar(&tar);
+        let names = files.iter().map(|file| file.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "proc/version",
+                "proc/modules",
+                "proc/net/tcp",
+                "proc/1/status",
+                "proc/42/cmdline",
+                "proc/1/maps",
+                "proc/42/maps",
+            ]
+        );
+        assert_eq!(
+            files.get(4).map(|file| file.1.as_slice()),
+            Some(b"sshd\0-D\0".as_slice())
+        );
+        assert_eq!(
+            collection.archived.first(),
+            Some(&PathBuf::from("/proc/version"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn never_exceeds_the_size_limit() -> Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        fake_root(dir.path())?;
+        // incompressible, so it cannot fit in the limit below
+        let mut noise = vec![0; 0x10000];
+        SmallRng::seed_from_u64(0).fill_bytes(&mut noise);
+        write(dir.path().join("proc/1/maps"), noise)?;
+
+        let max_size = 4096;
+        let mut archive = Vec::new();
+        let collection = collect_from(dir.path(), &mut archive, max_size)?;
+        assert!(u64::try_from(archive.len())? <= max_size);
+        assert_eq!(collection.skipped, [PathBuf::from("/proc/1/maps")]);
+        // smaller files after the one left out are still archived
+        assert!(
+            collection
+                .archived
+                .contains(&PathBuf::from("/proc/42/maps"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_limits_too_small_for_an_archive() -> Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let collected = collect_from(dir.path(), Vec::new(), 16);
+        assert!(collected.is_err_and(|e| matches!(e, Error::TooSmall { max_size: 16 })));
+        Ok(())
+    }
+}
@@ -1,16 +1,32 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use avml::{Format, Result, Snapshot, Source, iomem};
+use crate::{
+    artifacts::ArtifactsArgs, guest::GuestArgs, process::ProcessArgs, progress::ProgressArgs,
+    reads::ReadArgs, swap, throttle::ThrottleArgs,
+};
+use avml::{
+    Format, Result, Snapshot, Source, Summary,
+    image::{self, OnReadError},
+    iomem,
+    process::ProcessMemory,
+    source::MemorySource,
+};
 #[cfg(feature = "upload")]
 use clap::ArgGroup;
 use clap::Parser;
 #[cfg(feature = "upload")]
 use core::num::NonZeroUsize;
-use core::{num::NonZeroU64, ops::Range};
-use std::path::PathBuf;
+use core::{num::NonZeroU64, ops::Range, str::FromStr};
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+#[cfg(any(feature = "upload", feature = "stream"))]
+use url::Url;
 #[cfg(feature = "upload")]
-use {avml::Error, tokio::fs::remove_file, url::Url};
+use {avml::Error, tokio::fs::remove_file};
 
 #[derive(Parser)]
 #[cfg_attr(
@@ -19,18 +35,34 @@ use {avml::Error, tokio::fs::remove_file, url::Url};
         ArgGroup::new("upload-destination")
             .args(["url", "sas_url"])
             .multiple(true)
-            .required(false),
+            .required(false)
+            .conflicts_with("split_size"),
     ))
 )]
+#[cfg_attr(
+    feature = "upload",
+    expect(
+        clippy::struct_excessive_bools,
+        reason = "each bool is a command-line flag"
+    )
+)]
 pub struct Args {
     /// compress via snappy
     #[arg(long)]
     compress: bool,
 
-    /// specify input source
-    #[arg(long, value_enum)]
+    /// specify input source: /dev/crash, /dev/mem, /proc/kcore, or
+    /// path:<file> for a raw memory file, such as one provided by a
+    /// hypervisor
+    #[arg(long, value_parser = <Source as FromStr>::from_str)]
     source: Option<Source>,
 
+    /// what to do with memory that cannot be read. Defaults to skip with
+    /// --pid, as mappings can be unmapped while they are read, and to
+    /// abort otherwise
+    #[arg(long, value_enum)]
+    on_read_error: Option<OnReadError>,
+
     /// Specify the maximum estimated disk usage (in MiB)
     #[arg(long)]
     max_disk_usage: Option<NonZeroU64>,
@@ -39,6 +71,54 @@ pub struct Args {
     #[arg(long, value_parser = disk_usage_percentage)]
     max_disk_usage_percentage: Option<f64>,
 
+    /// only write pages that changed since this earlier snapshot or manifest
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// write a hash of every page to this manifest, for use as a later
+    /// `--baseline` and to reconstruct the full snapshot from a delta
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// split the snapshot into files of at most this many MiB, named
+    /// `FILENAME.001`, `FILENAME.002`, and so on, each a valid snapshot on
+    /// its own. `FILENAME.parts` lists each file with its SHA-256. Disk
+    /// usage limits apply to each file.
+    #[arg(long)]
+    split_size: Option<NonZeroU64>,
+
+    /// before acquiring memory, archive live-system artifacts such as
+    /// /proc/modules, /proc/kallsyms, /proc/net/* and the maps of each
+    /// process to FILENAME.artifacts.tar.sz, a snappy-compressed tar
+    /// archive
+    #[arg(long, group = "artifacts_destination")]
+    artifacts: bool,
+
+    #[command(flatten)]
+    artifacts_archive: ArtifactsArgs,
+
+    /// after acquiring memory, acquire the active swap areas listed in
+    /// /proc/swaps, including swap files and zram, to FILENAME.swap in
+    /// the same format. The n-th area starts at address n << 40, and
+    /// FILENAME.swaps lists the address and path of each
+    #[arg(long)]
+    swap: bool,
+
+    #[command(flatten)]
+    guest: GuestArgs,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+
+    #[command(flatten)]
+    reads: ReadArgs,
+
+    #[command(flatten)]
+    throttle: ThrottleArgs,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
+
     /// upload via HTTP PUT upon acquisition; mutually exclusive with --sas-url
     #[cfg(feature = "upload")]
     #[arg(long, conflicts_with = "sas_url")]
@@ -64,6 +144,12 @@ pub struct Args {
     #[arg(long)]
     sas_block_concurrency: Option<NonZeroUsize>,
 
+    /// the most block data, in MiB, to buffer across concurrent block
+    /// uploads; concurrency is lowered to fit
+    #[cfg(feature = "upload")]
+    #[arg(long, requires = "sas_url")]
+    max_buffer_memory: Option<NonZeroU64>,
+
     /// name of the file to write to on local system
     filename: PathBuf,
 }
@@ -84,41 +170,178 @@ fn disk_usage_percentage(s: &str) -> core::result::Result<f64, String> {
     }
 }
 
-pub fn run(args: &Args) -> Result<()> {
+/// A snapshot acquired to a local file.
+pub struct Acquired {
+    pub summary: Summary,
+    /// The suffix of each file written alongside the snapshot, named after
+    /// it, in the order written.
+    #[cfg_attr(
+        not(feature = "upload"),
+        expect(dead_code, reason = "only read to upload the sidecars")
+    )]
+    pub sidecars: Vec<&'static str>,
+}
+
+pub fn run(args: &Args) -> Result<Acquired> {
     let format = Format::from(args.compress);
+    args.throttle.apply()?;
 
-    let ranges = iomem::parse()?;
+    let mut sidecars = Vec::new();
+    if args.artifacts {
+        args.artifacts_archive
+            .write(&sidecar(&args.filename, ".artifacts.tar.sz"))?;
+        sidecars.push(".artifacts.tar.sz");
+    }
+
+    let process = args.process.open()?.map(Arc::new);
+    let on_read_error = args
+        .on_read_error
+        .unwrap_or_else(|| args.process.default_on_read_error());
+    let (ranges, memory_source) = memory(args, process.clone())?;
     let snapshot = Snapshot::new(&args.filename, ranges)
         .source(args.source.clone())
+        .memory_source(memory_source)
         .max_disk_usage_percentage(args.max_disk_usage_percentage)
         .max_disk_usage(args.max_disk_usage)
+        .baseline(args.baseline.as_deref())
+        .manifest(args.manifest.as_deref())
+        .split_size(args.split_size)
+        .max_rate(args.throttle.max_rate())
+        .progress(args.progress.reporter())
+        .on_read_error(on_read_error)
         .format(format);
-    snapshot.create()?;
+    let snapshot = args.reads.configure(snapshot);
+    let summary = snapshot.create()?;
+    if let Some(process) = process {
+        process.write_maps(&sidecar(&args.filename, ".maps"))?;
+        sidecars.push(".maps");
+    }
+    if on_read_error != OnReadError::Abort {
+        write_unreadable(&sidecar(&args.filename, ".unreadable"), &summary.unreadable)?;
+        sidecars.push(".unreadable");
+    }
+
+    if args.swap && acquire_swap(args, format, on_read_error)? {
+        sidecars.extend([".swaps", ".swap"]);
+    }
+    Ok(Acquired { summary, sidecars })
+}
+
+// returns whether there were swap areas to acquire
+fn acquire_swap(args: &Args, format: Format, on_read_error: OnReadError) -> Result<bool> {
+    let Some(swap) = swap::open()? else {
+        return Ok(false);
+    };
+    swap::write_areas(&swap, &sidecar(&args.filename, ".swaps"))?;
+    let ranges = swap.ranges(&[]).map_err(|source| avml::Error::Io {
+        context: "unable to list swap areas",
+        source,
+    })?;
+    let filename = sidecar(&args.filename, ".swap");
+    let snapshot = Snapshot::new(&filename, ranges)
+        .memory_source(Some(Arc::new(swap)))
+        .max_disk_usage_percentage(args.max_disk_usage_percentage)
+        .max_disk_usage(args.max_disk_usage)
+        .max_rate(args.throttle.max_rate())
+        .on_read_error(on_read_error)
+        .format(format);
+    args.reads.configure(snapshot).create()?;
+    Ok(true)
+}
+
+// The memory to acquire, and what to read it from if not one of the
+// physical memory sources of this host.
+type Memory = (Vec<Range<u64>>, Option<Arc<dyn MemorySource>>);
+
+fn memory(args: &Args, process: Option<Arc<ProcessMemory>>) -> Result<Memory> {
+    if let Some(process) = process {
+        let ranges = process
+            .mappings()
+            .iter()
+            .map(|mapping| mapping.range.clone())
+            .collect();
+        return Ok((ranges, Some(process)));
+    }
+    if let Some(guest) = args.guest.open()? {
+        return Ok((guest.map().ranges(), Some(Arc::new(guest))));
+    }
+    Ok((iomem::parse()?, None))
+}
+
+// Write the memory that could not be read, one `start..end` range of
+// addresses per line, so the gaps in a snapshot are known after the run.
+fn write_unreadable(path: &Path, unreadable: &[Range<u64>]) -> Result<()> {
+    let mut dst = image::open_dst(path)?;
+    for range in unreadable {
+        writeln!(dst, "{:#x}..{:#x}", range.start, range.end).map_err(|source| {
+            image::Error::Io {
+                context: "unable to write unreadable ranges",
+                source,
+            }
+        })?;
+    }
     Ok(())
 }
 
+// `filename` with `suffix` appended, for files written alongside it
+fn sidecar(filename: &Path, suffix: &str) -> PathBuf {
+    let mut path = filename.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// `url` with `suffix` appended to its path, for uploads alongside it.
+#[cfg(any(feature = "upload", feature = "stream"))]
+pub fn sidecar_url(url: &Url, suffix: &str) -> Url {
+    let mut url = url.clone();
+    let path = format!("{}{suffix}", url.path());
+    url.set_path(&path);
+    url
+}
+
+/// Upload the snapshot, then each of `sidecars`, to the destination given,
+/// naming each sidecar after the snapshot as its file is.
 #[cfg(feature = "upload")]
-pub async fn upload_after_acquire(args: &Args) -> Result<()> {
+pub async fn upload_after_acquire(args: &Args, sidecars: &[&str]) -> Result<()> {
+    // the snapshot, then the files written alongside it
+    let suffixes = [""].iter().chain(sidecars);
     let did_upload = if let Some(ref url) = args.url {
-        avml::put(&args.filename, url).await?;
+        for suffix in suffixes.clone() {
+            let filename = sidecar(&args.filename, suffix);
+            avml::put(
+                &filename,
+                &sidecar_url(url, suffix),
+                args.progress.reporter(),
+            )
+            .await?;
+        }
         true
     } else if let Some(ref sas_url) = args.sas_url {
-        let uploader = avml::BlobUploader::new(sas_url)?
-            .block_size(args.sas_block_size)
-            .concurrency(args.sas_block_concurrency);
-        uploader.upload_file(&args.filename).await?;
+        for suffix in suffixes.clone() {
+            let uploader = avml::BlobUploader::new(&sidecar_url(sas_url, suffix), None)?
+                .block_size(args.sas_block_size)
+                .concurrency(args.sas_block_concurrency)
+                .max_buffer_memory(args.max_buffer_memory)
+                .max_rate(args.throttle.max_rate())
+                .report_progress(args.progress.reporter());
+            uploader
+                .upload_file(&sidecar(&args.filename, suffix))
+                .await?;
+        }
         true
     } else {
         false
     };
 
     if did_upload && args.delete {
-        remove_file(&args.filename)
-            .await
-            .map_err(|source| Error::Io {
-                context: "unable to remove snapshot",
-                source,
-            })?;
+        for suffix in suffixes {
+            remove_file(sidecar(&args.filename, suffix))
+                .await
+                .map_err(|source| Error::Io {
+                    context: "unable to remove snapshot",
+                    source,
+                })?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,61 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{
+    Result,
+    artifacts::{Collection, collect},
+    image::open_dst,
+};
+use clap::Parser;
+use core::num::NonZeroU64;
+use std::path::Path;
+
+/// Options for the archive of live-system artifacts. Each command puts the
+/// option that asks for the archive in the `artifacts_destination` group.
+#[derive(Parser)]
+pub struct ArtifactsArgs {
+    /// the most the archive of live-system artifacts may take, in MiB.
+    /// Files that would take it over are left out
+    #[arg(long, default_value = "32", requires = "artifacts_destination")]
+    artifacts_max_size: NonZeroU64,
+}
+
+impl ArtifactsArgs {
+    /// Archive the live-system artifacts of this host to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let dst = open_dst(path)?;
+        warn(&collect(dst, self.max_size())?);
+        Ok(())
+    }
+
+    /// Archive the live-system artifacts of this host in memory, to send
+    /// alongside a streamed snapshot.
+    #[cfg(feature = "stream")]
+    pub fn collect(&self) -> Result<Vec<u8>> {
+        let mut archive = Vec::new();
+        warn(&collect(&mut archive, self.max_size())?);
+        Ok(archive)
+    }
+
+    fn max_size(&self) -> u64 {
+        self.artifacts_max_size
+            .get()
+            .saturating_mul(1024)
+            .saturating_mul(1024)
+    }
+}
+
+fn warn(collection: &Collection) {
+    if !collection.skipped.is_empty() {
+        eprintln!(
+            "Warning: left {} files out of the artifacts archive to stay within its size limit: {}",
+            collection.skipped.len(),
+            collection
+                .skipped
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
@@ -1,14 +1,21 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use avml::{Error, Format, Result, image};
+use crate::progress::ProgressArgs;
+#[cfg(feature = "blobstore")]
+use avml::BlobReader;
+use avml::{Error, Format, Result, image, progress::Operation};
 use clap::{Parser, ValueEnum};
+#[cfg(feature = "blobstore")]
+use core::num::{NonZeroU64, NonZeroUsize};
 use snap::read::FrameDecoder;
 use std::{
-    fs::{File, metadata},
-    io::{Read, Seek, SeekFrom, Write, copy, repeat},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write, copy, repeat, stdin, stdout},
     path::{Path, PathBuf},
 };
+#[cfg(feature = "blobstore")]
+use url::Url;
 
 #[derive(Parser)]
 pub struct Args {
@@ -20,33 +27,72 @@ pub struct Args {
     #[arg(long, value_enum, default_value_t = CliFormat::Lime)]
     format: CliFormat,
 
-    /// name of the source file to read from on local system
-    src: PathBuf,
+    /// name of the source file to read from on local system, `-` to read
+    /// from stdin, or (with blob support) an `https://` SAS URL to read an
+    /// Azure Block Blob with ranged GETs instead of downloading it first
+    #[arg(value_parser = parse_input)]
+    src: Input,
 
-    /// name of the destination file to write to on local system
+    /// name of the destination file to write to on local system, or `-` to
+    /// write to stdout
     dst: PathBuf,
+
+    /// size in MiB of each ranged GET when the source is a SAS URL; must be
+    /// greater than 0
+    #[cfg(feature = "blobstore")]
+    #[arg(long)]
+    sas_block_size: Option<NonZeroU64>,
+
+    /// number of ranged GETs fetched ahead of the reader when the source is
+    /// a SAS URL; must be greater than 0
+    #[cfg(feature = "blobstore")]
+    #[arg(long)]
+    sas_block_concurrency: Option<NonZeroUsize>,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
+}
+
+/// Where `convert` reads its source snapshot from.
+#[derive(Clone)]
+enum Input {
+    Stdin,
+    File(PathBuf),
+    #[cfg(feature = "blobstore")]
+    Blob(Url),
+}
+
+fn parse_input(s: &str) -> core::result::Result<Input, String> {
+    if s == STDIO {
+        return Ok(Input::Stdin);
+    }
+    #[cfg(feature = "blobstore")]
+    if s.starts_with("https://") || s.starts_with("http://") {
+        return Url::parse(s)
+            .map(Input::Blob)
+            .map_err(|e| format!("`{s}` isn't a valid URL: {e}"));
+    }
+    Ok(Input::File(PathBuf::from(s)))
 }
 
 #[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
-enum CliFormat {
+pub enum CliFormat {
     Raw,
     Lime,
     #[value(rename_all = "snake_case")]
     LimeCompressed,
 }
 
+pub const STDIO: &str = "-";
+
 pub fn run(args: &Args) -> Result<()> {
     match (args.source_format, args.format) {
-        (CliFormat::Lime | CliFormat::LimeCompressed, CliFormat::Raw) => {
-            convert_to_raw(&args.src, &args.dst)
-        }
-        (CliFormat::Lime, CliFormat::LimeCompressed) => {
-            convert(&args.src, &args.dst, Format::AvmlCompressed)
-        }
-        (CliFormat::LimeCompressed, CliFormat::Lime) => convert(&args.src, &args.dst, Format::Lime),
-        (CliFormat::Raw, CliFormat::Lime) => convert_from_raw(&args.src, &args.dst, Format::Lime),
+        (CliFormat::Lime | CliFormat::LimeCompressed, CliFormat::Raw) => convert_to_raw(args),
+        (CliFormat::Lime, CliFormat::LimeCompressed) => convert(args, Format::AvmlCompressed),
+        (CliFormat::LimeCompressed, CliFormat::Lime) => convert(args, Format::Lime),
+        (CliFormat::Raw, CliFormat::Lime) => convert_from_raw(args, Format::Lime),
         (CliFormat::Raw, CliFormat::LimeCompressed) => {
-            convert_from_raw(&args.src, &args.dst, Format::AvmlCompressed)
+            convert_from_raw(args, Format::AvmlCompressed)
         }
         (CliFormat::Lime, CliFormat::Lime)
         | (CliFormat::LimeCompressed, CliFormat::LimeCompressed)
@@ -54,66 +100,90 @@ pub fn run(args: &Args) -> Result<()> {
     }
 }
 
-fn convert(src: &Path, dst: &Path, format: Format) -> Result<()> {
-    let src_len = metadata(src)
-        .map_err(|source| image::Error::Io {
-            context: "unable to read source size",
-            source,
+/// Run the conversion on a blocking thread, leaving the runtime free to
+/// drive the ranged GETs of a blob source.
+#[cfg(feature = "blobstore")]
+pub async fn run_blocking(args: Args) -> Result<()> {
+    tokio::task::spawn_blocking(move || run(&args))
+        .await
+        .map_err(|e| Error::Io {
+            context: "spawn_blocking join failed",
+            source: std::io::Error::other(e.to_string()),
         })?
-        .len();
-    let mut image = image::Image::<File, File>::new(format, src, dst)?;
-    convert_image(&mut image, src_len)
 }
 
-fn convert_image<R, W>(image: &mut image::Image<R, W>, src_len: u64) -> Result<()>
-where
-    R: Read + Seek,
-    W: Write,
-{
-    loop {
-        let current = image
-            .src
-            .stream_position()
-            .map_err(|source| image::Error::Io {
-                context: "unable to get current offset into the memory source",
-                source,
-            })?;
-        if current >= src_len {
-            break;
-        }
+type StreamImage = image::Image<BufReader<Box<dyn Read>>, BufWriter<Box<dyn Write>>>;
 
-        image.convert_block()?;
-    }
+fn open_image(format: Format, args: &Args) -> Result<StreamImage> {
+    let image = image::Image::from_streams(format, open_src(args)?, open_dst(&args.dst)?);
+    Ok(match args.progress.reporter() {
+        Some(reporter) => image.report_progress(reporter, Operation::Convert),
+        None => image,
+    })
+}
 
+fn finish(mut image: StreamImage) -> Result<()> {
+    image.finish()?;
+    image.dst.flush().map_err(|source| image::Error::Io {
+        context: "unable to flush destination",
+        source,
+    })?;
     Ok(())
 }
 
-fn convert_to_raw_image<R, W>(image: &mut image::Image<R, W>, src_len: u64) -> Result<()>
-where
-    R: Read + Seek,
-    W: Write + Seek,
-{
-    loop {
-        let current = image
-            .src
-            .stream_position()
-            .map_err(|source| image::Error::Io {
-                context: "unable to get the current offset into the memory source",
-                source,
-            })?;
-        if current >= src_len {
-            break;
-        }
-        let current_dst = image
-            .dst
-            .stream_position()
-            .map_err(|source| image::Error::Io {
-                context: "unable to get the current offset into the destination stream",
-                source,
-            })?;
+fn convert(args: &Args, format: Format) -> Result<()> {
+    let mut image = open_image(format, args)?;
+    image.convert_blocks()?;
+    finish(image)
+}
+
+fn convert_to_raw(args: &Args) -> Result<()> {
+    let mut image = open_image(Format::Lime, args)?;
+    convert_to_raw_image(&mut image)?;
+    finish(image)
+}
+
+fn convert_from_raw(args: &Args, format: Format) -> Result<()> {
+    let mut image = open_image(format, args)?;
+    encode_raw_image(&mut image)?;
+    finish(image)
+}
+
+fn open_src(args: &Args) -> Result<BufReader<Box<dyn Read>>> {
+    let src: Box<dyn Read> = match args.src {
+        Input::Stdin => Box::new(stdin().lock()),
+        Input::File(ref path) => Box::new(File::open(path).map_err(|source| image::Error::Io {
+            context: "unable to open source file",
+            source,
+        })?),
+        #[cfg(feature = "blobstore")]
+        Input::Blob(ref url) => Box::new(
+            BlobReader::new(url)?
+                .block_size(args.sas_block_size)
+                .concurrency(args.sas_block_concurrency),
+        ),
+    };
+    Ok(BufReader::new(src))
+}
 
-        let header = image.read_header()?;
+pub fn open_dst(path: &Path) -> Result<BufWriter<Box<dyn Write>>> {
+    let dst: Box<dyn Write> = if path == Path::new(STDIO) {
+        Box::new(stdout().lock())
+    } else {
+        Box::new(image::open_dst(path)?)
+    };
+    Ok(BufWriter::new(dst))
+}
 
+fn convert_to_raw_image<R, W>(image: &mut image::Image<R, W>) -> Result<()>
+where
+    R: Read,
+    W: Write,
+{
+    // The destination is written strictly sequentially, so its offset is
+    // tracked here rather than queried via `Seek`.
+    let mut current_dst = 0_u64;
+    while let Some(header) = image::Header::read_next(&mut image.src)? {
         let pad = header.range.start.saturating_sub(current_dst);
         if pad > 0 {
             copy(&mut repeat(0).take(pad), &mut image.dst).map_err(|source| image::Error::Io {
@@ -124,82 +194,63 @@ where
 
         let size = header.size()?;
 
-        match header.format {
+        let copied = match header.format {
             Format::Lime => {
                 let mut handle =
                     (&mut image.src).take(size.try_into().map_err(image::Error::IntConversion)?);
                 copy(&mut handle, &mut image.dst).map_err(|source| image::Error::Io {
                     context: "unable to copy image data",
                     source,
-                })?;
+                })?
             }
             Format::AvmlCompressed => {
-                let mut decoder = FrameDecoder::new(&mut image.src)
-                    .take(size.try_into().map_err(image::Error::IntConversion)?);
-                copy(&mut decoder, &mut image.dst).map_err(|source| image::Error::Io {
-                    context: "unable to copy image data",
-                    source,
-                })?;
-                image
-                    .src
-                    .seek(SeekFrom::Current(8))
-                    .map_err(|source| image::Error::Io {
-                        context: "unable to seek past the compressed size",
+                let copied = {
+                    let mut decoder = FrameDecoder::new(&mut image.src)
+                        .take(size.try_into().map_err(image::Error::IntConversion)?);
+                    copy(&mut decoder, &mut image.dst).map_err(|source| image::Error::Io {
+                        context: "unable to copy image data",
                         source,
-                    })?;
+                    })?
+                };
+                image::read_compressed_len(&mut image.src)?;
+                copied
             }
-        }
+        };
+        current_dst = current_dst.saturating_add(pad).saturating_add(copied);
+        image.record_progress(header.range, pad.saturating_add(copied));
     }
 
     Ok(())
 }
 
-fn convert_to_raw(src: &Path, dst: &Path) -> Result<()> {
-    let src_len = metadata(src)
-        .map_err(|source| image::Error::Io {
-            context: "unable to get source file size",
-            source,
-        })?
-        .len();
-    let mut image = image::Image::<File, File>::new(Format::Lime, src, dst)?;
-    convert_to_raw_image(&mut image, src_len)
-}
-
-fn encode_raw_image<R, W>(image: &mut image::Image<R, W>, raw_len: u64) -> Result<()>
+fn encode_raw_image<R, W>(image: &mut image::Image<R, W>) -> Result<()>
 where
-    R: Read + Seek,
+    R: Read,
     W: Write,
 {
     let mut start = 0_u64;
-    while start < raw_len {
-        let end = raw_len.min(start.saturating_add(image::MAX_BLOCK_SIZE));
-        image.copy_block(start..end)?;
-        start = end;
+    loop {
+        let len = image.copy_raw_block(start)?;
+        if len == 0 {
+            break;
+        }
+        start = start.saturating_add(len);
     }
 
     Ok(())
 }
 
-fn convert_from_raw(src: &Path, dst: &Path, format: Format) -> Result<()> {
-    let src_len = metadata(src)
-        .map_err(|source| image::Error::Io {
-            context: "unable to read source size",
-            source,
-        })?
-        .len();
-    let mut image = image::Image::<File, File>::new(format, src, dst)?;
-    encode_raw_image(&mut image, src_len)
-}
-
 #[cfg(test)]
 mod tests {
-    use super::{convert_image, convert_to_raw_image, encode_raw_image};
-    use avml::{Format, Result, image};
+    use super::{convert_to_raw_image, encode_raw_image};
+    use avml::{Error, Format, Result, image};
     use rand::{Rng as _, SeedableRng as _, rngs::SmallRng};
     use std::io::Cursor;
 
-    fn memory_image(format: Format, src: &[u8]) -> image::Image<Cursor<&[u8]>, Cursor<Vec<u8>>> {
-        image::Image::from_streams(format, Cursor::new(src), Cursor::new(Vec::new()))
+    // `&[u8]` and `Vec<u8>` implement neither `Seek` nor a known length, so
+    // these helpers exercise the same path as stdin/stdout pipelines.
+    fn memory_image(format: Format, src: &[u8]) -> image::Image<&[u8], Vec<u8>> {
+        image::Image::from_streams(format, src, Vec::new())
     }
 
     fn block_size() -> Result<usize> {
@@ -237,23 +288,20 @@ mod tests {
 
     fn encode_raw(raw: &[u8], format: Format) -> Result<Vec<u8>> {
         let mut image = memory_image(format, raw);
-        let total = u64::try_from(raw.len()).map_err(image::Error::IntConversion)?;
-        encode_raw_image(&mut image, total)?;
-        Ok(image.dst.into_inner())
+        encode_raw_image(&mut image)?;
+        Ok(image.dst)
     }
 
     fn convert_encoded(encoded: &[u8], format: Format) -> Result<Vec<u8>> {
-        let encoded_len = u64::try_from(encoded.len()).map_err(image::Error::IntConversion)?;
         let mut image = memory_image(format, encoded);
-        convert_image(&mut image, encoded_len)?;
-        Ok(image.dst.into_inner())
+        image.convert_blocks()?;
+        Ok(image.dst)
     }
 
     fn decode_to_raw(encoded: &[u8]) -> Result<Vec<u8>> {
-        let encoded_len = u64::try_from(encoded.len()).map_err(image::Error::IntConversion)?;
         let mut image = memory_image(Format::Lime, encoded);
-        convert_to_raw_image(&mut image, encoded_len)?;
-        Ok(image.dst.into_inner())
+        convert_to_raw_image(&mut image)?;
+        Ok(image.dst)
     }
 
     fn header_format(encoded: &[u8]) -> Result<Format> {
@@ -298,4 +346,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn truncated_record_is_an_error() -> Result<()> {
+        let raw = build_sparse_raw()?;
+        let compressed = encode_raw(&raw, Format::AvmlCompressed)?;
+        let truncated = compressed
+            .get(..compressed.len().saturating_sub(4))
+            .unwrap_or_default();
+
+        assert!(matches!(
+            convert_encoded(truncated, Format::Lime),
+            Err(Error::Image(image::Error::Io { .. }))
+        ));
+        assert!(matches!(
+            decode_to_raw(truncated),
+            Err(Error::Image(image::Error::Io { .. }))
+        ));
+        Ok(())
+    }
 }
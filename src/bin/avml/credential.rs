@@ -0,0 +1,53 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{ClientCredential, ImdsCredential, Result};
+use azure_core::credentials::TokenCredential;
+use clap::{Parser, ValueEnum};
+use std::sync::Arc;
+
+/// Options for authenticating to Azure Blob Storage without a SAS URL.
+#[derive(Parser)]
+pub struct CredentialArgs {
+    /// authenticate with an Entra ID credential, rather than a SAS token in
+    /// each blob URL. The URLs are then the plain URLs of the blobs
+    #[arg(long, value_enum)]
+    credential: Option<CredentialKind>,
+
+    /// the client ID of the user-assigned managed identity to use, rather
+    /// than the system-assigned identity
+    #[arg(long)]
+    managed_identity_client_id: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum CredentialKind {
+    /// a managed identity of this VM, from the Azure Instance Metadata
+    /// Service
+    ManagedIdentity,
+    /// the federated token in `AZURE_FEDERATED_TOKEN_FILE`, for
+    /// `AZURE_CLIENT_ID` in `AZURE_TENANT_ID`, as set by workload identity
+    WorkloadIdentity,
+    /// the client secret in `AZURE_CLIENT_SECRET`, for `AZURE_CLIENT_ID` in
+    /// `AZURE_TENANT_ID`
+    Environment,
+}
+
+impl CredentialArgs {
+    /// The credential requested, if any.
+    pub fn credential(&self) -> Result<Option<Arc<dyn TokenCredential>>> {
+        let credential: Arc<dyn TokenCredential> = match self.credential {
+            None => return Ok(None),
+            Some(CredentialKind::ManagedIdentity) => {
+                Arc::new(ImdsCredential::new(self.managed_identity_client_id.clone()))
+            }
+            Some(CredentialKind::WorkloadIdentity) => {
+                Arc::new(ClientCredential::workload_identity_from_env()?)
+            }
+            Some(CredentialKind::Environment) => {
+                Arc::new(ClientCredential::client_secret_from_env()?)
+            }
+        };
+        Ok(Some(credential))
+    }
+}
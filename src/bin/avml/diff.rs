@@ -0,0 +1,89 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use crate::convert::{STDIO, open_dst};
+use avml::{
+    Format, Result,
+    diff::{self, Diff},
+    image,
+};
+use clap::Parser;
+use core::ops::Range;
+use std::{
+    fs::File,
+    io::{BufReader, Write, stderr, stdout},
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+pub struct Args {
+    /// write the pages that changed or appeared in the newer snapshot, with
+    /// their newer contents, to this file as a snapshot. `-` writes it to
+    /// stdout, and the report to stderr instead
+    #[arg(long)]
+    delta: Option<PathBuf>,
+
+    /// compress the delta snapshot
+    #[arg(long, requires = "delta")]
+    compress: bool,
+
+    /// only print the summary, not every changed range
+    #[arg(long)]
+    summary: bool,
+
+    /// the older snapshot, in either `LiME` or AVML compressed format
+    old: PathBuf,
+
+    /// the newer snapshot, in either `LiME` or AVML compressed format
+    new: PathBuf,
+}
+
+fn open_src(path: &Path) -> Result<BufReader<File>> {
+    let src = File::open(path).map_err(|source| image::Error::Io {
+        context: "unable to open source file",
+        source,
+    })?;
+    Ok(BufReader::new(src))
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let old = open_src(&args.old)?;
+    let new = open_src(&args.new)?;
+    let result = match args.delta.as_ref() {
+        Some(path) => {
+            diff::diff_with_delta(old, new, Format::from(args.compress), open_dst(path)?)?
+        }
+        None => diff::diff(old, new)?,
+    };
+    // keep the report out of a delta written to stdout
+    let out: Box<dyn Write> = match args.delta.as_deref() {
+        Some(path) if path == Path::new(STDIO) => Box::new(stderr().lock()),
+        _ => Box::new(stdout().lock()),
+    };
+    report(out, &result, args.summary).map_err(|source| image::Error::Io {
+        context: "unable to write report",
+        source,
+    })?;
+    Ok(())
+}
+
+fn report<W: Write>(mut out: W, result: &Diff, summary: bool) -> std::io::Result<()> {
+    if !summary {
+        let mut ranges: Vec<(&Range<u64>, &str)> = result
+            .changed
+            .iter()
+            .map(|r| (r, "changed"))
+            .chain(result.only_in_old.iter().map(|r| (r, "only in old")))
+            .chain(result.only_in_new.iter().map(|r| (r, "only in new")))
+            .collect();
+        ranges.sort_by_key(|&(r, _)| r.start);
+        for (range, kind) in ranges {
+            writeln!(out, "{:#014x}-{:#014x} {kind}", range.start, range.end)?;
+        }
+    }
+    writeln!(out, "unchanged pages:   {}", result.unchanged_pages)?;
+    writeln!(out, "changed pages:     {}", result.changed_pages)?;
+    writeln!(out, "only in old pages: {}", result.only_in_old_pages)?;
+    writeln!(out, "only in new pages: {}", result.only_in_new_pages)?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use crate::convert::{CliFormat, open_dst};
+use avml::{
+    Format, Result,
+    image::{self, OnGap},
+};
+use clap::{Parser, ValueEnum};
+use core::ops::Range;
+use std::{
+    fs::File,
+    io::{BufReader, Write as _},
+    path::PathBuf,
+};
+
+#[derive(Parser)]
+pub struct Args {
+    /// physical address range to extract, as `START-END` with `END`
+    /// exclusive. Addresses may be decimal or `0x`-prefixed hex.
+    #[arg(long, value_parser = parse_range)]
+    range: Range<u64>,
+
+    /// specify output format
+    #[arg(long, value_enum, default_value_t = CliFormat::Raw)]
+    format: CliFormat,
+
+    /// how to handle parts of the range not present in the snapshot
+    #[arg(long, value_enum, default_value_t = CliOnGap::Zero)]
+    on_gap: CliOnGap,
+
+    /// name of the snapshot file to read from on local system, in either
+    /// `LiME` or AVML compressed format
+    src: PathBuf,
+
+    /// name of the destination file to write to on local system, or `-` to
+    /// write to stdout
+    dst: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum CliOnGap {
+    /// zero-fill raw output; omit the gap from `LiME` or AVML output
+    Zero,
+    /// fail if any part of the range is missing from the snapshot
+    Error,
+}
+
+impl From<CliOnGap> for OnGap {
+    fn from(value: CliOnGap) -> Self {
+        match value {
+            CliOnGap::Zero => Self::Zero,
+            CliOnGap::Error => Self::Error,
+        }
+    }
+}
+
+fn parse_addr(s: &str) -> core::result::Result<u64, String> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| format!("`{s}` isn't a valid address: {e}"))
+}
+
+fn parse_range(s: &str) -> core::result::Result<Range<u64>, String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("`{s}` isn't a range of the form START-END"))?;
+    let range = parse_addr(start)?..parse_addr(end)?;
+    if range.start >= range.end {
+        return Err(format!("`{s}` is empty: START must be less than END"));
+    }
+    Ok(range)
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let src = File::open(&args.src).map_err(|source| image::Error::Io {
+        context: "unable to open source file",
+        source,
+    })?;
+    let format = match args.format {
+        CliFormat::Raw => None,
+        CliFormat::Lime => Some(Format::Lime),
+        CliFormat::LimeCompressed => Some(Format::AvmlCompressed),
+    };
+
+    let mut image = image::Image::from_streams(
+        format.unwrap_or(Format::Lime),
+        BufReader::new(src),
+        open_dst(&args.dst)?,
+    );
+    let range = args.range.clone();
+    let gaps = args.on_gap.into();
+    match format {
+        Some(_) => image.extract(range, gaps)?,
+        None => image.extract_raw(range, gaps)?,
+    }
+    image.dst.flush().map_err(|source| image::Error::Io {
+        context: "unable to flush destination",
+        source,
+    })?;
+    Ok(())
+}
@@ -0,0 +1,50 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{
+    Error, Result,
+    guest::{GuestMap, GuestMemory},
+};
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Options for acquiring a QEMU/KVM guest from the host.
+#[derive(Parser)]
+#[expect(
+    clippy::struct_field_names,
+    reason = "clap names each option after its field"
+)]
+pub struct GuestArgs {
+    /// acquire a QEMU/KVM guest, rather than this host, from the file
+    /// backing its RAM, such as a memory-backend-file in /dev/shm or
+    /// hugetlbfs
+    #[arg(long, requires = "guest_map", conflicts_with = "source")]
+    guest_memory: Option<PathBuf>,
+
+    /// the guest's memory map: its QEMU command line, such as a copy of
+    /// /proc/<pid>/cmdline, the output of the monitor command `info mtree`,
+    /// or a JSON array of {"start", "size", "offset"} objects
+    #[arg(long, requires = "guest_memory")]
+    guest_map: Option<PathBuf>,
+
+    /// the memory region in the `info mtree` output backed by
+    /// --guest-memory; defaults to the RAM region mapping the most memory
+    #[arg(long, requires = "guest_map")]
+    guest_region: Option<String>,
+}
+
+impl GuestArgs {
+    /// The guest memory to acquire, if any.
+    pub fn open(&self) -> Result<Option<GuestMemory>> {
+        let (Some(memory), Some(map)) = (self.guest_memory.as_ref(), self.guest_map.as_ref())
+        else {
+            return Ok(None);
+        };
+        let map = GuestMap::read(map, self.guest_region.as_deref())?;
+        let guest = GuestMemory::open(memory, map).map_err(|source| Error::Io {
+            context: "unable to open guest memory",
+            source,
+        })?;
+        Ok(Some(guest))
+    }
+}
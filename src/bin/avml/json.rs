@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Formatting of the JSON written for orchestration tools.
+
+use core::{fmt::Write as _, ops::Range};
+
+/// `value` as a JSON string.
+pub fn string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len().saturating_add(2));
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `value`, already formatted as JSON, or `null` without one.
+pub fn or_null(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".to_string())
+}
+
+/// `range` as a `[start,end]` array.
+pub fn range(range: &Range<u64>) -> String {
+    format!("[{},{}]", range.start, range.end)
+}
+
+/// `ranges` as an array of `[start,end]` arrays.
+pub fn ranges(ranges: &[Range<u64>]) -> String {
+    let ranges = ranges.iter().map(range).collect::<Vec<_>>();
+    format!("[{}]", ranges.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{or_null, ranges, string};
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("a \"b\"\\\n\u{1}"), "\"a \\\"b\\\"\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn formats_ranges_and_nulls() {
+        assert_eq!(ranges(&[0..1, 4096..8192]), "[[0,1],[4096,8192]]");
+        assert_eq!(ranges(&[]), "[]");
+        assert_eq!(or_null(None), "null");
+    }
+}
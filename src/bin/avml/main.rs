@@ -1,8 +1,10 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use avml::Result;
+use crate::output::OutputFormat;
+use avml::{Result, Summary};
 use clap::{Parser, Subcommand};
+use std::{process::ExitCode, time::Instant};
 
 // `acquire` and `stream` both depend on Linux kernel interfaces
 // (/proc/iomem, /proc/kcore, /dev/crash, /dev/mem). They're absent
@@ -10,10 +12,38 @@ use clap::{Parser, Subcommand};
 // only `convert` and `upload` (whichever features the user enabled).
 #[cfg(target_os = "linux")]
 mod acquire;
+#[cfg(target_os = "linux")]
+mod artifacts;
 #[cfg(feature = "convert")]
 mod convert;
+#[cfg(any(feature = "upload", all(feature = "stream", target_os = "linux")))]
+mod credential;
+#[cfg(feature = "convert")]
+mod diff;
+#[cfg(feature = "convert")]
+mod extract;
+#[cfg(target_os = "linux")]
+mod guest;
+mod json;
+#[cfg(feature = "convert")]
+mod merge;
+mod output;
+#[cfg(target_os = "linux")]
+mod probe;
+#[cfg(target_os = "linux")]
+mod process;
+#[cfg(any(feature = "convert", target_os = "linux"))]
+mod progress;
+#[cfg(target_os = "linux")]
+mod reads;
+#[cfg(feature = "convert")]
+mod split;
 #[cfg(all(feature = "stream", target_os = "linux"))]
 mod stream;
+#[cfg(target_os = "linux")]
+mod swap;
+#[cfg(target_os = "linux")]
+mod throttle;
 #[cfg(feature = "upload")]
 mod upload;
 
@@ -21,6 +51,10 @@ mod upload;
 #[derive(Parser)]
 #[command(author, version, long_about = None)]
 struct Cmd {
+    /// how to report the result
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -29,12 +63,36 @@ struct Cmd {
 enum Commands {
     /// Acquire a memory snapshot to a local file (and optionally upload it).
     #[cfg(target_os = "linux")]
-    Acquire(acquire::Args),
+    Acquire(Box<acquire::Args>),
+
+    /// Check which memory sources will work on this host, without
+    /// acquiring a snapshot.
+    #[cfg(target_os = "linux")]
+    Probe(probe::Args),
 
     /// Convert between AVML and `LiME` snapshot formats and a raw memory image.
     #[cfg(feature = "convert")]
     Convert(convert::Args),
 
+    /// Extract a physical address range from a snapshot, as raw memory or
+    /// as a smaller snapshot.
+    #[cfg(feature = "convert")]
+    Extract(extract::Args),
+
+    /// Compare two snapshots of the same host page by page.
+    #[cfg(feature = "convert")]
+    Diff(diff::Args),
+
+    /// Combine several snapshots into one with ordered, non-overlapping
+    /// records.
+    #[cfg(feature = "convert")]
+    Merge(merge::Args),
+
+    /// Split a snapshot at record boundaries into smaller, independently
+    /// valid snapshots.
+    #[cfg(feature = "convert")]
+    Split(split::Args),
+
     /// Upload an already-acquired snapshot file to remote storage.
     #[cfg(feature = "upload")]
     #[command(subcommand)]
@@ -47,34 +105,104 @@ enum Commands {
     Stream(stream::Commands),
 }
 
-#[cfg(not(any(feature = "stream", feature = "upload")))]
-fn main() -> Result<()> {
+impl Commands {
+    fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(target_os = "linux")]
+            Self::Acquire(_) => "acquire",
+            #[cfg(target_os = "linux")]
+            Self::Probe(_) => "probe",
+            #[cfg(feature = "convert")]
+            Self::Convert(_) => "convert",
+            #[cfg(feature = "convert")]
+            Self::Extract(_) => "extract",
+            #[cfg(feature = "convert")]
+            Self::Diff(_) => "diff",
+            #[cfg(feature = "convert")]
+            Self::Merge(_) => "merge",
+            #[cfg(feature = "convert")]
+            Self::Split(_) => "split",
+            #[cfg(feature = "upload")]
+            Self::Upload(_) => "upload",
+            #[cfg(all(feature = "stream", target_os = "linux"))]
+            Self::Stream(_) => "stream",
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let cmd = Cmd::parse();
-    match cmd.command {
+    let name = cmd.command.name();
+    let start = Instant::now();
+    let mut report = None;
+    let result = run(cmd.command, cmd.output, &mut report);
+    output::finish(
+        cmd.output,
+        name,
+        start.elapsed(),
+        &result,
+        report.as_deref(),
+    )
+}
+
+// `report` is set to the JSON report of a command that has one of its own,
+// such as `probe`, to go in the final report.
+#[cfg(not(any(feature = "blobstore", feature = "put")))]
+fn run(
+    command: Commands,
+    output: OutputFormat,
+    report: &mut Option<String>,
+) -> Result<Option<Summary>> {
+    match command {
+        #[cfg(target_os = "linux")]
+        Commands::Acquire(args) => acquire::run(&args).map(|acquired| Some(acquired.summary)),
         #[cfg(target_os = "linux")]
-        Commands::Acquire(args) => acquire::run(&args),
+        Commands::Probe(args) => probe::run(&args, output, report).map(|()| None),
         #[cfg(feature = "convert")]
-        Commands::Convert(args) => convert::run(&args),
+        Commands::Convert(args) => convert::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Extract(args) => extract::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Diff(args) => diff::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Merge(args) => merge::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Split(args) => split::run(&args).map(|()| None),
     }
 }
 
-#[cfg(any(feature = "stream", feature = "upload"))]
+#[cfg(any(feature = "blobstore", feature = "put"))]
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let cmd = Cmd::parse();
-    match cmd.command {
+async fn run(
+    command: Commands,
+    output: OutputFormat,
+    report: &mut Option<String>,
+) -> Result<Option<Summary>> {
+    match command {
         #[cfg(target_os = "linux")]
         Commands::Acquire(args) => {
-            acquire::run(&args)?;
+            let acquired = acquire::run(&args)?;
             #[cfg(feature = "upload")]
-            acquire::upload_after_acquire(&args).await?;
-            Ok(())
+            acquire::upload_after_acquire(&args, &acquired.sidecars).await?;
+            Ok(Some(acquired.summary))
         }
+        #[cfg(target_os = "linux")]
+        Commands::Probe(args) => probe::run(&args, output, report).map(|()| None),
+        #[cfg(all(feature = "convert", feature = "blobstore"))]
+        Commands::Convert(args) => convert::run_blocking(args).await.map(|()| None),
+        #[cfg(all(feature = "convert", not(feature = "blobstore")))]
+        Commands::Convert(args) => convert::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Extract(args) => extract::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Diff(args) => diff::run(&args).map(|()| None),
+        #[cfg(feature = "convert")]
+        Commands::Merge(args) => merge::run(&args).map(|()| None),
         #[cfg(feature = "convert")]
-        Commands::Convert(args) => convert::run(&args),
+        Commands::Split(args) => split::run(&args).map(|()| None),
         #[cfg(feature = "upload")]
-        Commands::Upload(sub) => upload::run(sub).await,
+        Commands::Upload(sub) => upload::run(sub).await.map(|()| None),
         #[cfg(all(feature = "stream", target_os = "linux"))]
-        Commands::Stream(sub) => stream::run(sub).await,
+        Commands::Stream(sub) => stream::run(sub).await.map(Some),
     }
 }
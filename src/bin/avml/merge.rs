@@ -0,0 +1,95 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use crate::convert::open_dst;
+use avml::{
+    Format, Result, image, incremental,
+    merge::{self, Conflict},
+};
+use clap::{Parser, ValueEnum};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+pub struct Args {
+    /// which snapshot's memory to keep where several hold different memory
+    /// for the same range
+    #[arg(long, value_enum, default_value_t = CliConflict::Error)]
+    on_conflict: CliConflict,
+
+    /// compress via snappy
+    #[arg(long)]
+    compress: bool,
+
+    /// treat the two snapshots as a baseline and a delta captured with
+    /// `acquire --baseline`, and rebuild the full snapshot described by the
+    /// delta's manifest, checking every page against its hash
+    #[arg(long, conflicts_with = "on_conflict")]
+    manifest: Option<PathBuf>,
+
+    /// the snapshots to merge, in either `LiME` or AVML compressed format
+    #[arg(required = true, num_args = 1..)]
+    srcs: Vec<PathBuf>,
+
+    /// name of the destination file to write to on local system, or `-` to
+    /// write to stdout
+    dst: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum CliConflict {
+    /// keep the memory from the first snapshot listed
+    First,
+    /// keep the memory from the last snapshot listed
+    Last,
+    /// fail if the snapshots disagree
+    Error,
+}
+
+impl From<CliConflict> for Conflict {
+    fn from(value: CliConflict) -> Self {
+        match value {
+            CliConflict::First => Self::First,
+            CliConflict::Last => Self::Last,
+            CliConflict::Error => Self::Error,
+        }
+    }
+}
+
+fn open_src(path: &Path) -> Result<BufReader<File>> {
+    let src = File::open(path).map_err(|source| image::Error::Io {
+        context: "unable to open source file",
+        source,
+    })?;
+    Ok(BufReader::new(src))
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let format = Format::from(args.compress);
+    if let Some(manifest) = args.manifest.as_ref() {
+        let [ref baseline, ref delta] = *args.srcs.as_slice() else {
+            return Err(avml::Error::Usage(
+                "--manifest requires exactly two snapshots: the baseline and the delta",
+            ));
+        };
+        incremental::reconstruct(
+            open_src(baseline)?,
+            open_src(delta)?,
+            open_src(manifest)?,
+            format,
+            open_dst(&args.dst)?,
+        )?;
+        return Ok(());
+    }
+
+    let srcs = args
+        .srcs
+        .iter()
+        .map(|path| open_src(path))
+        .collect::<Result<Vec<_>>>()?;
+    merge::merge(srcs, format, args.on_conflict.into(), open_dst(&args.dst)?)?;
+    Ok(())
+}
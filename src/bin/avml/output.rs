@@ -0,0 +1,232 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! The final report of a run, as text or as a JSON object for
+//! orchestration tools.
+
+use crate::json::{or_null, ranges, string};
+use avml::{Error, Format, Result, Summary};
+use clap::ValueEnum;
+use core::{error::Error as StdError, time::Duration};
+use std::process::ExitCode;
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum OutputFormat {
+    /// print errors as text, and nothing on success
+    #[default]
+    Text,
+    /// print a JSON object describing the result as the last line on stderr
+    Json,
+}
+
+/// Report the result of `command` in `format`, along with the command's
+/// own JSON `report`, if it has one, returning the exit code for the kind
+/// of failure, if any.
+pub fn finish(
+    format: OutputFormat,
+    command: &str,
+    elapsed: Duration,
+    result: &Result<Option<Summary>>,
+    report: Option<&str>,
+) -> ExitCode {
+    match format {
+        OutputFormat::Text => match *result {
+            Ok(Some(ref summary)) => warn(summary),
+            Ok(None) => {}
+            Err(ref e) => eprintln!("Error: {e:?}"),
+        },
+        OutputFormat::Json => eprintln!("{}", to_json(command, elapsed, result, report)),
+    }
+    exit_code(result)
+}
+
+/// The exit code for the kind of failure of `result`, if any.
+fn exit_code(result: &Result<Option<Summary>>) -> ExitCode {
+    match *result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(ref e) => ExitCode::from(e.failure_kind().exit_code()),
+    }
+}
+
+fn warn(summary: &Summary) {
+    if summary.zero_filled {
+        eprintln!(
+            "Warning: every page read above the first MiB was zero; {} may hide memory, as /dev/mem does with CONFIG_STRICT_DEVMEM",
+            summary.source_name()
+        );
+    }
+    if !summary.unreadable.is_empty() {
+        let bytes = summary
+            .unreadable
+            .iter()
+            .map(|range| range.end.saturating_sub(range.start))
+            .fold(0_u64, u64::saturating_add);
+        eprintln!(
+            "Warning: unable to read {bytes} bytes of memory in {} ranges: {}",
+            summary.unreadable.len(),
+            summary
+                .unreadable
+                .iter()
+                .map(|range| format!("{:#x}..{:#x}", range.start, range.end))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+fn to_json(
+    command: &str,
+    elapsed: Duration,
+    result: &Result<Option<Summary>>,
+    report: Option<&str>,
+) -> String {
+    let (status, key, value) = match *result {
+        Ok(ref summary) => (
+            "ok",
+            "snapshot",
+            or_null(summary.as_ref().map(summary_json)),
+        ),
+        Err(ref e) => ("error", "error", error_json(e)),
+    };
+    format!(
+        "{{\"status\":\"{status}\",\"command\":{},\"elapsed\":{:.3},\"{key}\":{value}{}}}",
+        string(command),
+        elapsed.as_secs_f64(),
+        report.map_or_else(String::new, |report| format!(",\"report\":{report}")),
+    )
+}
+
+fn summary_json(summary: &Summary) -> String {
+    let volumes = summary
+        .volumes
+        .iter()
+        .map(|volume| {
+            let (ref path, ref sha256) = *volume;
+            format!(
+                "{{\"path\":{},\"sha256\":{}}}",
+                string(&path.to_string_lossy()),
+                string(sha256)
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"source\":{},\"format\":\"{}\",\"bytes_read\":{},\"bytes_written\":{},\"ranges\":{},\"elapsed\":{:.3},\"sha256\":{},\"volumes\":[{}],\"zero_filled\":{},\"unreadable\":{}}}",
+        string(&summary.source_name()),
+        format_name(summary.format),
+        summary.bytes_read,
+        summary.bytes_written,
+        ranges(&summary.ranges),
+        summary.elapsed.as_secs_f64(),
+        or_null(summary.sha256.as_deref().map(string)),
+        volumes.join(","),
+        summary.zero_filled,
+        ranges(&summary.unreadable),
+    )
+}
+
+fn error_json(e: &Error) -> String {
+    let sources = e
+        .source_failures()
+        .into_iter()
+        .map(|(source, failure)| {
+            format!(
+                "{{\"source\":{},\"code\":\"{}\",\"message\":{},\"causes\":{}}}",
+                string(&source.to_string()),
+                failure.code(),
+                string(&failure.to_string()),
+                causes(failure),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{{\"code\":\"{}\",\"exit_code\":{},\"message\":{},\"causes\":{},\"sources\":[{}]}}",
+        e.code(),
+        e.failure_kind().exit_code(),
+        string(&e.to_string()),
+        causes(e),
+        sources.join(","),
+    )
+}
+
+fn format_name(format: Format) -> &'static str {
+    match format {
+        Format::Lime => "lime",
+        Format::AvmlCompressed => "lime_compressed",
+    }
+}
+
+// the messages of the chain of errors that caused `e`, as a JSON array
+fn causes(e: &dyn StdError) -> String {
+    let mut messages = Vec::new();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        messages.push(string(&inner.to_string()));
+        source = inner.source();
+    }
+    format!("[{}]", messages.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_json;
+    use avml::{Error, Format, Source, Summary};
+    use core::time::Duration;
+    use std::path::PathBuf;
+
+    #[test]
+    fn reports_a_snapshot() {
+        let summary = Summary {
+            source: Some(Source::ProcKcore),
+            memory_source: None,
+            format: Format::AvmlCompressed,
+            bytes_read: 0x3000,
+            bytes_written: 0x1020,
+            ranges: vec![0x0..0x2000, 0x8000..0x9000],
+            elapsed: Duration::from_millis(1500),
+            sha256: None,
+            volumes: vec![(PathBuf::from("out.lime.001"), "ab".to_string())],
+            zero_filled: false,
+            unreadable: vec![0x1000..0x2000, 0x4000..0x5000],
+        };
+        assert_eq!(
+            to_json("acquire", Duration::from_secs(2), &Ok(Some(summary)), None),
+            concat!(
+                "{\"status\":\"ok\",\"command\":\"acquire\",\"elapsed\":2.000,\"snapshot\":",
+                "{\"source\":\"/proc/kcore\",\"format\":\"lime_compressed\",\"bytes_read\":12288,",
+                "\"bytes_written\":4128,\"ranges\":[[0,8192],[32768,36864]],\"elapsed\":1.500,",
+                "\"sha256\":null,\"volumes\":[{\"path\":\"out.lime.001\",\"sha256\":\"ab\"}],\"zero_filled\":false,\"unreadable\":[[4096,8192],[16384,20480]]}}",
+            )
+        );
+        assert_eq!(
+            to_json("convert", Duration::ZERO, &Ok(None), None),
+            "{\"status\":\"ok\",\"command\":\"convert\",\"elapsed\":0.000,\"snapshot\":null}"
+        );
+    }
+
+    #[test]
+    fn includes_a_command_report() {
+        assert_eq!(
+            to_json("probe", Duration::ZERO, &Ok(None), Some("{\"sources\":[]}")),
+            concat!(
+                "{\"status\":\"ok\",\"command\":\"probe\",\"elapsed\":0.000,",
+                "\"snapshot\":null,\"report\":{\"sources\":[]}}",
+            )
+        );
+    }
+
+    #[test]
+    fn reports_an_error_with_its_causes() {
+        let error = Error::Io {
+            context: "unable to open source file",
+            source: std::io::Error::other("no such file"),
+        };
+        assert_eq!(
+            to_json("convert", Duration::ZERO, &Err(error), None),
+            concat!(
+                "{\"status\":\"error\",\"command\":\"convert\",\"elapsed\":0.000,\"error\":",
+                "{\"code\":\"io\",\"exit_code\":1,\"message\":\"io error: unable to open source file\",",
+                "\"causes\":[\"no such file\"],\"sources\":[]}}",
+            )
+        );
+    }
+}
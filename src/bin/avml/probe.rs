@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use crate::{
+    json::{or_null, ranges, string},
+    output::OutputFormat,
+};
+use avml::{
+    Result,
+    probe::{self, Lockdown, Report, Status},
+};
+use clap::Parser;
+
+#[derive(Parser)]
+pub struct Args {}
+
+/// Report on each source, failing with the `NoSource` kind if none is
+/// usable. As JSON, the report is set in `json`, for the final report.
+pub fn run(_: &Args, format: OutputFormat, json: &mut Option<String>) -> Result<()> {
+    let report = probe::probe();
+    match format {
+        OutputFormat::Text => print!("{}", to_text(&report)),
+        OutputFormat::Json => *json = Some(to_json(&report)),
+    }
+    report.require_source()?;
+    Ok(())
+}
+
+// what to do next, given what the probe found
+fn recommendation(report: &Report) -> String {
+    if let Some(source) = report.recommended() {
+        return format!("acquire will read memory from {source}");
+    }
+    if report.lockdown == Some(Lockdown::Confidentiality) {
+        return "kernel lockdown is in confidentiality mode, which blocks reading memory from every source".to_string();
+    }
+    if report
+        .capabilities
+        .is_some_and(|caps| !caps.sys_admin() || !caps.sys_rawio())
+    {
+        return "run as root, or with CAP_SYS_ADMIN and CAP_SYS_RAWIO".to_string();
+    }
+    "no source is usable; load the crash driver to provide /dev/crash".to_string()
+}
+
+fn to_text(report: &Report) -> String {
+    let mut lines = Vec::new();
+    for entry in &report.sources {
+        let (ref source, ref status) = *entry;
+        lines.push(format!("{:<12} {status}", source.to_string()));
+    }
+    lines.push(format!(
+        "kernel lockdown: {}",
+        report
+            .lockdown
+            .map_or_else(|| "unknown".to_string(), |lockdown| lockdown.to_string())
+    ));
+    if let Some(caps) = report.capabilities {
+        lines.push(format!(
+            "CAP_SYS_ADMIN: {}, CAP_SYS_RAWIO: {}",
+            yes_no(caps.sys_admin()),
+            yes_no(caps.sys_rawio())
+        ));
+    }
+    match report.memory {
+        Ok(ref ranges) => {
+            let total = ranges
+                .iter()
+                .map(|range| range.end.saturating_sub(range.start))
+                .fold(0_u64, u64::saturating_add);
+            lines.push(format!(
+                "memory: {} bytes of System RAM in {} ranges",
+                total,
+                ranges.len()
+            ));
+        }
+        Err(ref e) => lines.push(format!("memory: {e}")),
+    }
+    if let Some(size) = report.estimated_size {
+        lines.push(format!("estimated snapshot size: at most {size} bytes"));
+    }
+    lines.push(format!("recommendation: {}", recommendation(report)));
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn to_json(report: &Report) -> String {
+    let sources = report
+        .sources
+        .iter()
+        .map(|entry| {
+            let (ref source, ref status) = *entry;
+            let (name, reason) = match *status {
+                Status::Usable => ("usable", None),
+                Status::Missing => ("missing", None),
+                Status::Unopenable(ref reason) => ("unopenable", Some(reason)),
+                Status::LockedDown => ("locked_down", None),
+                Status::Restricted(ref reason) => ("restricted", Some(reason)),
+            };
+            format!(
+                "{{\"source\":{},\"status\":\"{name}\",\"reason\":{}}}",
+                string(&source.to_string()),
+                or_null(reason.map(|reason| string(reason))),
+            )
+        })
+        .collect::<Vec<_>>();
+    let memory = match report.memory {
+        Ok(ref memory) => format!("{{\"ranges\":{}}}", ranges(memory)),
+        Err(ref e) => format!("{{\"error\":{}}}", string(&e.to_string())),
+    };
+    format!(
+        "{{\"sources\":[{}],\"lockdown\":{},\"capabilities\":{},\"memory\":{},\"estimated_size\":{},\"recommended\":{},\"recommendation\":{}}}",
+        sources.join(","),
+        or_null(report.lockdown.map(|lockdown| format!("\"{lockdown}\""))),
+        or_null(report.capabilities.map(|caps| format!(
+            "{{\"sys_admin\":{},\"sys_rawio\":{}}}",
+            caps.sys_admin(),
+            caps.sys_rawio()
+        ))),
+        memory,
+        or_null(report.estimated_size.map(|size| size.to_string())),
+        or_null(
+            report
+                .recommended()
+                .map(|source| string(&source.to_string()))
+        ),
+        string(&recommendation(report)),
+    )
+}
+
+const fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_json, to_text};
+    use avml::{
+        Source,
+        probe::{Capabilities, Lockdown, Report, Status},
+    };
+
+    fn report() -> Report {
+        Report {
+            sources: vec![
+                (Source::DevCrash, Status::Missing),
+                (Source::ProcKcore, Status::LockedDown),
+                (
+                    Source::DevMem,
+                    Status::Restricted("Operation not permitted".to_string()),
+                ),
+            ],
+            lockdown: Some(Lockdown::Integrity),
+            capabilities: Some(Capabilities {
+                effective: 0x0000_003f_ffff_ffff,
+            }),
+            memory: Ok(vec![0x1000..0x9_f000, 0x10_0000..0x4000_0000]),
+            estimated_size: Some(1_073_545_216),
+        }
+    }
+
+    #[test]
+    fn reports_as_text() {
+        assert_eq!(
+            to_text(&report()),
+            concat!(
+                "/dev/crash   missing\n",
+                "/proc/kcore  locked down\n",
+                "/dev/mem     unable to read above the first MiB: Operation not permitted\n",
+                "kernel lockdown: integrity\n",
+                "CAP_SYS_ADMIN: yes, CAP_SYS_RAWIO: yes\n",
+                "memory: 1073340416 bytes of System RAM in 2 ranges\n",
+                "estimated snapshot size: at most 1073545216 bytes\n",
+                "recommendation: no source is usable; load the crash driver to provide /dev/crash\n",
+            )
+        );
+    }
+
+    #[test]
+    fn reports_as_json() {
+        let report = Report {
+            sources: vec![(Source::ProcKcore, Status::Usable)],
+            lockdown: None,
+            capabilities: None,
+            ..report()
+        };
+        assert_eq!(
+            to_json(&report),
+            concat!(
+                "{\"sources\":[{\"source\":\"/proc/kcore\",\"status\":\"usable\",\"reason\":null}],",
+                "\"lockdown\":null,\"capabilities\":null,",
+                "\"memory\":{\"ranges\":[[4096,651264],[1048576,1073741824]]},",
+                "\"estimated_size\":1073545216,\"recommended\":\"/proc/kcore\",",
+                "\"recommendation\":\"acquire will read memory from /proc/kcore\"}",
+            )
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{
+    Result,
+    image::OnReadError,
+    process::{Filter, ProcessMemory},
+};
+use clap::Parser;
+
+/// Options for acquiring a single process rather than physical memory.
+#[derive(Parser)]
+pub struct ProcessArgs {
+    /// acquire the memory of this process, as records keyed by virtual
+    /// address, rather than physical memory. The mappings acquired are
+    /// listed in FILENAME.maps, in the format of /proc/<pid>/maps
+    #[arg(long, conflicts_with_all = ["source", "guest_memory"])]
+    pid: Option<u32>,
+
+    /// leave out mappings of files, such as executables and libraries
+    #[arg(long, requires = "pid")]
+    skip_file_backed: bool,
+
+    /// leave out mappings shared with other processes
+    #[arg(long, requires = "pid")]
+    skip_shared: bool,
+}
+
+impl ProcessArgs {
+    /// The process to acquire, if any.
+    pub fn open(&self) -> Result<Option<ProcessMemory>> {
+        let Some(pid) = self.pid else {
+            return Ok(None);
+        };
+        let filter = Filter {
+            skip_file_backed: self.skip_file_backed,
+            skip_shared: self.skip_shared,
+        };
+        Ok(Some(ProcessMemory::open(pid, filter)?))
+    }
+
+    /// What to do with memory that cannot be read, unless told otherwise.
+    /// A mapping unmapped while a process is acquired fails to read with
+    /// `EIO` or `EFAULT`, so a process is acquired around it.
+    pub const fn default_on_read_error(&self) -> OnReadError {
+        if self.pid.is_some() {
+            OnReadError::Skip
+        } else {
+            OnReadError::Abort
+        }
+    }
+}
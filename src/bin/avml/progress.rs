@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use crate::json::{or_null, range};
+#[cfg(feature = "status")]
+use avml::progress::Bar;
+use avml::progress::{Progress, Reporter};
+use clap::{Parser, ValueEnum};
+use std::{
+    io::{Stderr, Write, stderr},
+    sync::{Arc, Mutex},
+};
+
+/// Options for reporting progress on stderr.
+#[derive(Parser)]
+pub struct ProgressArgs {
+    /// report progress on stderr
+    #[arg(long, value_enum)]
+    progress: Option<ProgressFormat>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ProgressFormat {
+    /// draw a progress bar, if stderr is a terminal
+    #[cfg(feature = "status")]
+    Bar,
+    /// write a line of JSON per record or block, for orchestration tools
+    Json,
+}
+
+impl ProgressArgs {
+    /// The reporter requested, if any.
+    pub fn reporter(&self) -> Option<Arc<dyn Reporter>> {
+        let reporter: Arc<dyn Reporter> = match self.progress? {
+            #[cfg(feature = "status")]
+            ProgressFormat::Bar => Arc::new(Bar::new()),
+            ProgressFormat::Json => Arc::new(JsonLines::stderr()),
+        };
+        Some(reporter)
+    }
+}
+
+/// Writes each report as a line of JSON, for orchestration tools.
+///
+/// Each line is an object with the fields of [`Progress`], with `range` as
+/// a two element array, durations in seconds, and the derived `eta` and
+/// `compression_ratio`. Fields without a value are `null`.
+struct JsonLines<W: Write + Send> {
+    dst: Mutex<W>,
+}
+
+impl JsonLines<Stderr> {
+    /// Report to stderr, leaving stdout free for a snapshot.
+    fn stderr() -> Self {
+        Self::new(stderr())
+    }
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    const fn new(dst: W) -> Self {
+        Self {
+            dst: Mutex::new(dst),
+        }
+    }
+}
+
+impl<W: Write + Send> Reporter for JsonLines<W> {
+    fn report(&self, progress: &Progress) {
+        let line = to_json(progress);
+        if let Ok(mut dst) = self.dst.lock() {
+            let _ = writeln!(dst, "{line}").and_then(|()| dst.flush());
+        }
+    }
+}
+
+fn to_json(progress: &Progress) -> String {
+    format!(
+        "{{\"operation\":\"{}\",\"bytes_read\":{},\"bytes_written\":{},\"total\":{},\"range\":{},\"elapsed\":{:.3},\"eta\":{},\"compression_ratio\":{},\"done\":{}}}",
+        progress.operation.name(),
+        progress.bytes_read,
+        progress.bytes_written,
+        or_null(progress.total.map(|total| total.to_string())),
+        or_null(progress.range.as_ref().map(range)),
+        progress.elapsed.as_secs_f64(),
+        or_null(
+            progress
+                .eta()
+                .map(|eta| format!("{:.3}", eta.as_secs_f64()))
+        ),
+        or_null(
+            progress
+                .compression_ratio()
+                .map(|ratio| format!("{ratio:.3}"))
+        ),
+        progress.done,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonLines;
+    use avml::progress::{Operation, Progress, Reporter as _};
+    use core::time::Duration;
+
+    #[test]
+    fn writes_a_json_line_per_report() -> Result<(), std::string::FromUtf8Error> {
+        let progress = Progress {
+            operation: Operation::Acquire,
+            bytes_read: 100,
+            bytes_written: 25,
+            total: Some(400),
+            range: Some(0x1000..0x2000),
+            elapsed: Duration::from_secs(10),
+            done: false,
+        };
+        let json = JsonLines::new(Vec::new());
+        json.report(&progress);
+        json.report(&Progress {
+            bytes_read: 0,
+            bytes_written: 0,
+            total: None,
+            range: None,
+            ..progress
+        });
+        let output = String::from_utf8(
+            json.dst
+                .into_inner()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )?;
+        assert_eq!(
+            output,
+            concat!(
+                "{\"operation\":\"acquire\",\"bytes_read\":100,\"bytes_written\":25,",
+                "\"total\":400,\"range\":[4096,8192],\"elapsed\":10.000,\"eta\":30.000,",
+                "\"compression_ratio\":4.000,\"done\":false}\n",
+                "{\"operation\":\"acquire\",\"bytes_read\":0,\"bytes_written\":0,",
+                "\"total\":null,\"range\":null,\"elapsed\":10.000,\"eta\":null,",
+                "\"compression_ratio\":null,\"done\":false}\n",
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::Snapshot;
+use clap::Parser;
+use core::num::NonZeroUsize;
+
+/// Options for how memory is read from the source.
+#[derive(Parser, Clone, Copy)]
+pub struct ReadArgs {
+    /// number of threads to read memory with at once
+    #[arg(long, default_value = "1")]
+    readers: NonZeroUsize,
+
+    /// size of each read from /dev/crash, /dev/mem, or /proc/kcore in KiB,
+    /// detected if not given
+    #[arg(long)]
+    read_size: Option<NonZeroUsize>,
+}
+
+impl ReadArgs {
+    /// Apply the reader count and read size to `snapshot`.
+    pub fn configure(self, snapshot: Snapshot<'_>) -> Snapshot<'_> {
+        snapshot.readers(self.readers).read_size(
+            self.read_size
+                .and_then(|kib| NonZeroUsize::new(kib.get().saturating_mul(1024))),
+        )
+    }
+}
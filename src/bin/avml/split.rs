@@ -0,0 +1,48 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{Result, image, split};
+use clap::Parser;
+use core::num::NonZeroU64;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+const ONE_MIB_NZ: NonZeroU64 = NonZeroU64::new(1024 * 1024).expect("ONE_MIB must be non-zero");
+
+#[derive(Parser)]
+pub struct Args {
+    /// specify the maximum size of each piece in MiB; must be greater than
+    /// 0. A single record larger than this gets a piece to itself.
+    #[arg(long)]
+    max_size: NonZeroU64,
+
+    /// name of the snapshot file to split, in either `LiME` or AVML
+    /// compressed format
+    src: PathBuf,
+
+    /// prefix of the pieces to write; pieces are named `DST.001`,
+    /// `DST.002`, and so on
+    dst: PathBuf,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let src = File::open(&args.src).map_err(|source| image::Error::Io {
+        context: "unable to open source file",
+        source,
+    })?;
+    let max_size = args.max_size.saturating_mul(ONE_MIB_NZ);
+
+    let pieces = split::split(BufReader::new(src), max_size, |index| {
+        let path = split::volume_path(&args.dst, index.saturating_add(1));
+        Ok(BufWriter::new(image::open_dst(&path)?))
+    })?;
+    // pieces left by an earlier split into more pieces
+    split::remove_volumes_after(&args.dst, pieces).map_err(|source| image::Error::Io {
+        context: "unable to remove stale piece",
+        source,
+    })?;
+    Ok(())
+}
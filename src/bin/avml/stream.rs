@@ -1,21 +1,30 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use avml::{BLOB_MAX_BLOCKS, BlobError, BlockBlobStream, Format, Result, Snapshot, Source, iomem};
-use azure_storage_blob::BlobClient;
+use crate::{
+    acquire::sidecar_url, artifacts::ArtifactsArgs, credential::CredentialArgs,
+    progress::ProgressArgs, reads::ReadArgs, swap, throttle::ThrottleArgs,
+};
+use avml::{
+    BLOB_MAX_BLOCKS, BlobError, BlobUploader, BlockBlobStream, Format, Result, Snapshot, Source,
+    Summary, image::OnReadError, iomem, progress::Reporter, source::MemorySource as _,
+    swap::SwapMemory,
+};
+use azure_core::credentials::TokenCredential;
 use clap::{Parser, Subcommand};
 use core::{
     num::{NonZeroU64, NonZeroUsize},
     ops::Range,
+    str::FromStr,
 };
-use std::path::PathBuf;
-use tokio_util::io::SyncIoBridge;
+use std::{path::Path, sync::Arc};
+use tokio::io::AsyncWriteExt as _;
 use url::Url;
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Stream to Azure Block Blob Storage via `stage_block` + `commit_block_list`.
-    Blob(BlobArgs),
+    Blob(Box<BlobArgs>),
 
     /// Stream to a remote TCP listener (e.g. `nc -l PORT > snapshot.lime`).
     ///
@@ -30,15 +39,29 @@ pub struct BlobArgs {
     #[arg(long)]
     compress: bool,
 
-    /// specify input source. If unset, the source is probed once at
-    /// start (kcore, then /dev/crash, then /dev/mem); the choice cannot
-    /// be changed once any bytes have been written.
-    #[arg(long, value_enum)]
+    /// specify input source: /dev/crash, /dev/mem, /proc/kcore, or
+    /// path:<file> for a raw memory file. If unset, the source is probed
+    /// once at start (kcore, then /dev/crash, then /dev/mem); the choice
+    /// cannot be changed once any bytes have been written.
+    #[arg(long, value_parser = <Source as FromStr>::from_str)]
     source: Option<Source>,
 
-    /// SAS URL identifying the destination Block Blob.
+    /// what to do with memory that cannot be read
+    #[arg(long, value_enum, default_value_t)]
+    on_read_error: OnReadError,
+
+    /// SAS URL identifying the destination Block Blob, or its plain URL
+    /// with --credential.
     sas_url: Url,
 
+    /// after streaming memory, stream the active swap areas listed in
+    /// /proc/swaps, including swap files and zram, as a snapshot in the
+    /// same format to the Block Blob at this SAS URL. The n-th area starts
+    /// at address n << 40, and the blob named after it with `.swaps`
+    /// appended lists the address and path of each
+    #[arg(long)]
+    swap_sas_url: Option<Url>,
+
     /// minimum block size in MiB. The actual block size may be larger
     /// if needed to keep the total block count below Azure's 50,000
     /// limit.
@@ -48,6 +71,41 @@ pub struct BlobArgs {
     /// maximum number of in-flight `stage_block` calls.
     #[arg(long)]
     sas_block_concurrency: Option<NonZeroUsize>,
+
+    /// tune the number of in-flight `stage_block` calls to the upload
+    /// throughput measured, starting from --sas-block-concurrency and
+    /// keeping buffered blocks within --max-buffer-memory
+    #[arg(long, requires = "max_buffer_memory")]
+    sas_adaptive: bool,
+
+    /// the most block data, in MiB, to buffer while streaming, counting
+    /// the block being filled, blocks queued for upload and blocks being
+    /// staged. Concurrency is lowered to fit; fails if three blocks of the
+    /// block size cannot fit
+    #[arg(long)]
+    max_buffer_memory: Option<NonZeroU64>,
+
+    /// before streaming memory, archive live-system artifacts such as
+    /// /proc/modules, /proc/kallsyms, /proc/net/* and the maps of each
+    /// process, and upload the snappy-compressed tar archive to this SAS
+    /// URL
+    #[arg(long, group = "artifacts_destination")]
+    artifacts_sas_url: Option<Url>,
+
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
+    #[command(flatten)]
+    credential: CredentialArgs,
+
+    #[command(flatten)]
+    reads: ReadArgs,
+
+    #[command(flatten)]
+    throttle: ThrottleArgs,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
 }
 
 #[derive(Parser)]
@@ -56,33 +114,82 @@ pub struct TcpArgs {
     #[arg(long)]
     compress: bool,
 
-    /// specify input source. If unset, the source is probed once at
-    /// start (kcore, then /dev/crash, then /dev/mem); the choice cannot
-    /// be changed once any bytes have been written.
-    #[arg(long, value_enum)]
+    /// specify input source: /dev/crash, /dev/mem, /proc/kcore, or
+    /// path:<file> for a raw memory file. If unset, the source is probed
+    /// once at start (kcore, then /dev/crash, then /dev/mem); the choice
+    /// cannot be changed once any bytes have been written.
+    #[arg(long, value_parser = <Source as FromStr>::from_str)]
     source: Option<Source>,
 
+    /// what to do with memory that cannot be read
+    #[arg(long, value_enum, default_value_t)]
+    on_read_error: OnReadError,
+
+    #[command(flatten)]
+    reads: ReadArgs,
+
+    #[command(flatten)]
+    throttle: ThrottleArgs,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
+
+    /// before streaming memory, archive live-system artifacts such as
+    /// /proc/modules, /proc/kallsyms, /proc/net/* and the maps of each
+    /// process, and send the snappy-compressed tar archive to this TCP
+    /// listener as host:port
+    #[arg(long, group = "artifacts_destination")]
+    artifacts_addr: Option<String>,
+
+    /// after streaming memory, stream the active swap areas listed in
+    /// /proc/swaps, including swap files and zram, as a snapshot in the
+    /// same format to this TCP listener as host:port. The n-th area starts
+    /// at address n << 40
+    #[arg(long)]
+    swap_addr: Option<String>,
+
+    /// after streaming the swap areas, send the address and path of each to
+    /// this TCP listener as host:port
+    #[arg(long, requires = "swap_addr")]
+    swap_areas_addr: Option<String>,
+
+    #[command(flatten)]
+    artifacts: ArtifactsArgs,
+
     /// destination TCP listener as host:port. Hostnames are resolved.
     addr: String,
 }
 
-pub async fn run(cmd: Commands) -> Result<()> {
+pub async fn run(cmd: Commands) -> Result<Summary> {
     match cmd {
-        Commands::Blob(args) => stream_blob(args).await,
+        Commands::Blob(args) => stream_blob(*args).await,
         Commands::Tcp(args) => stream_tcp(args).await,
     }
 }
 
-async fn stream_blob(args: BlobArgs) -> Result<()> {
+async fn stream_blob(args: BlobArgs) -> Result<Summary> {
+    args.throttle.apply()?;
+    let credential = args.credential.credential()?;
+    if let Some(ref url) = args.artifacts_sas_url {
+        let archive = args.artifacts.collect()?;
+        BlobUploader::new(url, credential.clone())?
+            .upload_bytes(archive)
+            .await?;
+    }
+    let max_rate = args.throttle.max_rate();
+    let reporter = args.progress.reporter();
+    let on_read_error = args.on_read_error;
+    let reads = args.reads;
     let ranges = iomem::parse()?;
-    let block_size = derive_block_size(&ranges, args.sas_block_size)?;
-    let concurrency = args
-        .sas_block_concurrency
-        .unwrap_or(avml::DEFAULT_CONCURRENCY);
-
-    let block_client = BlobClient::new(args.sas_url, None, None)
-        .map_err(BlobError::from)?
-        .block_blob_client();
+    let blocks = Blocks {
+        min_size: args.sas_block_size,
+        concurrency: args
+            .sas_block_concurrency
+            .unwrap_or(avml::DEFAULT_CONCURRENCY),
+        adaptive: args.sas_adaptive,
+        max_buffer_memory: args.max_buffer_memory,
+        max_rate,
+    };
     let format = Format::from(args.compress);
 
     let source = match args.source {
@@ -90,37 +197,116 @@ async fn stream_blob(args: BlobArgs) -> Result<()> {
         None => Snapshot::probe_single_source().map_err(avml::Error::from)?,
     };
 
-    let stream = BlockBlobStream::new(block_client, block_size, concurrency);
-
-    let (stream, result) = tokio::task::spawn_blocking(
-        move || -> (BlockBlobStream, core::result::Result<(), avml::Error>) {
-            let mut stream = stream;
-            // Snapshot::create_to_writer never inspects `destination`;
-            // any in-scope path satisfies the &Path borrow.
-            let dummy = PathBuf::from("/dev/null");
-            let snapshot = Snapshot::new(&dummy, ranges)
-                .source(Some(source))
-                .format(format);
-            let r: core::result::Result<(), avml::Error> = snapshot
-                .create_to_writer(stream.writer())
-                .map_err(avml::Error::from)
-                .and_then(|()| {
-                    stream.finish_writes().map_err(|io_err| avml::Error::Io {
-                        context: "unable to finish blob stream",
-                        source: io_err,
-                    })
-                });
-            (stream, r)
-        },
+    let snapshot = reads.configure(
+        streamed(ranges.clone())
+            .source(Some(source))
+            .format(format)
+            .max_rate(max_rate)
+            .progress(reporter.clone())
+            .on_read_error(on_read_error),
+    );
+    let summary = stream_to_blob(
+        &args.sas_url,
+        credential.clone(),
+        snapshot,
+        &ranges,
+        blocks,
+        reporter,
     )
-    .await
-    .map_err(|e| avml::Error::Io {
-        context: "spawn_blocking join failed",
-        source: std::io::Error::other(e.to_string()),
-    })?;
+    .await?;
 
+    if let Some(url) = args.swap_sas_url
+        && let Some(swap) = swap::open()?
+    {
+        let areas = swap_ranges(&swap)?;
+        let listing = swap::list_areas(&swap);
+        let swap_snapshot = reads.configure(
+            streamed(areas.clone())
+                .memory_source(Some(Arc::new(swap)))
+                .format(format)
+                .max_rate(max_rate)
+                .on_read_error(on_read_error),
+        );
+        stream_to_blob(
+            &url,
+            credential.clone(),
+            swap_snapshot,
+            &areas,
+            blocks,
+            None,
+        )
+        .await?;
+        BlobUploader::new(&sidecar_url(&url, ".swaps"), credential)?
+            .upload_bytes(listing.into_bytes())
+            .await?;
+    }
+    Ok(summary)
+}
+
+/// How a snapshot is split into blocks and staged.
+#[derive(Clone, Copy)]
+struct Blocks {
+    /// The smallest block size to use, in MiB.
+    min_size: Option<NonZeroU64>,
+    /// `stage_block` calls in flight, or where tuning starts from.
+    concurrency: NonZeroUsize,
+    /// Whether to tune concurrency to the throughput measured.
+    adaptive: bool,
+    /// The most block data to buffer, in MiB.
+    max_buffer_memory: Option<NonZeroU64>,
+    /// The most MiB per second to stage blocks at, on average.
+    max_rate: Option<NonZeroU64>,
+}
+
+// A snapshot of `ranges` to stream with Snapshot::create_async, which never
+// inspects the destination.
+fn streamed(ranges: Vec<Range<u64>>) -> Snapshot<'static> {
+    Snapshot::new(Path::new("/dev/null"), ranges)
+}
+
+// Stream `snapshot`, of `ranges`, to the block blob at `url`.
+async fn stream_to_blob(
+    url: &Url,
+    credential: Option<Arc<dyn TokenCredential>>,
+    snapshot: Snapshot<'_>,
+    ranges: &[Range<u64>],
+    blocks: Blocks,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<Summary> {
+    let block_size = derive_block_size(ranges, blocks.min_size)?;
+    let mib = |mib: NonZeroU64| NonZeroU64::new(mib.get().saturating_mul(1024 * 1024));
+    let mut stream = match blocks.max_buffer_memory.and_then(mib) {
+        Some(limit) => BlockBlobStream::with_max_buffer_memory(
+            url,
+            credential,
+            block_size,
+            blocks.concurrency,
+            limit,
+        )?,
+        None => BlockBlobStream::new(url, credential, block_size, blocks.concurrency)?,
+    };
+    if blocks.adaptive {
+        stream = stream.adaptive();
+    }
+    if let Some(rate) = blocks.max_rate.and_then(mib) {
+        stream = stream.max_rate(rate);
+    }
+    if let Some(reporter) = reporter {
+        stream = stream.report_progress(reporter);
+    }
+
+    let result = match stream.async_writer() {
+        Some(writer) => snapshot.create_async(writer).await,
+        None => Err(avml::Error::Io {
+            context: "unable to stream to blob",
+            source: std::io::Error::other("the blob stream writer was already taken"),
+        }),
+    };
     match result {
-        Ok(()) => stream.finalize().await.map_err(avml::Error::from),
+        Ok(summary) => {
+            stream.finalize().await?;
+            Ok(summary)
+        }
         Err(e) => {
             drop(stream.abort().await);
             Err(e)
@@ -128,6 +314,23 @@ async fn stream_blob(args: BlobArgs) -> Result<()> {
     }
 }
 
+fn swap_ranges(swap: &SwapMemory) -> Result<Vec<Range<u64>>> {
+    swap.ranges(&[]).map_err(|source| avml::Error::Io {
+        context: "unable to list swap areas",
+        source,
+    })
+}
+
+// Send `data` to the TCP listener at `addr`, failing with `context`.
+async fn send(addr: &str, data: &[u8], context: &'static str) -> Result<()> {
+    let io_error = |source| avml::Error::Io { context, source };
+    let mut socket = tokio::net::TcpStream::connect(addr)
+        .await
+        .map_err(io_error)?;
+    socket.write_all(data).await.map_err(io_error)?;
+    socket.shutdown().await.map_err(io_error)
+}
+
 fn derive_block_size(
     ranges: &[Range<u64>],
     user_floor_mib: Option<NonZeroU64>,
@@ -171,7 +374,16 @@ fn derive_block_size(
     })
 }
 
-async fn stream_tcp(args: TcpArgs) -> Result<()> {
+async fn stream_tcp(args: TcpArgs) -> Result<Summary> {
+    args.throttle.apply()?;
+    if let Some(ref addr) = args.artifacts_addr {
+        send(
+            addr,
+            &args.artifacts.collect()?,
+            "unable to send artifacts to TCP destination",
+        )
+        .await?;
+    }
     let ranges = iomem::parse()?;
     let format = Format::from(args.compress);
     let source = match args.source {
@@ -185,27 +397,43 @@ async fn stream_tcp(args: TcpArgs) -> Result<()> {
             context: "unable to connect to TCP destination",
             source: io_err,
         })?;
-    let mut bridge = SyncIoBridge::new(socket);
 
-    tokio::task::spawn_blocking(move || -> Result<()> {
-        // Snapshot::create_to_writer never inspects `destination`;
-        // any in-scope path satisfies the &Path borrow.
-        let dummy = PathBuf::from("/dev/null");
-        let snapshot = Snapshot::new(&dummy, ranges)
-            .source(Some(source))
-            .format(format);
-        snapshot
-            .create_to_writer(&mut bridge)
-            .map_err(avml::Error::from)?;
-        bridge.shutdown().map_err(|io_err| avml::Error::Io {
-            context: "unable to finish TCP stream",
-            source: io_err,
-        })?;
-        Ok(())
-    })
-    .await
-    .map_err(|e| avml::Error::Io {
-        context: "spawn_blocking join failed",
-        source: std::io::Error::other(e.to_string()),
-    })?
+    let snapshot = streamed(ranges)
+        .source(Some(source))
+        .format(format)
+        .max_rate(args.throttle.max_rate())
+        .progress(args.progress.reporter())
+        .on_read_error(args.on_read_error);
+    let summary = args.reads.configure(snapshot).create_async(socket).await?;
+
+    if let Some(ref addr) = args.swap_addr
+        && let Some(swap) = swap::open()?
+    {
+        let areas = swap_ranges(&swap)?;
+        let listing = swap::list_areas(&swap);
+        let swap_socket = tokio::net::TcpStream::connect(addr)
+            .await
+            .map_err(|io_err| avml::Error::Io {
+                context: "unable to connect to TCP destination for swap",
+                source: io_err,
+            })?;
+        let swap_snapshot = streamed(areas)
+            .memory_source(Some(Arc::new(swap)))
+            .format(format)
+            .max_rate(args.throttle.max_rate())
+            .on_read_error(args.on_read_error);
+        args.reads
+            .configure(swap_snapshot)
+            .create_async(swap_socket)
+            .await?;
+        if let Some(ref areas_addr) = args.swap_areas_addr {
+            send(
+                areas_addr,
+                listing.as_bytes(),
+                "unable to send swap areas to TCP destination",
+            )
+            .await?;
+        }
+    }
+    Ok(summary)
 }
@@ -0,0 +1,40 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{
+    Result,
+    image::open_dst,
+    swap::{SwapMemory, areas},
+};
+use core::fmt::Write as _;
+use std::{io::Write as _, path::Path};
+
+/// The active swap areas of this host, if there are any.
+pub fn open() -> Result<Option<SwapMemory>> {
+    let areas = areas()?;
+    if areas.is_empty() {
+        eprintln!("Warning: no active swap areas to acquire");
+        return Ok(None);
+    }
+    Ok(Some(SwapMemory::open(areas)?))
+}
+
+/// The address each swap area starts at in a snapshot of `swap`, with the
+/// device or file it was read from, one area per line.
+pub fn list_areas(swap: &SwapMemory) -> String {
+    let mut list = String::new();
+    for (start, area) in swap.areas() {
+        let _ = writeln!(list, "{start:#x} {} {}", area.kind, area.path.display());
+    }
+    list
+}
+
+/// Write the [`list_areas`] of `swap` to `path`.
+pub fn write_areas(swap: &SwapMemory, path: &Path) -> Result<()> {
+    open_dst(path)?
+        .write_all(list_areas(swap).as_bytes())
+        .map_err(|source| avml::Error::Io {
+            context: "unable to write swap areas",
+            source,
+        })
+}
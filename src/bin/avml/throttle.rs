@@ -0,0 +1,64 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use avml::{
+    Result,
+    throttle::{self, IoPriority},
+};
+use clap::{Parser, ValueEnum};
+use core::num::NonZeroU64;
+
+/// Options that keep acquisition from competing with the host's workloads.
+#[derive(Parser)]
+pub struct ThrottleArgs {
+    /// read memory at no more than this many MiB per second on average;
+    /// must be greater than 0. Writes, and uploads to blob storage, are
+    /// bounded by the same rate.
+    #[arg(long)]
+    max_rate: Option<NonZeroU64>,
+
+    /// run at this nice value, from -20 (highest priority) to 19 (lowest)
+    #[arg(long, allow_hyphen_values = true, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    nice: Option<i32>,
+
+    /// run in this disk I/O scheduling class
+    #[arg(long, value_enum)]
+    ionice: Option<CliIoPriority>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum CliIoPriority {
+    /// only use the disk when no other process wants it
+    Idle,
+    /// share the disk at the lowest best-effort priority
+    BestEffort,
+}
+
+impl From<CliIoPriority> for IoPriority {
+    fn from(value: CliIoPriority) -> Self {
+        match value {
+            CliIoPriority::Idle => Self::Idle,
+            CliIoPriority::BestEffort => Self::BestEffort(7),
+        }
+    }
+}
+
+impl ThrottleArgs {
+    /// The cap on the rate memory is read and uploaded at, in MiB per
+    /// second.
+    pub fn max_rate(&self) -> Option<NonZeroU64> {
+        self.max_rate
+    }
+
+    /// Lower the priority of this thread, and of the threads it starts
+    /// afterwards, as requested.
+    pub fn apply(&self) -> Result<()> {
+        if let Some(nice) = self.nice {
+            throttle::set_nice(nice)?;
+        }
+        if let Some(ionice) = self.ionice {
+            throttle::set_io_priority(ionice.into())?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
+use crate::{credential::CredentialArgs, progress::ProgressArgs};
 use avml::{BlobUploader, Result, put};
 use clap::Subcommand;
 use core::num::{NonZeroU64, NonZeroUsize};
@@ -15,13 +16,16 @@ pub enum Commands {
         filename: PathBuf,
         /// url to upload via HTTP PUT
         url: Url,
+        #[command(flatten)]
+        progress: ProgressArgs,
     },
 
     /// Upload a local file to Azure Block Blob Storage.
     Blob {
         /// name of the file to upload on the local system
         filename: PathBuf,
-        /// SAS URL identifying the destination Block Blob
+        /// SAS URL identifying the destination Block Blob, or its plain URL
+        /// with --credential
         url: Url,
         /// specify blob upload concurrency; must be greater than 0
         #[arg(long)]
@@ -29,21 +33,44 @@ pub enum Commands {
         /// specify maximum block size in MiB; must be greater than 0
         #[arg(long)]
         sas_block_size: Option<NonZeroU64>,
+        /// the most block data, in MiB, to buffer across concurrent block
+        /// uploads; concurrency is lowered to fit
+        #[arg(long)]
+        max_buffer_memory: Option<NonZeroU64>,
+        /// upload at no more than this many MiB per second on average;
+        /// must be greater than 0
+        #[arg(long)]
+        max_rate: Option<NonZeroU64>,
+        #[command(flatten)]
+        credential: CredentialArgs,
+        #[command(flatten)]
+        progress: ProgressArgs,
     },
 }
 
 pub async fn run(cmd: Commands) -> Result<()> {
     match cmd {
-        Commands::Put { filename, url } => put(&filename, &url).await?,
+        Commands::Put {
+            filename,
+            url,
+            progress,
+        } => put(&filename, &url, progress.reporter()).await?,
         Commands::Blob {
             filename,
             url,
             sas_block_size,
             sas_block_concurrency,
+            max_buffer_memory,
+            max_rate,
+            credential,
+            progress,
         } => {
-            let uploader = BlobUploader::new(&url)?
+            let uploader = BlobUploader::new(&url, credential.credential()?)?
                 .block_size(sas_block_size)
-                .concurrency(sas_block_concurrency);
+                .concurrency(sas_block_concurrency)
+                .max_buffer_memory(max_buffer_memory)
+                .max_rate(max_rate)
+                .report_progress(progress.reporter());
             uploader.upload_file(&filename).await?;
         }
     }
@@ -0,0 +1,206 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Page-level comparison of two snapshots of the same host.
+//!
+//! Both snapshots are decoded sequentially with [`BlockReader`] and walked
+//! in lockstep by physical address, one page at a time. Records are
+//! expected in ascending address order, as `acquire` writes them.
+//!
+//! `acquire` elides all-zero blocks, so memory that was zeroed between two
+//! captures shows up as present only in the older snapshot.
+
+use crate::image::{BlockWriter, Error, Format, PageCursor, page_end};
+use core::ops::Range;
+use std::io::{Read, Sink, Write};
+
+type Result<T> = core::result::Result<T, Error>;
+
+/// The differences between two snapshots.
+///
+/// Page counts include partial pages at the edges of records.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Diff {
+    /// Ranges present in both snapshots whose contents differ.
+    pub changed: Vec<Range<u64>>,
+    /// Ranges present only in the older snapshot.
+    pub only_in_old: Vec<Range<u64>>,
+    /// Ranges present only in the newer snapshot.
+    pub only_in_new: Vec<Range<u64>>,
+    pub unchanged_pages: u64,
+    pub changed_pages: u64,
+    pub only_in_old_pages: u64,
+    pub only_in_new_pages: u64,
+}
+
+impl Diff {
+    fn record(&mut self, kind: Kind, range: Range<u64>) {
+        let (ranges, pages) = match kind {
+            Kind::Unchanged => {
+                self.unchanged_pages = self.unchanged_pages.saturating_add(1);
+                return;
+            }
+            Kind::Changed => (&mut self.changed, &mut self.changed_pages),
+            Kind::OnlyInOld => (&mut self.only_in_old, &mut self.only_in_old_pages),
+            Kind::OnlyInNew => (&mut self.only_in_new, &mut self.only_in_new_pages),
+        };
+        *pages = pages.saturating_add(1);
+        match ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => ranges.push(range),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Unchanged,
+    Changed,
+    OnlyInOld,
+    OnlyInNew,
+}
+
+/// Compare two snapshots page by page.
+///
+/// # Errors
+/// Returns an error if either snapshot cannot be read or decoded.
+pub fn diff<A: Read, B: Read>(old: A, new: B) -> Result<Diff> {
+    compare::<_, _, Sink>(old, new, None)
+}
+
+/// Compare two snapshots page by page, writing every page that changed or
+/// appeared in the newer snapshot, with its newer contents, to `delta` as a
+/// snapshot in `format`.
+///
+/// Unlike `acquire`, all-zero pages are kept, since a page that became zero
+/// is still a change.
+///
+/// # Errors
+/// Returns an error if either snapshot cannot be read or decoded, or
+/// writing to `delta` fails.
+pub fn diff_with_delta<A: Read, B: Read, W: Write>(
+    old: A,
+    new: B,
+    format: Format,
+    delta: W,
+) -> Result<Diff> {
+    compare(old, new, Some(BlockWriter::new(delta, format)))
+}
+
+fn compare<A: Read, B: Read, W: Write>(
+    old: A,
+    new: B,
+    mut delta: Option<BlockWriter<W>>,
+) -> Result<Diff> {
+    let mut old = PageCursor::new(old);
+    let mut new = PageCursor::new(new);
+    let mut diff = Diff::default();
+
+    loop {
+        let (a, b) = (old.peek()?, new.peek()?);
+        let Some(start) = a.iter().chain(&b).map(|r| r.start).min() else {
+            break;
+        };
+        // stop at the end of the page, the end of either block, or the start
+        // of a block that begins later, whichever comes first
+        let mut end = page_end(start)?;
+        for r in a.iter().chain(&b) {
+            end = end.min(if r.start == start { r.end } else { r.start });
+        }
+
+        let old_data = a.is_some_and(|r| r.start == start).then(|| old.take(end));
+        let new_data = b.is_some_and(|r| r.start == start).then(|| new.take(end));
+        let kind = match (old_data, new_data) {
+            (Some(o), Some(n)) if o == n => Kind::Unchanged,
+            (Some(_), Some(_)) => Kind::Changed,
+            (Some(_), None) => Kind::OnlyInOld,
+            (None, _) => Kind::OnlyInNew,
+        };
+        if let (Kind::Changed | Kind::OnlyInNew, Some(delta)) = (kind, delta.as_mut()) {
+            delta.push(start..end, new_data.unwrap_or_default())?;
+        }
+        diff.record(kind, start..end);
+    }
+
+    if let Some(delta) = delta {
+        delta.finish()?;
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+#[expect(
+    clippy::single_range_in_vec_init,
+    reason = "the expected lists of ranges happen to hold a single range"
+)]
+mod tests {
+    use super::{Diff, diff, diff_with_delta};
+    use crate::image::{BlockData, BlockReader, Error, Format, filled_snapshot};
+
+    #[test]
+    fn reports_changed_and_missing_pages() -> Result<(), Error> {
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            let old = filled_snapshot(format, &[(0x0..0x4000, 1), (0x8000..0xa000, 2)])?;
+            // the second page changes, the last old page disappears, and
+            // pages appear at 0xc000; record boundaries also differ
+            let new = filled_snapshot(
+                format,
+                &[
+                    (0x0..0x1000, 1),
+                    (0x1000..0x2000, 9),
+                    (0x2000..0x4000, 1),
+                    (0x8000..0x9000, 2),
+                    (0xc000..0xe000, 3),
+                ],
+            )?;
+
+            let result = diff(old.as_slice(), new.as_slice())?;
+            assert_eq!(
+                result,
+                Diff {
+                    changed: vec![0x1000..0x2000],
+                    only_in_old: vec![0x9000..0xa000],
+                    only_in_new: vec![0xc000..0xe000],
+                    unchanged_pages: 4,
+                    changed_pages: 1,
+                    only_in_old_pages: 1,
+                    only_in_new_pages: 2,
+                }
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn delta_holds_new_contents_of_changed_pages() -> Result<(), Error> {
+        let old = filled_snapshot(Format::Lime, &[(0x0..0x3000, 1)])?;
+        // a page becoming zero is still a change
+        let new = filled_snapshot(
+            Format::AvmlCompressed,
+            &[(0x0..0x1000, 0), (0x1000..0x2000, 1), (0x2000..0x4000, 7)],
+        )?;
+
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            let mut delta = Vec::new();
+            let result = diff_with_delta(old.as_slice(), new.as_slice(), format, &mut delta)?;
+            assert_eq!(result.changed, vec![0x0..0x1000, 0x2000..0x3000]);
+            assert_eq!(result.only_in_new, vec![0x3000..0x4000]);
+
+            let blocks = BlockReader::new(delta.as_slice()).collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(
+                blocks,
+                vec![
+                    BlockData {
+                        range: 0x0..0x1000,
+                        data: vec![0; 0x1000],
+                    },
+                    BlockData {
+                        range: 0x2000..0x4000,
+                        data: vec![7; 0x2000],
+                    },
+                ]
+            );
+        }
+        Ok(())
+    }
+}
@@ -29,8 +29,22 @@ pub fn check(
     max_disk_usage: Option<NonZeroU64>,
     max_disk_usage_percentage: Option<f64>,
 ) -> Result<()> {
-    let estimate_add = estimate(memory_ranges);
+    check_estimate(
+        image_path,
+        estimate(memory_ranges),
+        max_disk_usage,
+        max_disk_usage_percentage,
+    )
+}
 
+/// Check that writing `estimate_add` more bytes to `image_path` fits in the
+/// configured parameters.
+pub fn check_estimate(
+    image_path: &Path,
+    estimate_add: u64,
+    max_disk_usage: Option<NonZeroU64>,
+    max_disk_usage_percentage: Option<f64>,
+) -> Result<()> {
     if let Some(max_disk_usage) = max_disk_usage {
         check_max_usage(estimate_add, max_disk_usage)?;
     }
@@ -115,7 +129,7 @@ fn f64_to_u64(value: f64) -> Result<u64> {
 }
 
 /// Estimate potential disk usage for a given set of memory ranges
-fn estimate(ranges: &[Range<u64>]) -> u64 {
+pub fn estimate(ranges: &[Range<u64>]) -> u64 {
     let mut total: u64 = 0;
     for range in ranges {
         let chunk_size = range.end.saturating_sub(range.start);
@@ -1,8 +1,82 @@
+use crate::snapshot::{Error as SnapshotError, Source};
 use core::{
     error::Error as StdError,
     fmt::{Debug as FmtDebug, Formatter, Result as FmtResult},
 };
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind};
+
+/// The kinds of failure automation can tell apart by exit code alone.
+///
+/// The codes are stable across releases:
+///
+/// | Code | Kind | Cause |
+/// |------|------|-------|
+/// | 1 | `Other` | any failure not listed below |
+/// | 2 | `Usage` | invalid arguments, or nothing to do |
+/// | 3 | `NoSource` | no memory source is available, or every source failed |
+/// | 4 | `LockedDownKcore` | `/proc/kcore` is locked down by the kernel |
+/// | 5 | `PermissionDenied` | insufficient privileges, such as to read `/proc/iomem` |
+/// | 6 | `DiskUsageExceeded` | the snapshot would exceed the disk usage limits given |
+/// | 7 | `DiskFull` | the destination ran out of space or quota |
+/// | 8 | `InvalidSnapshot` | an input snapshot is malformed or inconsistent |
+/// | 9 | `UploadDenied` | the upload destination refused the credentials (401 or 403) |
+/// | 10 | `UploadFailed` | any other upload failure |
+/// | 11 | `Cancelled` | the caller cancelled the acquisition |
+///
+/// When every source fails, the kind is that of the first source to fail
+/// for a reason other than `Other` or `NoSource`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Other,
+    Usage,
+    NoSource,
+    LockedDownKcore,
+    PermissionDenied,
+    DiskUsageExceeded,
+    DiskFull,
+    InvalidSnapshot,
+    UploadDenied,
+    UploadFailed,
+    Cancelled,
+}
+
+impl FailureKind {
+    /// The process exit code for this kind of failure.
+    #[must_use]
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            Self::Other => 1,
+            Self::Usage => 2,
+            Self::NoSource => 3,
+            Self::LockedDownKcore => 4,
+            Self::PermissionDenied => 5,
+            Self::DiskUsageExceeded => 6,
+            Self::DiskFull => 7,
+            Self::InvalidSnapshot => 8,
+            Self::UploadDenied => 9,
+            Self::UploadFailed => 10,
+            Self::Cancelled => 11,
+        }
+    }
+
+    /// Classify an I/O error by what the OS reported.
+    pub(crate) fn from_io(e: &IoError) -> Self {
+        match e.kind() {
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => Self::DiskFull,
+            ErrorKind::PermissionDenied => Self::PermissionDenied,
+            _ => Self::Other,
+        }
+    }
+
+    /// Classify the HTTP status an upload was refused with.
+    #[cfg(any(feature = "put", feature = "blobstore"))]
+    pub(crate) const fn from_upload_status(status: u16) -> Self {
+        match status {
+            401 | 403 => Self::UploadDenied,
+            _ => Self::UploadFailed,
+        }
+    }
+}
 
 #[derive(thiserror::Error)]
 pub enum Error {
@@ -15,6 +89,21 @@ pub enum Error {
     #[error("unable to parse /proc/iomem")]
     Iomem(#[from] crate::iomem::Error),
 
+    #[error("unable to map guest memory")]
+    Guest(#[from] crate::guest::Error),
+
+    #[cfg(target_family = "unix")]
+    #[error("unable to open process memory")]
+    Process(#[from] crate::process::Error),
+
+    #[cfg(target_family = "unix")]
+    #[error("unable to collect live-system artifacts")]
+    Artifacts(#[from] crate::artifacts::Error),
+
+    #[cfg(target_family = "unix")]
+    #[error("unable to open swap")]
+    Swap(#[from] crate::swap::Error),
+
     #[cfg(feature = "put")]
     #[error("unable to upload file via PUT")]
     Upload(#[from] crate::upload::http::Error),
@@ -23,6 +112,13 @@ pub enum Error {
     #[error("unable to upload file to Azure Storage")]
     Blob(#[from] crate::upload::blobstore::Error),
 
+    #[cfg(feature = "blobstore")]
+    #[error("unable to set up Azure credential")]
+    Credential(#[from] crate::upload::credential::Error),
+
+    #[error("unable to lower priority")]
+    Throttle(#[from] crate::throttle::Error),
+
     #[error("io error: {context}")]
     Io {
         context: &'static str,
@@ -32,6 +128,75 @@ pub enum Error {
 
     #[error("no conversion required")]
     NoConversionRequired,
+
+    #[error("invalid arguments: {0}")]
+    Usage(&'static str),
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages. Wrapped errors report the code of
+    /// the underlying failure.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Image(ref e) => e.code(),
+            Self::Memory(ref e) => e.code(),
+            Self::Iomem(ref e) => e.code(),
+            Self::Guest(ref e) => e.code(),
+            #[cfg(target_family = "unix")]
+            Self::Process(ref e) => e.code(),
+            #[cfg(target_family = "unix")]
+            Self::Artifacts(ref e) => e.code(),
+            #[cfg(target_family = "unix")]
+            Self::Swap(ref e) => e.code(),
+            #[cfg(feature = "put")]
+            Self::Upload(_) => "upload",
+            #[cfg(feature = "blobstore")]
+            Self::Blob(_) => "blob_upload",
+            #[cfg(feature = "blobstore")]
+            Self::Credential(_) => "blob_credential",
+            Self::Throttle(_) => "throttle",
+            Self::Io { .. } => "io",
+            Self::NoConversionRequired => "no_conversion_required",
+            Self::Usage(_) => "usage",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Image(ref e) => e.failure_kind(),
+            Self::Memory(ref e) => e.failure_kind(),
+            Self::Iomem(ref e) => e.failure_kind(),
+            Self::Guest(ref e) => e.failure_kind(),
+            #[cfg(target_family = "unix")]
+            Self::Process(ref e) => e.failure_kind(),
+            #[cfg(target_family = "unix")]
+            Self::Artifacts(ref e) => e.failure_kind(),
+            #[cfg(target_family = "unix")]
+            Self::Swap(ref e) => e.failure_kind(),
+            #[cfg(feature = "put")]
+            Self::Upload(ref e) => e.failure_kind(),
+            #[cfg(feature = "blobstore")]
+            Self::Blob(ref e) => e.failure_kind(),
+            #[cfg(feature = "blobstore")]
+            Self::Credential(ref e) => e.failure_kind(),
+            Self::Throttle(ref e) => e.failure_kind(),
+            Self::Io { ref source, .. } => FailureKind::from_io(source),
+            Self::NoConversionRequired | Self::Usage(_) => FailureKind::Usage,
+        }
+    }
+
+    /// Why each memory source failed, when every source tried failed.
+    #[must_use]
+    pub fn source_failures(&self) -> Vec<(Source, &SnapshotError)> {
+        match *self {
+            Self::Memory(ref e) => e.source_failures(),
+            _ => Vec::new(),
+        }
+    }
 }
 
 pub(crate) fn format_error(e: &impl StdError, f: &mut Formatter) -> FmtResult {
@@ -58,3 +223,311 @@ impl FmtDebug for Error {
         format_error(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, FailureKind};
+    use crate::{
+        guest::Error as GuestError, image::Error as ImageError, iomem::Error as IomemError,
+        snapshot::Error as SnapshotError, snapshot::Source, throttle::Error as ThrottleError,
+    };
+    use std::io::{Error as IoError, ErrorKind};
+
+    fn io(kind: ErrorKind) -> IoError {
+        IoError::new(kind, "simulated")
+    }
+
+    fn memory(e: SnapshotError) -> Error {
+        Error::Memory(e)
+    }
+
+    fn image(e: ImageError) -> Error {
+        Error::Image(e)
+    }
+
+    #[test]
+    fn exit_codes_are_stable() {
+        let table = [
+            (FailureKind::Other, 1),
+            (FailureKind::Usage, 2),
+            (FailureKind::NoSource, 3),
+            (FailureKind::LockedDownKcore, 4),
+            (FailureKind::PermissionDenied, 5),
+            (FailureKind::DiskUsageExceeded, 6),
+            (FailureKind::DiskFull, 7),
+            (FailureKind::InvalidSnapshot, 8),
+            (FailureKind::UploadDenied, 9),
+            (FailureKind::UploadFailed, 10),
+            (FailureKind::Cancelled, 11),
+        ];
+        for (kind, code) in table {
+            assert_eq!(kind.exit_code(), code, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn snapshot_errors_map_to_their_kind() {
+        let cases = [
+            (
+                memory(SnapshotError::LockedDownKcore),
+                FailureKind::LockedDownKcore,
+            ),
+            (
+                memory(SnapshotError::DiskUsageEstimateExceeded {
+                    estimated: 2,
+                    allowed: 1,
+                }),
+                FailureKind::DiskUsageExceeded,
+            ),
+            (
+                memory(SnapshotError::NoSourceAvailable),
+                FailureKind::NoSource,
+            ),
+            (
+                memory(SnapshotError::AllSourcesFailed {
+                    crash: Box::new(SnapshotError::Disk(io(ErrorKind::NotFound))),
+                    kcore: Box::new(SnapshotError::LockedDownKcore),
+                    devmem: Box::new(SnapshotError::Disk(io(ErrorKind::PermissionDenied))),
+                }),
+                FailureKind::LockedDownKcore,
+            ),
+            (
+                memory(SnapshotError::AllSourcesFailed {
+                    crash: Box::new(SnapshotError::Disk(io(ErrorKind::NotFound))),
+                    kcore: Box::new(SnapshotError::NoSourceAvailable),
+                    devmem: Box::new(SnapshotError::Disk(io(ErrorKind::NotFound))),
+                }),
+                FailureKind::NoSource,
+            ),
+            (
+                memory(SnapshotError::UnableToCreateSnapshotFromSource {
+                    src: Source::DevMem,
+                    source: Box::new(SnapshotError::AllSourcesFailed {
+                        crash: Box::new(SnapshotError::UnableToCreateSnapshotFromSource {
+                            src: Source::DevCrash,
+                            source: Box::new(SnapshotError::Disk(io(ErrorKind::NotFound))),
+                        }),
+                        kcore: Box::new(SnapshotError::UnableToCreateSnapshotFromSource {
+                            src: Source::ProcKcore,
+                            source: Box::new(SnapshotError::KcoreParse("no PT_LOAD segments")),
+                        }),
+                        devmem: Box::new(SnapshotError::UnableToCreateSnapshotFromSource {
+                            src: Source::DevMem,
+                            source: Box::new(SnapshotError::Disk(io(ErrorKind::PermissionDenied))),
+                        }),
+                    }),
+                }),
+                FailureKind::PermissionDenied,
+            ),
+            (
+                memory(SnapshotError::UnableToCreateSnapshotFromSource {
+                    src: Source::ProcKcore,
+                    source: Box::new(SnapshotError::LockedDownKcore),
+                }),
+                FailureKind::LockedDownKcore,
+            ),
+            (
+                memory(SnapshotError::UnableToCreateMemorySnapshot(
+                    ImageError::Io {
+                        context: "unable to write block",
+                        source: io(ErrorKind::StorageFull),
+                    },
+                )),
+                FailureKind::DiskFull,
+            ),
+            (
+                memory(SnapshotError::Disk(io(ErrorKind::QuotaExceeded))),
+                FailureKind::DiskFull,
+            ),
+            (
+                memory(SnapshotError::Disk(io(ErrorKind::PermissionDenied))),
+                FailureKind::PermissionDenied,
+            ),
+            (
+                memory(SnapshotError::KcoreParse("no PT_LOAD segments")),
+                FailureKind::Other,
+            ),
+            (
+                memory(SnapshotError::BlockSize { value: -1 }),
+                FailureKind::Other,
+            ),
+            (
+                memory(SnapshotError::UnsupportedPlatform { os: "plan9" }),
+                FailureKind::Other,
+            ),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.failure_kind(), kind, "{error:?}");
+        }
+    }
+
+    #[test]
+    fn image_errors_map_to_their_kind() {
+        let cases = [
+            (
+                image(ImageError::Io {
+                    context: "unable to write block",
+                    source: io(ErrorKind::StorageFull),
+                }),
+                FailureKind::DiskFull,
+            ),
+            (
+                image(ImageError::Io {
+                    context: "unable to read",
+                    source: io(ErrorKind::UnexpectedEof),
+                }),
+                FailureKind::Other,
+            ),
+            (
+                image(ImageError::WriteBlock {
+                    range: 0..0x1000,
+                    source: Box::new(ImageError::Io {
+                        context: "unable to write block",
+                        source: io(ErrorKind::QuotaExceeded),
+                    }),
+                }),
+                FailureKind::DiskFull,
+            ),
+            (
+                image(ImageError::InvalidPadding),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::UnsupportedFormat),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::InvalidRange { range: 1..1 }),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::Gap { range: 0..0x1000 }),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::InvalidManifest("truncated")),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::ManifestMismatch { range: 0..0x1000 }),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::Conflict { range: 0..0x1000 }),
+                FailureKind::InvalidSnapshot,
+            ),
+            (
+                image(ImageError::OutOfOrder { range: 0..0x1000 }),
+                FailureKind::InvalidSnapshot,
+            ),
+            (image(ImageError::TooLarge), FailureKind::Other),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.failure_kind(), kind, "{error:?}");
+        }
+    }
+
+    #[test]
+    fn other_errors_map_to_their_kind() {
+        let cases = [
+            (
+                Error::Iomem(IomemError::PermissionDenied),
+                FailureKind::PermissionDenied,
+            ),
+            (
+                Error::Iomem(IomemError::ParseLine("garbage".to_string())),
+                FailureKind::Other,
+            ),
+            (
+                Error::Iomem(IomemError::Io(io(ErrorKind::NotFound))),
+                FailureKind::Other,
+            ),
+            (Error::Guest(GuestError::NoRam), FailureKind::Usage),
+            (
+                Error::Throttle(ThrottleError::InvalidNice(20)),
+                FailureKind::Usage,
+            ),
+            (
+                Error::Throttle(ThrottleError::Io {
+                    context: "unable to set nice value",
+                    source: io(ErrorKind::PermissionDenied),
+                }),
+                FailureKind::PermissionDenied,
+            ),
+            (
+                Error::Io {
+                    context: "unable to create output file",
+                    source: io(ErrorKind::StorageFull),
+                },
+                FailureKind::DiskFull,
+            ),
+            (Error::NoConversionRequired, FailureKind::Usage),
+            (Error::Usage("simulated"), FailureKind::Usage),
+        ];
+        for (error, kind) in cases {
+            assert_eq!(error.failure_kind(), kind, "{error:?}");
+        }
+    }
+
+    #[cfg(feature = "put")]
+    #[test]
+    fn put_errors_map_to_their_kind() {
+        use crate::upload::http::Error as PutError;
+
+        let cases = [
+            (401, FailureKind::UploadDenied),
+            (403, FailureKind::UploadDenied),
+            (404, FailureKind::UploadFailed),
+            (500, FailureKind::UploadFailed),
+        ];
+        for (status, kind) in cases {
+            let error = Error::Upload(PutError::UnexpectedStatusCode { status });
+            assert_eq!(error.failure_kind(), kind, "{status}");
+        }
+    }
+
+    #[cfg(feature = "blobstore")]
+    #[test]
+    fn blob_errors_map_to_their_kind() {
+        use crate::upload::blobstore::Error as BlobError;
+        use azure_core::{
+            error::{Error as AzureError, ErrorKind as AzureErrorKind},
+            http::StatusCode,
+        };
+
+        let azure = |status| {
+            Error::Blob(BlobError::Azure(AzureError::with_message(
+                AzureErrorKind::HttpResponse {
+                    status,
+                    error_code: None,
+                    raw_response: None,
+                },
+                "simulated",
+            )))
+        };
+        assert_eq!(
+            azure(StatusCode::Forbidden).failure_kind(),
+            FailureKind::UploadDenied
+        );
+        assert_eq!(
+            azure(StatusCode::Unauthorized).failure_kind(),
+            FailureKind::UploadDenied
+        );
+        assert_eq!(
+            azure(StatusCode::InternalServerError).failure_kind(),
+            FailureKind::UploadFailed
+        );
+        assert_eq!(
+            Error::Blob(BlobError::TooLarge).failure_kind(),
+            FailureKind::UploadFailed
+        );
+        assert_eq!(
+            Error::Blob(BlobError::Azure(AzureError::with_message(
+                AzureErrorKind::Credential,
+                "simulated",
+            )))
+            .failure_kind(),
+            FailureKind::UploadDenied
+        );
+    }
+}
@@ -0,0 +1,677 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Acquiring a QEMU/KVM guest from the host, through the file backing the
+//! guest's RAM, such as a `memory-backend-file` in `/dev/shm` or hugetlbfs.
+//!
+//! The backing file holds guest RAM contiguously, while the guest sees it
+//! split around holes such as the PCI hole below 4 GiB. A [`GuestMap`]
+//! gives the offset in the file of each range of guest-physical memory,
+//! derived from the QEMU command line, an `info mtree` dump, or a JSON
+//! description, and [`GuestMemory`] reads the file through it.
+
+use crate::{
+    errors::FailureKind,
+    image::Block,
+    source::{MemorySource, read_mapped},
+};
+use core::{num::ParseIntError, ops::Range};
+use std::{
+    collections::BTreeMap,
+    fs::{File, read_to_string},
+    io::{Error as IoError, Result as IoResult},
+    path::{Path, PathBuf},
+};
+
+const ONE_MIB: u64 = 0x10_0000;
+const FOUR_GIB: u64 = 0x1_0000_0000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("unable to read the guest memory map")]
+    Io(#[from] IoError),
+
+    #[error("unable to parse value")]
+    ParseInt(#[from] ParseIntError),
+
+    #[error("unable to parse the guest memory map: {0}")]
+    Parse(String),
+
+    #[error("unsupported QEMU machine type: {0}")]
+    UnsupportedMachine(String),
+
+    #[error("no guest RAM found in the memory map")]
+    NoRam,
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Io(_) => "guest_map_io",
+            Self::ParseInt(_) | Self::Parse(_) => "guest_map_parse",
+            Self::UnsupportedMachine(_) => "guest_map_unsupported_machine",
+            Self::NoRam => "guest_map_no_ram",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io(ref e) => FailureKind::from_io(e),
+            Self::ParseInt(_) | Self::Parse(_) | Self::UnsupportedMachine(_) | Self::NoRam => {
+                FailureKind::Usage
+            }
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Where each range of guest-physical memory is in the file backing the
+/// guest's RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestMap {
+    // sorted ascending by guest-physical address, and non-overlapping
+    blocks: Vec<Block>,
+}
+
+impl GuestMap {
+    /// Read a memory map from `path`, as [`Self::parse`] does.
+    ///
+    /// # Errors
+    /// Returns an error if `path` cannot be read or parsed.
+    pub fn read(path: &Path, region: Option<&str>) -> Result<Self> {
+        Self::parse(&read_to_string(path)?, region)
+    }
+
+    /// Parse a JSON description if `text` is a JSON array, an `info mtree`
+    /// dump if it looks like one, and a QEMU command line otherwise.
+    /// `region` is only used for `info mtree`.
+    ///
+    /// # Errors
+    /// Returns an error if `text` cannot be parsed, or maps no RAM.
+    pub fn parse(text: &str, region: Option<&str>) -> Result<Self> {
+        if text.trim_start().starts_with('[') {
+            Self::from_json(text)
+        } else if text.contains("address-space:") || text.contains("FlatView #") {
+            Self::from_mtree(text, region)
+        } else {
+            Self::from_command_line(text)
+        }
+    }
+
+    /// Derive the memory map from a QEMU command line, as the machine
+    /// lays out RAM of the size given by `-m`. Arguments may be separated
+    /// by whitespace or by NUL bytes, as in `/proc/<pid>/cmdline`.
+    ///
+    /// Supported machine types are `pc` and `q35` on x86, which split RAM
+    /// around the PCI hole below 4 GiB, and `virt` on Arm, which maps RAM
+    /// from 1 GiB.
+    ///
+    /// # Errors
+    /// Returns an error if the RAM size is missing or invalid, or the
+    /// machine type is not supported.
+    pub fn from_command_line(cmdline: &str) -> Result<Self> {
+        let mut ram_size = None;
+        let mut machine = "pc";
+        let mut max_ram_below_4g = None;
+
+        let mut args = cmdline
+            .split(|c: char| c.is_whitespace() || c == '\0')
+            .filter(|arg| !arg.is_empty() && *arg != "\\");
+        while let Some(arg) = args.next() {
+            let option = arg.trim_start_matches('-');
+            if option.len() == arg.len() {
+                continue;
+            }
+            let (option, value) = match option.split_once('=') {
+                Some((option, value)) if ["m", "machine", "M"].contains(&option) => {
+                    (option, Some(value))
+                }
+                _ => (option, None),
+            };
+            if !["m", "machine", "M"].contains(&option) {
+                continue;
+            }
+            let value = value
+                .or_else(|| args.next())
+                .ok_or_else(|| Error::Parse(format!("-{option} needs a value")))?;
+            for (i, part) in value.split(',').enumerate() {
+                let (key, val) = match part.split_once('=') {
+                    Some((key, val)) => (key, val),
+                    None if i == 0 && option == "m" => ("size", part),
+                    None if i == 0 => ("type", part),
+                    None => continue,
+                };
+                match (option, key) {
+                    ("m", "size") => ram_size = Some(parse_size(val, ONE_MIB)?),
+                    ("machine" | "M", "type") => machine = val,
+                    ("machine" | "M", "max-ram-below-4g") => {
+                        max_ram_below_4g = Some(parse_size(val, 1)?);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let ram_size = ram_size.ok_or_else(|| Error::Parse("no -m option".to_string()))?;
+        let blocks = if machine == "q35" || machine.starts_with("pc-q35") {
+            let lowmem = if ram_size >= 0xb000_0000 {
+                0x8000_0000
+            } else {
+                0xb000_0000
+            };
+            split_at_pci_hole(
+                ram_size,
+                max_ram_below_4g.map_or(lowmem, |max| lowmem.min(max)),
+            )
+        } else if machine == "pc" || machine.starts_with("pc-") {
+            let mut lowmem = max_ram_below_4g.unwrap_or(0xe000_0000);
+            if ram_size >= lowmem {
+                lowmem = lowmem.min(0xc000_0000);
+            }
+            split_at_pci_hole(ram_size, lowmem)
+        } else if machine == "virt" || machine.starts_with("virt-") {
+            vec![Block {
+                range: 0x4000_0000..0x4000_0000_u64.saturating_add(ram_size),
+                offset: 0,
+            }]
+        } else {
+            return Err(Error::UnsupportedMachine(machine.to_string()));
+        };
+        Self::from_blocks(blocks)
+    }
+
+    /// Parse the output of the QEMU monitor command `info mtree`, or
+    /// `info mtree -f`, using the RAM mapped from `region`, or from the
+    /// region mapping the most memory if `None`.
+    ///
+    /// # Errors
+    /// Returns an error if a line of the dump cannot be parsed, or no RAM
+    /// is mapped from `region`.
+    pub fn from_mtree(dump: &str, region: Option<&str>) -> Result<Self> {
+        let mut regions: BTreeMap<&str, Vec<Block>> = BTreeMap::new();
+        // `memory-region:` sections list regions at their own offsets,
+        // rather than at guest-physical addresses
+        let mut guest_physical = false;
+        for line in dump.lines() {
+            if !line.starts_with(char::is_whitespace) {
+                guest_physical = line.starts_with("address-space:") || line.starts_with("FlatView");
+                continue;
+            }
+            if !guest_physical {
+                continue;
+            }
+            if let Some((name, block)) = parse_mtree_line(line.trim())? {
+                regions.entry(name).or_default().push(block);
+            }
+        }
+
+        let blocks = match region {
+            Some(region) => regions.remove(region).unwrap_or_default(),
+            None => regions
+                .into_values()
+                .max_by_key(|blocks| {
+                    blocks
+                        .iter()
+                        .map(|block| block.range.end.saturating_sub(block.range.start))
+                        .fold(0_u64, u64::saturating_add)
+                })
+                .unwrap_or_default(),
+        };
+        Self::from_blocks(blocks)
+    }
+
+    /// Parse a JSON array of the ranges of guest-physical memory, each an
+    /// object mapping `size` bytes from guest-physical address `start` to
+    /// `offset` in the file. Values are integers, or hex strings starting
+    /// with `0x`:
+    ///
+    /// ```json
+    /// [
+    ///   {"start": 0, "size": "0x80000000", "offset": 0},
+    ///   {"start": "0x100000000", "size": "0x80000000", "offset": "0x80000000"}
+    /// ]
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if `json` is not such an array, or maps no RAM.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut json = Json(json);
+        let mut blocks = Vec::new();
+        json.expect('[')?;
+        if !json.eat(']') {
+            loop {
+                blocks.push(json.block()?);
+                if json.eat(']') {
+                    break;
+                }
+                json.expect(',')?;
+            }
+        }
+        if !json.0.trim().is_empty() {
+            return Err(Error::Parse(format!("trailing text: {}", json.0.trim())));
+        }
+        Self::from_blocks(blocks)
+    }
+
+    // sort `blocks`, dropping the parts mapped more than once, as when a
+    // dump has both the tree and the flat view, and joining the blocks
+    // contiguous in both the guest and the file
+    fn from_blocks(mut blocks: Vec<Block>) -> Result<Self> {
+        blocks.retain(|block| block.range.start < block.range.end);
+        blocks.sort_by_key(|block| (block.range.start, block.range.end));
+
+        let mut merged: Vec<Block> = Vec::new();
+        for mut block in blocks {
+            if let Some(last) = merged.last_mut() {
+                if block.range.end <= last.range.end {
+                    continue;
+                }
+                if block.range.start < last.range.end {
+                    block.offset = block
+                        .offset
+                        .saturating_add(last.range.end.saturating_sub(block.range.start));
+                    block.range.start = last.range.end;
+                }
+                let last_len = last.range.end.saturating_sub(last.range.start);
+                if block.range.start == last.range.end
+                    && block.offset == last.offset.saturating_add(last_len)
+                {
+                    last.range.end = block.range.end;
+                    continue;
+                }
+            }
+            merged.push(block);
+        }
+
+        if merged.is_empty() {
+            return Err(Error::NoRam);
+        }
+        Ok(Self { blocks: merged })
+    }
+
+    /// The ranges of guest-physical memory backed by the file.
+    #[must_use]
+    pub fn ranges(&self) -> Vec<Range<u64>> {
+        self.blocks
+            .iter()
+            .map(|block| block.range.clone())
+            .collect()
+    }
+
+    /// Each range of guest-physical memory, with its offset in the file.
+    #[must_use]
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+}
+
+// x86 machines map RAM up to `lowmem` from 0, and the rest from 4 GiB
+fn split_at_pci_hole(ram_size: u64, lowmem: u64) -> Vec<Block> {
+    let below_4g = ram_size.min(lowmem);
+    let above_4g = ram_size.saturating_sub(below_4g);
+    let mut blocks = vec![Block {
+        range: 0..below_4g,
+        offset: 0,
+    }];
+    if above_4g > 0 {
+        blocks.push(Block {
+            range: FOUR_GIB..FOUR_GIB.saturating_add(above_4g),
+            offset: below_4g,
+        });
+    }
+    blocks
+}
+
+// Parse a size as QEMU does, in units of `default_unit` without a suffix.
+fn parse_size(value: &str, default_unit: u64) -> Result<u64> {
+    let value = value.trim_end_matches(['B', 'b']);
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                'P' => 50,
+                'E' => 60,
+                _ => return Err(Error::Parse(format!("invalid size: {value}"))),
+            };
+            (value.get(..i).unwrap_or_default(), 1_u64 << shift)
+        }
+        _ => (value, default_unit),
+    };
+    digits
+        .parse::<u64>()?
+        .checked_mul(unit)
+        .ok_or_else(|| Error::Parse(format!("size too large: {value}")))
+}
+
+// Parse a line of `info mtree`, returning the region the RAM it maps is
+// from, or `None` if it maps no RAM. Lines look like:
+//
+//   0000000100000000-000000027fffffff (prio 0, ram): alias ram-above-4g @pc.ram 0000000080000000-00000001ffffffff
+//   0000000040000000-000000013fffffff (prio 0, ram): mach-virt.ram
+//   00000000000c0000-00000000000dffff (prio 0, ram): pc.ram @00000000000c0000 KVM
+fn parse_mtree_line(line: &str) -> Result<Option<(&str, Block)>> {
+    let Some((range, rest)) = line.split_once(" (") else {
+        return Ok(None);
+    };
+    let Some((attributes, description)) = rest.split_once("): ") else {
+        return Ok(None);
+    };
+    if attributes.rsplit(", ").next() != Some("ram") {
+        return Ok(None);
+    }
+    let range = parse_mtree_range(range)?;
+
+    let mut words = description.split_whitespace();
+    let (name, offset) = if description.starts_with("alias ") {
+        let name = words
+            .nth(2)
+            .and_then(|target| target.strip_prefix('@'))
+            .ok_or_else(|| Error::Parse(format!("invalid alias: {line}")))?;
+        let target = words
+            .next()
+            .ok_or_else(|| Error::Parse(format!("invalid alias: {line}")))?;
+        (name, parse_mtree_range(target)?.start)
+    } else {
+        let name = words
+            .next()
+            .ok_or_else(|| Error::Parse(format!("missing region name: {line}")))?;
+        let offset = match words.next().and_then(|word| word.strip_prefix('@')) {
+            Some(offset) => u64::from_str_radix(offset, 16)?,
+            None => 0,
+        };
+        (name, offset)
+    };
+    Ok(Some((name, Block { offset, range })))
+}
+
+// `start-end`, in hex, with an inclusive end
+fn parse_mtree_range(range: &str) -> Result<Range<u64>> {
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| Error::Parse(format!("invalid range: {range}")))?;
+    let start = u64::from_str_radix(start, 16)?;
+    let end = u64::from_str_radix(end, 16)?
+        .checked_add(1)
+        .ok_or_else(|| Error::Parse(format!("range too large: {range}")))?;
+    Ok(start..end)
+}
+
+// The unparsed rest of a JSON memory map.
+struct Json<'a>(&'a str);
+
+impl<'a> Json<'a> {
+    // consume `c`, after any whitespace, if it is next
+    fn eat(&mut self, c: char) -> bool {
+        match self.0.trim_start().strip_prefix(c) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            return Ok(());
+        }
+        let next = self.0.trim_start().chars().next();
+        Err(Error::Parse(match next {
+            Some(found) => format!("expected `{c}`, found `{found}`"),
+            None => format!("expected `{c}`, found the end"),
+        }))
+    }
+
+    // a string, without escapes
+    fn string(&mut self) -> Result<&'a str> {
+        self.expect('"')?;
+        let (string, rest) = self
+            .0
+            .split_once('"')
+            .ok_or_else(|| Error::Parse("unterminated string".to_string()))?;
+        if string.contains('\\') {
+            return Err(Error::Parse(format!("unsupported escape in \"{string}\"")));
+        }
+        self.0 = rest;
+        Ok(string)
+    }
+
+    // an integer, or a hex string starting with `0x`
+    fn value(&mut self) -> Result<u64> {
+        if self.0.trim_start().starts_with('"') {
+            let hex = self.string()?;
+            let digits = hex
+                .strip_prefix("0x")
+                .ok_or_else(|| Error::Parse(format!("expected a hex value: \"{hex}\"")))?;
+            return Ok(u64::from_str_radix(digits, 16)?);
+        }
+        self.0 = self.0.trim_start();
+        let len = self
+            .0
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        let (digits, rest) = self.0.split_at_checked(len).unwrap_or((self.0, ""));
+        self.0 = rest;
+        Ok(digits.parse()?)
+    }
+
+    // an object with the `start`, `size`, and `offset` of a block
+    fn block(&mut self) -> Result<Block> {
+        let (mut start, mut size, mut offset) = (None, None, None);
+        self.expect('{')?;
+        loop {
+            let key = self.string()?;
+            self.expect(':')?;
+            let value = Some(self.value()?);
+            match key {
+                "start" => start = value,
+                "size" => size = value,
+                "offset" => offset = value,
+                _ => return Err(Error::Parse(format!("unknown field: {key}"))),
+            }
+            if self.eat('}') {
+                break;
+            }
+            self.expect(',')?;
+        }
+        let missing = |field: &str| Error::Parse(format!("missing field: {field}"));
+        let start = start.ok_or_else(|| missing("start"))?;
+        let size = size.ok_or_else(|| missing("size"))?;
+        let end = start
+            .checked_add(size)
+            .ok_or_else(|| Error::Parse(format!("range too large: {start:#x}+{size:#x}")))?;
+        Ok(Block {
+            range: start..end,
+            offset: offset.ok_or_else(|| missing("offset"))?,
+        })
+    }
+}
+
+/// The RAM of a QEMU/KVM guest, read from the file backing it.
+pub struct GuestMemory {
+    path: PathBuf,
+    file: File,
+    map: GuestMap,
+}
+
+impl GuestMemory {
+    /// Open the file at `path` backing the RAM of a guest with memory map
+    /// `map`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened.
+    pub fn open(path: &Path, map: GuestMap) -> IoResult<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: File::open(path)?,
+            map,
+        })
+    }
+
+    /// The guest's memory map.
+    #[must_use]
+    pub fn map(&self) -> &GuestMap {
+        &self.map
+    }
+}
+
+impl MemorySource for GuestMemory {
+    // Debug escapes control characters in the path, as names end up in
+    // error messages
+    #[expect(
+        clippy::unnecessary_debug_formatting,
+        reason = "escaping is the point — see comment above"
+    )]
+    fn name(&self) -> String {
+        format!("guest memory {:?}", self.path)
+    }
+
+    // the guest's memory map, rather than the host's
+    fn ranges(&self, _memory_ranges: &[Range<u64>]) -> IoResult<Vec<Range<u64>>> {
+        Ok(self.map.ranges())
+    }
+
+    fn read_at(&self, addr: u64, buf: &mut [u8]) -> IoResult<usize> {
+        read_mapped(&self.file, self.map.blocks(), addr, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, GuestMap};
+    use crate::image::Block;
+
+    const GIB: u64 = 0x4000_0000;
+
+    fn block(range: core::ops::Range<u64>, offset: u64) -> Block {
+        Block { offset, range }
+    }
+
+    #[test]
+    fn splits_q35_ram_around_the_pci_hole() -> Result<(), Error> {
+        let map = GuestMap::from_command_line(
+            "qemu-system-x86_64 -machine q35,accel=kvm -m 8G \\\n -object memory-backend-file,id=mem,size=8G,mem-path=/dev/shm/vm,share=on",
+        )?;
+        assert_eq!(
+            map.blocks(),
+            [block(0..2 * GIB, 0), block(4 * GIB..10 * GIB, 2 * GIB)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn splits_pc_ram_at_three_gib() -> Result<(), Error> {
+        let map = GuestMap::from_command_line("qemu-system-x86_64\0-m\0size=4096,slots=2\0")?;
+        assert_eq!(
+            map.blocks(),
+            [block(0..3 * GIB, 0), block(4 * GIB..5 * GIB, 3 * GIB)]
+        );
+
+        let small = GuestMap::from_command_line("qemu-system-x86_64 -M pc-i440fx-8.2 -m 2G")?;
+        assert_eq!(small.blocks(), [block(0..2 * GIB, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn maps_arm_virt_ram_from_one_gib() -> Result<(), Error> {
+        let map = GuestMap::from_command_line("qemu-system-aarch64 -M virt -m 1G")?;
+        assert_eq!(map.blocks(), [block(GIB..2 * GIB, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_machines() {
+        let map = GuestMap::from_command_line("qemu-system-x86_64 -M microvm -m 1G");
+        assert!(
+            map.is_err_and(|e| matches!(e, Error::UnsupportedMachine(ref m) if m == "microvm"))
+        );
+    }
+
+    const MTREE: &str = "\
+address-space: memory
+  0000000000000000-ffffffffffffffff (prio 0, i/o): system
+    0000000000000000-000000007fffffff (prio 0, ram): alias ram-below-4g @pc.ram 0000000000000000-000000007fffffff
+    0000000000000000-ffffffffffffffff (prio -1, i/o): pci
+      00000000000a0000-00000000000bffff (prio 1, i/o): vga-lowmem
+      00000000fd000000-00000000fdffffff (prio 1, ram): vga.vram
+    0000000100000000-000000017fffffff (prio 0, ram): alias ram-above-4g @pc.ram 0000000080000000-00000000ffffffff
+
+address-space: I/O
+  0000000000000000-000000000000ffff (prio 0, i/o): io
+
+memory-region: pc.ram
+  0000000000000000-00000000ffffffff (prio 0, ram): pc.ram
+";
+
+    #[test]
+    fn follows_aliases_of_the_largest_region() -> Result<(), Error> {
+        let map = GuestMap::parse(MTREE, None)?;
+        assert_eq!(
+            map.blocks(),
+            [block(0..2 * GIB, 0), block(4 * GIB..6 * GIB, 2 * GIB)]
+        );
+
+        let vram = GuestMap::from_mtree(MTREE, Some("vga.vram"))?;
+        assert_eq!(vram.blocks(), [block(0xfd00_0000..0xfe00_0000, 0)]);
+        Ok(())
+    }
+
+    #[test]
+    fn joins_flat_view_ranges() -> Result<(), Error> {
+        let map = GuestMap::from_mtree(
+            "\
+FlatView #0
+ AS \"memory\", root: system
+ Root memory region: system
+  0000000000000000-000000000009ffff (prio 0, ram): pc.ram KVM
+  00000000000c0000-00000000000dffff (prio 0, ram): pc.ram @00000000000c0000 KVM
+  00000000000e0000-00000000000fffff (prio 0, ram): pc.ram @00000000000e0000 KVM
+",
+            Some("pc.ram"),
+        )?;
+        assert_eq!(
+            map.blocks(),
+            [block(0..0xa_0000, 0), block(0xc_0000..0x10_0000, 0xc_0000)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_a_json_memory_map() -> Result<(), Error> {
+        let map = GuestMap::parse(
+            r#"
+[
+  {"start": "0x100000000", "size": "0x80000000", "offset": 2147483648},
+  {"start": 0, "size": "0x80000000", "offset": "0x0"}
+]
+"#,
+            None,
+        )?;
+        assert_eq!(
+            map.blocks(),
+            [block(0..2 * GIB, 0), block(4 * GIB..6 * GIB, 2 * GIB)]
+        );
+
+        assert!(GuestMap::from_json("[]").is_err_and(|e| matches!(e, Error::NoRam)));
+        let missing = GuestMap::from_json(r#"[{"start": 0, "size": 4096}]"#);
+        assert!(missing.is_err_and(|e| matches!(e, Error::Parse(ref m) if m.contains("offset"))));
+        let unknown = GuestMap::from_json(r#"[{"start": 0, "length": 4096, "offset": 0}]"#);
+        assert!(unknown.is_err_and(|e| matches!(e, Error::Parse(ref m) if m.contains("length"))));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_regions_map_no_ram() {
+        let map = GuestMap::from_mtree(MTREE, Some("mem0"));
+        assert!(map.is_err_and(|e| matches!(e, Error::NoRam)));
+    }
+}
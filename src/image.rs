@@ -1,9 +1,17 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use crate::io::snappy::SnapCountWriter;
+use crate::{
+    errors::FailureKind,
+    incremental::Incremental,
+    io::{counter::Counter, parallel::read_in_order, snappy::SnapCountWriter},
+    progress::{Operation, Progress, Reporter, Tracker},
+    source::{MemorySource, read_exact_at},
+    throttle::RateLimit,
+};
 use byteorder::{ByteOrder as _, LittleEndian, ReadBytesExt as _};
-use core::ops::Range;
+use clap::ValueEnum;
+use core::{num::NonZeroUsize, ops::Range};
 #[cfg(target_family = "unix")]
 use libc::O_NOFOLLOW;
 use snap::read::FrameDecoder;
@@ -11,9 +19,15 @@ use snap::read::FrameDecoder;
 use std::os::unix::fs::OpenOptionsExt as _;
 use std::{
     fs::{File, OpenOptions, canonicalize},
-    io::{Cursor, Read, Seek, SeekFrom, Write},
+    io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write, copy as io_copy, repeat, sink},
     path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
+#[cfg(feature = "async")]
+use tokio_util::sync::CancellationToken;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -45,6 +59,66 @@ pub enum Error {
 
     #[error("invalid header range {range:?}: start must be strictly less than end")]
     InvalidRange { range: Range<u64> },
+
+    #[error("no record covers {range:?}")]
+    Gap { range: Range<u64> },
+
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(&'static str),
+
+    #[error("reconstructed memory at {range:?} does not match the manifest")]
+    ManifestMismatch { range: Range<u64> },
+
+    #[error("inputs hold different memory at {range:?}")]
+    Conflict { range: Range<u64> },
+
+    #[error("record {range:?} overlaps or precedes an earlier record")]
+    OutOfOrder { range: Range<u64> },
+
+    #[error("acquisition cancelled")]
+    Cancelled,
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Io { .. } => "io",
+            Self::InvalidPadding => "invalid_padding",
+            Self::TooLarge => "too_large",
+            Self::UnsupportedFormat => "unsupported_format",
+            Self::WriteBlock { ref source, .. } => source.code(),
+            Self::IntConversion(_) => "int_conversion",
+            Self::InvalidRange { .. } => "invalid_range",
+            Self::Gap { .. } => "gap",
+            Self::InvalidManifest(_) => "invalid_manifest",
+            Self::ManifestMismatch { .. } => "manifest_mismatch",
+            Self::Conflict { .. } => "conflict",
+            Self::OutOfOrder { .. } => "out_of_order",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io { ref source, .. } => FailureKind::from_io(source),
+            Self::WriteBlock { ref source, .. } => source.failure_kind(),
+            Self::InvalidPadding
+            | Self::UnsupportedFormat
+            | Self::InvalidRange { .. }
+            | Self::Gap { .. }
+            | Self::InvalidManifest(_)
+            | Self::ManifestMismatch { .. }
+            | Self::Conflict { .. }
+            | Self::OutOfOrder { .. } => FailureKind::InvalidSnapshot,
+            Self::TooLarge | Self::IntConversion(_) => FailureKind::Other,
+            Self::Cancelled => FailureKind::Cancelled,
+        }
+    }
 }
 
 type Result<T> = core::result::Result<T, Error>;
@@ -101,6 +175,31 @@ impl From<bool> for Format {
     }
 }
 
+/// How [`Image::extract`] and [`Image::extract_raw`] treat parts of the
+/// requested window that no record in the source covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnGap {
+    /// Treat uncovered memory as zero. Raw output is zero-filled; snapshot
+    /// output has no record for the gap, as with any other unmapped range.
+    Zero,
+    /// Fail with [`Error::Gap`].
+    Error,
+}
+
+/// How acquisition treats memory that cannot be read, such as a page that
+/// fails with `EIO` or `EFAULT` on some hypervisors or after memory is
+/// hot-removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OnReadError {
+    /// Fail the acquisition.
+    #[default]
+    Abort,
+    /// Write each unreadable page as zeros.
+    Zero,
+    /// Leave each unreadable page out, splitting the record around it.
+    Skip,
+}
+
 /// Largest block AVML emits in a single header. Ranges larger than this
 /// are split into `MAX_BLOCK_SIZE`-sized chunks before being written.
 ///
@@ -111,7 +210,7 @@ impl From<bool> for Format {
 /// the granularity at which zero regions are skipped.
 pub const MAX_BLOCK_SIZE: u64 = 0x1000 * 0x1000;
 const PAGE_SIZE: usize = 0x1000;
-const HEADER_LEN: usize = 32;
+pub(crate) const HEADER_LEN: usize = 32;
 
 #[derive(Debug, Clone)]
 pub struct Header {
@@ -170,6 +269,42 @@ impl Header {
         Ok(Self { range, format })
     }
 
+    /// Reads the next header from a stream of records.
+    ///
+    /// Returns `Ok(None)` if the source is exhausted exactly at a record
+    /// boundary, which lets callers walk a snapshot from a non-seekable
+    /// source (such as stdin) without knowing its length up front.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The source ends partway through a header
+    /// - The header itself is invalid (see [`Header::read`])
+    pub fn read_next<R: Read>(mut src: R) -> Result<Option<Self>> {
+        let mut bytes = [0; HEADER_LEN];
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            let remaining = bytes.get_mut(filled..).unwrap_or_default();
+            match src.read(remaining) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(Error::Io {
+                        context: "unable to read header",
+                        source: std::io::ErrorKind::UnexpectedEof.into(),
+                    });
+                }
+                Ok(n) => filled = filled.saturating_add(n),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(source) => {
+                    return Err(Error::Io {
+                        context: "unable to read header",
+                        source,
+                    });
+                }
+            }
+        }
+        Self::read(bytes.as_slice()).map(Some)
+    }
+
     fn encode(&self) -> [u8; HEADER_LEN] {
         let mut bytes = [0; HEADER_LEN];
         LittleEndian::write_u32_into(
@@ -220,34 +355,34 @@ impl Header {
 /// - Reading from the source fails
 /// - Writing to the destination fails
 #[inline]
-fn copy<R, W>(mut size: usize, align_src: bool, mut src: R, mut dst: W) -> Result<()>
+fn copy<R, W>(
+    mut size: usize,
+    align_src: bool,
+    read_size: &mut usize,
+    mut src: R,
+    mut dst: W,
+) -> Result<()>
 where
     R: Read,
     W: Write,
 {
     if align_src {
-        let mut buf = vec![0; PAGE_SIZE];
-        while size >= PAGE_SIZE {
-            src.read_exact(&mut buf).map_err(|source| Error::Io {
-                context: "unable to read memory page",
-                source,
-            })?;
-            dst.write_all(&buf).map_err(|source| Error::Io {
-                context: "unable to write memory page",
-                source,
-            })?;
-            size = size.saturating_sub(PAGE_SIZE);
-        }
-        if size > 0 {
-            buf.resize(size, 0);
-            src.read_exact(&mut buf).map_err(|source| Error::Io {
+        let mut buf = vec![0; size.min(*read_size)];
+        let learned = AtomicUsize::new(*read_size);
+        while size > 0 {
+            let len = size.min(learned.load(Ordering::Relaxed));
+            let chunk = buf.get_mut(..len).unwrap_or_default();
+            let read = read_pages(chunk, &learned, |part, _| src.read(part));
+            *read_size = learned.load(Ordering::Relaxed);
+            read.map_err(|source| Error::Io {
                 context: "unable to read memory page",
                 source,
             })?;
-            dst.write_all(&buf).map_err(|source| Error::Io {
+            dst.write_all(chunk).map_err(|source| Error::Io {
                 context: "unable to write memory page",
                 source,
             })?;
+            size = size.saturating_sub(len);
         }
     } else {
         let mut src = src.take(size.try_into()?);
@@ -259,58 +394,370 @@ where
     Ok(())
 }
 
-pub struct Image<R: Read + Seek, W: Write> {
-    pub(crate) format: Format,
-    pub(crate) align_src: bool,
-    pub src: R,
-    pub dst: W,
+/// Fills `buf`, which starts on a page boundary of the source, with reads
+/// of at most `read_size` bytes, using `read` to read into part of `buf`
+/// given the offset of that part. A short read is followed by a read of the
+/// rest of its page, so later reads stay page-aligned.
+///
+/// Drivers that reject reads of more than one page with `EINVAL`, such as
+/// some versions of `/dev/crash`, are read a page at a time instead, and
+/// `read_size` is lowered to one page for later reads, including those of
+/// other threads sharing it.
+fn read_pages<F>(buf: &mut [u8], read_size: &AtomicUsize, mut read: F) -> std::io::Result<()>
+where
+    F: FnMut(&mut [u8], usize) -> std::io::Result<usize>,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        let wanted = match filled.checked_rem(PAGE_SIZE).unwrap_or_default() {
+            0 => read_size.load(Ordering::Relaxed),
+            partial => PAGE_SIZE.saturating_sub(partial),
+        };
+        let end = buf.len().min(filled.saturating_add(wanted));
+        let part = buf.get_mut(filled..end).unwrap_or_default();
+        match read(part, filled) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(len) => filled = filled.saturating_add(len),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) && wanted > PAGE_SIZE => {
+                read_size.store(PAGE_SIZE, Ordering::Relaxed);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
 }
 
-impl<R: Read + Seek, W: Write> Image<R, W> {
-    /// Build an `Image` over arbitrary streams.
-    pub fn from_streams(format: Format, src: R, dst: W) -> Self {
+/// Read sizes tried by [`detect_read_size`], largest first.
+pub const READ_SIZES: [usize; 3] = [0x10_0000, 0x1_0000, 0x4000];
+
+/// Find the largest of [`READ_SIZES`] that `src` can be read with, by
+/// reading that much from the start of the first of `blocks` long enough.
+///
+/// Falls back to a single page if every larger read fails or comes up
+/// short.
+#[must_use]
+pub fn detect_read_size(src: &dyn MemorySource, blocks: &[Block]) -> usize {
+    for size in READ_SIZES {
+        let Ok(len) = u64::try_from(size) else {
+            continue;
+        };
+        let Some(block) = blocks
+            .iter()
+            .find(|block| range_len(block.range.clone()) >= len)
+        else {
+            continue;
+        };
+        let mut buf = vec![0; size];
+        if src
+            .read_at(block.offset, &mut buf)
+            .is_ok_and(|read| read == size)
+        {
+            return size;
+        }
+    }
+    PAGE_SIZE
+}
+
+/// Create (or truncate) a snapshot destination file.
+///
+/// On Unix the file is created with mode `0600` and symlinks are not
+/// followed, so a pre-planted link cannot redirect the snapshot.
+///
+/// # Errors
+/// Returns an error if the file cannot be created or opened for writing.
+#[cfg(target_family = "unix")]
+pub fn open_dst(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .mode(0o600)
+        .custom_flags(O_NOFOLLOW)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|source| Error::Io {
+            context: "unable to create snapshot file",
+            source,
+        })
+}
+
+/// Create (or truncate) a snapshot destination file.
+///
+/// # Errors
+/// Returns an error if the file cannot be created or opened for writing.
+#[cfg(target_family = "windows")]
+pub fn open_dst(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|source| Error::Io {
+            context: "unable to create snapshot file",
+            source,
+        })
+}
+
+/// A run of memory read from a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockData {
+    pub range: Range<u64>,
+    pub data: Vec<u8>,
+}
+
+/// Reads the memory held in a snapshot of either format as blocks of at
+/// most `MAX_BLOCK_SIZE` bytes, in the order the records appear.
+///
+/// The source is read strictly sequentially, so it need not be seekable.
+/// Iteration ends after the first error.
+pub struct BlockReader<R: Read> {
+    state: ReaderState<R>,
+}
+
+enum ReaderState<R: Read> {
+    Header(R),
+    Lime {
+        src: R,
+        remaining: Range<u64>,
+    },
+    Avml {
+        decoder: FrameDecoder<R>,
+        remaining: Range<u64>,
+    },
+    Done,
+}
+
+impl<R: Read> BlockReader<R> {
+    pub fn new(src: R) -> Self {
+        Self {
+            state: ReaderState::Header(src),
+        }
+    }
+
+    fn read_block(&mut self) -> Result<Option<BlockData>> {
+        loop {
+            match core::mem::replace(&mut self.state, ReaderState::Done) {
+                ReaderState::Done => return Ok(None),
+                ReaderState::Header(mut src) => {
+                    let Some(header) = Header::read_next(&mut src)? else {
+                        return Ok(None);
+                    };
+                    self.state = match header.format {
+                        Format::Lime => ReaderState::Lime {
+                            src,
+                            remaining: header.range,
+                        },
+                        Format::AvmlCompressed => ReaderState::Avml {
+                            decoder: FrameDecoder::new(src),
+                            remaining: header.range,
+                        },
+                    };
+                }
+                ReaderState::Lime { mut src, remaining } => {
+                    let (block, remaining) = read_block_data(&mut src, remaining)?;
+                    self.state = if remaining.is_empty() {
+                        ReaderState::Header(src)
+                    } else {
+                        ReaderState::Lime { src, remaining }
+                    };
+                    return Ok(Some(block));
+                }
+                ReaderState::Avml {
+                    mut decoder,
+                    remaining,
+                } => {
+                    let (block, remaining) = read_block_data(&mut decoder, remaining)?;
+                    self.state = if remaining.is_empty() {
+                        let mut src = decoder.into_inner();
+                        read_compressed_len(&mut src)?;
+                        ReaderState::Header(src)
+                    } else {
+                        ReaderState::Avml { decoder, remaining }
+                    };
+                    return Ok(Some(block));
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for BlockReader<R> {
+    type Item = Result<BlockData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+/// A snapshot's memory, consumed a page (or less) at a time in ascending
+/// address order.
+pub(crate) struct PageCursor<R: Read> {
+    blocks: BlockReader<R>,
+    current: Option<BlockData>,
+    // offset into `current.data` of the first unconsumed byte
+    offset: usize,
+}
+
+impl<R: Read> PageCursor<R> {
+    pub(crate) fn new(src: R) -> Self {
+        Self {
+            blocks: BlockReader::new(src),
+            current: None,
+            offset: 0,
+        }
+    }
+
+    /// The unconsumed part of the current block, reading the next block if
+    /// the current one is exhausted.
+    pub(crate) fn peek(&mut self) -> Result<Option<Range<u64>>> {
+        loop {
+            if let Some(block) = self.current.as_ref() {
+                let start = block
+                    .range
+                    .start
+                    .checked_add(u64::try_from(self.offset)?)
+                    .ok_or(Error::TooLarge)?;
+                if start < block.range.end {
+                    return Ok(Some(start..block.range.end));
+                }
+            }
+            if let Some(block) = self.blocks.next().transpose()? {
+                self.current = Some(block);
+                self.offset = 0;
+            } else {
+                self.current = None;
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Consume the bytes up to address `end`, which must be within the
+    /// range last returned by `peek`.
+    pub(crate) fn take(&mut self, end: u64) -> &[u8] {
+        let Some(block) = self.current.as_ref() else {
+            return &[];
+        };
+        let end = usize::try_from(end.saturating_sub(block.range.start))
+            .unwrap_or(usize::MAX)
+            .min(block.data.len());
+        let start = core::mem::replace(&mut self.offset, end);
+        block.data.get(start..end).unwrap_or_default()
+    }
+
+    /// The memory at `range`, if a single block holds all of it. Memory
+    /// below `range.start` is consumed, so calls must be made in ascending
+    /// address order.
+    pub(crate) fn read(&mut self, range: &Range<u64>) -> Result<Option<&[u8]>> {
+        loop {
+            match self.peek()? {
+                Some(available) if available.end <= range.start => {
+                    self.take(available.end);
+                }
+                Some(available) if available.start <= range.start && range.end <= available.end => {
+                    self.take(range.start);
+                    return Ok(Some(self.take(range.end)));
+                }
+                Some(_) | None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Accumulates contiguous runs of memory into records of at most
+/// `MAX_BLOCK_SIZE`. All-zero memory is written rather than elided.
+pub(crate) struct BlockWriter<W: Write> {
+    dst: W,
+    format: Format,
+    pending: Option<BlockData>,
+}
+
+impl<W: Write> BlockWriter<W> {
+    pub(crate) fn new(dst: W, format: Format) -> Self {
         Self {
-            format,
-            align_src: false,
-            src,
             dst,
+            format,
+            pending: None,
         }
     }
 
-    /// The destination format this `Image` writes.
-    #[must_use]
-    pub fn format(&self) -> Format {
-        self.format
+    pub(crate) fn push(&mut self, range: Range<u64>, data: &[u8]) -> Result<()> {
+        if let Some(pending) = self.pending.as_mut()
+            && pending.range.end == range.start
+            && range.end.saturating_sub(pending.range.start) <= MAX_BLOCK_SIZE
+        {
+            pending.range.end = range.end;
+            pending.data.extend_from_slice(data);
+            return Ok(());
+        }
+        self.flush()?;
+        self.pending = Some(BlockData {
+            range,
+            data: data.to_vec(),
+        });
+        Ok(())
     }
 
-    #[cfg(target_family = "windows")]
-    fn open_dst(path: &Path) -> Result<File> {
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .map_err(|source| Error::Io {
-                context: "unable to create snapshot file",
-                source,
-            })
+    fn flush(&mut self) -> Result<()> {
+        if let Some(block) = self.pending.take() {
+            write_record(&mut self.dst, self.format, block.range, &block.data)?;
+        }
+        Ok(())
     }
 
-    #[cfg(target_family = "unix")]
-    fn open_dst(path: &Path) -> Result<File> {
-        OpenOptions::new()
-            .mode(0o600)
-            .custom_flags(O_NOFOLLOW)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)
-            .map_err(|source| Error::Io {
-                context: "unable to create snapshot file",
-                source,
-            })
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        self.dst.flush().map_err(|source| Error::Io {
+            context: "unable to flush destination",
+            source,
+        })
     }
+}
+
+/// Reads the first `MAX_BLOCK_SIZE` bytes (at most) of `remaining`,
+/// returning them along with the part of `remaining` still to be read.
+fn read_block_data<R: Read>(mut src: R, remaining: Range<u64>) -> Result<(BlockData, Range<u64>)> {
+    let end = remaining.end.min(
+        remaining
+            .start
+            .checked_add(MAX_BLOCK_SIZE)
+            .ok_or(Error::TooLarge)?,
+    );
+    let mut data = vec![0; range_usize(remaining.start..end)?];
+    src.read_exact(&mut data).map_err(|source| Error::Io {
+        context: "unable to read record data",
+        source,
+    })?;
+    Ok((
+        BlockData {
+            range: remaining.start..end,
+            data,
+        },
+        end..remaining.end,
+    ))
+}
+
+pub struct Image<R: Read, W: Write> {
+    pub(crate) format: Format,
+    pub(crate) align_src: bool,
+    // bytes per read when `align_src`, a whole number of pages
+    pub(crate) read_size: usize,
+    pub(crate) incremental: Option<Incremental>,
+    // flush `dst` after each record acquired, so a writer that splits its
+    // output can tell where records end
+    pub(crate) flush_records: bool,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) progress: Option<Tracker>,
+    pub(crate) on_read_error: OnReadError,
+    // memory that could not be read, when `on_read_error` allows it
+    pub(crate) unreadable: Vec<Range<u64>>,
+    // stop before the next chunk once cancelled
+    #[cfg(feature = "async")]
+    pub(crate) cancel: Option<CancellationToken>,
+    pub src: R,
+    pub dst: W,
+}
 
+impl<R: Read + Seek, W: Write> Image<R, W> {
     /// Open `src_filename` for reading and `dst_filename` for writing,
     /// producing an `Image` that emits the given destination `format`.
     ///
@@ -324,11 +771,20 @@ impl<R: Read + Seek, W: Write> Image<R, W> {
         dst_filename: &Path,
     ) -> Result<Image<File, File>> {
         let (src, align_src) = open_src(src_filename)?;
-        let dst = Self::open_dst(dst_filename)?;
+        let dst = open_dst(dst_filename)?;
 
         Ok(Image::<File, File> {
             format,
             align_src,
+            read_size: PAGE_SIZE,
+            incremental: None,
+            flush_records: false,
+            rate_limit: None,
+            progress: None,
+            on_read_error: OnReadError::Abort,
+            unreadable: Vec::new(),
+            #[cfg(feature = "async")]
+            cancel: None,
             src,
             dst,
         })
@@ -350,6 +806,15 @@ impl<R: Read + Seek, W: Write> Image<R, W> {
         Ok(Image::<File, W2> {
             format,
             align_src,
+            read_size: PAGE_SIZE,
+            incremental: None,
+            flush_records: false,
+            rate_limit: None,
+            progress: None,
+            on_read_error: OnReadError::Abort,
+            unreadable: Vec::new(),
+            #[cfg(feature = "async")]
+            cancel: None,
             src,
             dst,
         })
@@ -360,6 +825,11 @@ impl<R: Read + Seek, W: Write> Image<R, W> {
     /// # Errors
     /// Returns an error if writing any block fails
     pub fn write_blocks(&mut self, blocks: &[Block]) -> Result<()> {
+        if let Some(progress) = self.progress.as_mut() {
+            for block in blocks {
+                progress.add_total(range_len(block.range.clone()));
+            }
+        }
         for block in blocks {
             self.write_block(block).map_err(|e| Error::WriteBlock {
                 range: block.range.clone(),
@@ -379,121 +849,650 @@ impl<R: Read + Seek, W: Write> Image<R, W> {
                 })?;
         }
 
-        self.copy_block(block.range.clone())?;
-        Ok(())
+        match self.on_read_error {
+            OnReadError::Abort => self.copy_block(block.range.clone()),
+            OnReadError::Zero | OnReadError::Skip => {
+                self.copy_chunks(block.range.clone(), Self::copy_readable)
+            }
+        }
     }
 
-    pub fn read_header(&mut self) -> Result<Header> {
-        Header::read(&mut self.src)
+    // As `copy_if_nonzero`, reading page by page and zero-filling or
+    // skipping the pages that cannot be read.
+    fn copy_readable(&mut self, range: Range<u64>) -> Result<()> {
+        if let Some(limit) = self.rate_limit.as_mut() {
+            limit.consume(range_len(range.clone()));
+        }
+        let (buf, unreadable) = self.read_readable(&range)?;
+        self.write_readable(range, &buf, unreadable)
     }
 
-    fn write_header(&mut self, range: Range<u64>) -> Result<()> {
-        Header {
-            range,
-            format: self.format,
+    // Read the memory at `range` from the current position of the source a
+    // page at a time, returning it with the runs of pages that could not be
+    // read, which are left zero.
+    fn read_readable(&mut self, range: &Range<u64>) -> Result<(Vec<u8>, Vec<Range<u64>>)> {
+        let position = self.src.stream_position().map_err(|source| Error::Io {
+            context: "unable to find position in memory",
+            source,
+        })?;
+        let mut buf = vec![0; range_usize(range.clone())?];
+        let mut unreadable: Vec<Range<u64>> = Vec::new();
+        let mut offset = 0_u64;
+        for page in buf.chunks_mut(PAGE_SIZE) {
+            let len = u64::try_from(page.len())?;
+            let next = offset.saturating_add(len);
+            if self.src.read_exact(page).is_err() {
+                page.fill(0);
+                let bad = range.start.saturating_add(offset)..range.start.saturating_add(next);
+                match unreadable.last_mut() {
+                    Some(last) if last.end == bad.start => last.end = bad.end,
+                    _ => unreadable.push(bad),
+                }
+                // a failed read leaves the position of the source unknown
+                self.src
+                    .seek(SeekFrom::Start(position.saturating_add(next)))
+                    .map_err(|source| Error::Io {
+                        context: "unable to seek past unreadable page",
+                        source,
+                    })?;
+            }
+            offset = next;
         }
-        .write(&mut self.dst)
+        Ok((buf, unreadable))
     }
 
-    /// Copies a memory block from the source reader to the destination writer.
-    ///
-    /// Ranges larger than `MAX_BLOCK_SIZE` are split into `MAX_BLOCK_SIZE`
-    /// chunks. The chunk granularity determines how aggressively zero
-    /// regions are elided (`copy_if_nonzero`) and caps the per-chunk
-    /// in-memory buffer.
+    /// Copies the part of the source snapshot within `range` to the
+    /// destination as new records in this `Image`'s format.
     ///
-    /// This applies to both v1 (`LiME`) and v2 (AVML compressed) output. For
-    /// v1 specifically, a single iomem range larger than `MAX_BLOCK_SIZE`
-    /// now produces multiple `LiME` records in the output instead of one.
-    /// The `LiME` format is a sequence of records walked in order, and
-    /// existing v1 output already contains gaps between iomem ranges, so
-    /// readers that handle the existing inter-range gaps will handle the
-    /// new intra-range gaps the same way. Tools that assume a strict
-    /// one-record-per-iomem-range mapping will see a behavior change.
+    /// Only records overlapping `range` are decoded; the rest are skipped
+    /// by seeking. Records are expected in ascending address order, as
+    /// `acquire` writes them, so the walk stops at the first record that
+    /// starts past the end of `range`.
     ///
     /// # Errors
     /// Returns an error if:
-    /// - Reading from the source fails
-    /// - Writing to the destination fails
-    /// - Size conversion from u64 to usize fails
-    pub fn copy_block(&mut self, range: Range<u64>) -> Result<()>
-    where
-        R: Read,
-        W: Write,
-    {
-        let mut start = range.start;
-        while start < range.end {
-            let end = range
-                .end
-                .min(start.checked_add(MAX_BLOCK_SIZE).ok_or(Error::TooLarge)?);
-            self.copy_if_nonzero(start..end)?;
-            start = end;
-        }
-        Ok(())
+    /// - `range` is empty or inverted
+    /// - A record is truncated or invalid
+    /// - Part of `range` is not covered by any record and `gaps` is
+    ///   [`OnGap::Error`]
+    /// - Reading from the source or writing to the destination fails
+    pub fn extract(&mut self, range: Range<u64>, gaps: OnGap) -> Result<()> {
+        self.extract_with(range, gaps, Some(self.format))
     }
 
-    // read the entire block into memory, and only write it if it's not empty.
-    //
-    // Caller (`copy_block`) guarantees `range_len(range) <= MAX_BLOCK_SIZE`,
-    // which bounds the in-memory allocation.
-    fn copy_if_nonzero(&mut self, range: Range<u64>) -> Result<()> {
-        let size = range_usize(range.clone())?;
-
-        // read the entire block into memory, but still read page by page
-        let mut buf = Cursor::new(vec![0; size]);
-        copy(size, self.align_src, &mut self.src, &mut buf)?;
-        let buf = buf.into_inner();
+    /// Copies the part of the source snapshot within `range` to the
+    /// destination as raw memory, so that byte `n` of the output is the
+    /// byte at physical address `range.start + n`.
+    ///
+    /// # Errors
+    /// Returns an error in the same cases as [`Image::extract`].
+    pub fn extract_raw(&mut self, range: Range<u64>, gaps: OnGap) -> Result<()> {
+        self.extract_with(range, gaps, None)
+    }
 
-        // if the entire block is zero, we can skip it
-        if buf.iter().all(|x| x == &0) {
-            return Ok(());
+    // `format` of `None` writes raw memory rather than records.
+    fn extract_with(
+        &mut self,
+        range: Range<u64>,
+        gaps: OnGap,
+        format: Option<Format>,
+    ) -> Result<()> {
+        if range.start >= range.end {
+            return Err(Error::InvalidRange { range });
         }
 
-        self.write_header(range.clone())?;
-        match self.format {
-            Format::Lime => {
-                self.dst.write_all(&buf).map_err(|source| Error::Io {
-                    context: "unable to write non-zero block",
-                    source,
-                })?;
+        // everything below `covered` has already been written (or filled)
+        let mut covered = range.start;
+        while let Some(header) = Header::read_next(&mut self.src)? {
+            if header.range.start >= range.end {
+                break;
             }
-            Format::AvmlCompressed => {
-                let mut encoder = SnapCountWriter::new(&mut self.dst);
-                encoder.write_all(&buf).map_err(|source| Error::Io {
-                    context: "unable to write compressed block",
-                    source,
-                })?;
-                encoder.finalize().map_err(|source| Error::Io {
-                    context: "unable to finalize compressed block",
-                    source,
+            let window = header.range.start.max(covered)..header.range.end.min(range.end);
+            if window.start >= window.end {
+                self.skip_record(&header)?;
+                continue;
+            }
+            self.fill_gap(covered..window.start, gaps, format)?;
+            self.extract_record(&header, window.clone(), format)?;
+            covered = window.end;
+        }
+        self.fill_gap(covered..range.end, gaps, format)
+    }
+
+    fn fill_gap(&mut self, gap: Range<u64>, gaps: OnGap, format: Option<Format>) -> Result<()> {
+        if gap.start >= gap.end {
+            return Ok(());
+        }
+        match (gaps, format) {
+            (OnGap::Error, _) => Err(Error::Gap { range: gap }),
+            (OnGap::Zero, Some(_)) => Ok(()),
+            (OnGap::Zero, None) => {
+                io_copy(&mut repeat(0).take(range_len(gap)), &mut self.dst).map_err(|source| {
+                    Error::Io {
+                        context: "unable to write padding bytes",
+                        source,
+                    }
                 })?;
+                Ok(())
             }
         }
-        Ok(())
     }
 
-    pub fn convert_block(&mut self) -> Result<()> {
-        let header = self.read_header()?;
+    fn extract_record(
+        &mut self,
+        header: &Header,
+        window: Range<u64>,
+        format: Option<Format>,
+    ) -> Result<()> {
+        let before = window.start.saturating_sub(header.range.start);
+        let after = header.range.end.saturating_sub(window.end);
         match header.format {
             Format::Lime => {
-                self.copy_block(header.range)?;
+                seek_forward(&mut self.src, before)?;
+                copy_window(&mut self.src, &mut self.dst, format, window)?;
+                seek_forward(&mut self.src, after)?;
             }
             Format::AvmlCompressed => {
-                self.write_header(header.range.clone())?;
                 {
-                    let size = range_len(header.range.clone());
-                    let mut decoder = FrameDecoder::new(&mut self.src).take(size);
-                    std::io::copy(&mut decoder, &mut self.dst).map_err(|source| Error::Io {
-                        context: "unable to copy compressed data",
-                        source,
-                    })?;
+                    let mut decoder =
+                        FrameDecoder::new(&mut self.src).take(range_len(header.range.clone()));
+                    discard(&mut decoder, before)?;
+                    copy_window(&mut decoder, &mut self.dst, format, window)?;
+                    discard(&mut decoder, after)?;
                 }
-                self.src
-                    .seek(SeekFrom::Current(8))
-                    .map_err(|source| Error::Io {
-                        context: "unable to seek passed compressed len",
+                read_compressed_len(&mut self.src)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_record(&mut self, header: &Header) -> Result<()> {
+        match header.format {
+            Format::Lime => seek_forward(&mut self.src, range_len(header.range.clone())),
+            Format::AvmlCompressed => {
+                skip_compressed_frame(&mut self.src, range_len(header.range.clone()))
+            }
+        }
+    }
+}
+
+impl<R: Read, W: Write> Image<R, W> {
+    /// Writes multiple memory blocks to the destination, as
+    /// [`Image::write_blocks`] does, reading each block from `source` at its
+    /// offset on `readers` threads at once rather than from `src`.
+    ///
+    /// Blocks are read in chunks of at most `MAX_BLOCK_SIZE` and written in
+    /// ascending order, so the output is the same as reading sequentially.
+    /// At most two chunks per reader are held in memory at a time.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - Reading any block fails, unless `on_read_error` allows it
+    /// - Writing any block fails
+    pub fn write_blocks_parallel(
+        &mut self,
+        source: &dyn MemorySource,
+        blocks: &[Block],
+        readers: NonZeroUsize,
+    ) -> Result<()> {
+        if let Some(progress) = self.progress.as_mut() {
+            for block in blocks {
+                progress.add_total(range_len(block.range.clone()));
+            }
+        }
+
+        // (range of the block, offset in the source, memory range)
+        let mut chunks = Vec::new();
+        for block in blocks {
+            let mut start = block.range.start;
+            while start < block.range.end {
+                let end = block
+                    .range
+                    .end
+                    .min(start.checked_add(MAX_BLOCK_SIZE).ok_or(Error::TooLarge)?);
+                let offset = block
+                    .offset
+                    .checked_add(start.saturating_sub(block.range.start))
+                    .ok_or(Error::TooLarge)?;
+                chunks.push((&block.range, offset, start..end));
+                start = end;
+            }
+        }
+
+        // a read size lowered by one reader is used by all of them
+        let learned = AtomicUsize::new(self.read_size);
+        let read_size = self.align_src.then_some(&learned);
+        let on_read_error = self.on_read_error;
+        let result = read_in_order(
+            vec![source; readers.get()],
+            &chunks,
+            readers.get().saturating_mul(2),
+            |src, chunk| {
+                let (_, offset, ref range) = *chunk;
+                read_at(*src, offset, range, read_size, on_read_error)
+            },
+            |chunk, read| {
+                let (block, _, ref range) = *chunk;
+                self.check_cancelled()
+                    .and(read)
+                    .and_then(|(buf, unreadable)| {
+                        if let Some(limit) = self.rate_limit.as_mut() {
+                            limit.consume(range_len(range.clone()));
+                        }
+                        self.write_readable(range.clone(), &buf, unreadable)
+                    })
+                    .map_err(|e| Error::WriteBlock {
+                        range: block.clone(),
+                        source: Box::new(e),
+                    })
+            },
+        );
+        self.read_size = learned.into_inner();
+        result
+    }
+}
+
+// Read the memory at `range` from `offset` in `src`, in reads of
+// `read_size` bytes if given, or a page at a time if read errors are
+// tolerated. Returns the memory with the runs of pages that could not be
+// read, which are left zero.
+fn read_at(
+    src: &dyn MemorySource,
+    offset: u64,
+    range: &Range<u64>,
+    read_size: Option<&AtomicUsize>,
+    on_read_error: OnReadError,
+) -> Result<(Vec<u8>, Vec<Range<u64>>)> {
+    let mut buf = vec![0; range_usize(range.clone())?];
+    let mut unreadable: Vec<Range<u64>> = Vec::new();
+    if on_read_error == OnReadError::Abort {
+        let result = match read_size {
+            Some(read_size) => read_pages(&mut buf, read_size, |part, position| {
+                let position = u64::try_from(position).map_err(std::io::Error::other)?;
+                src.read_at(offset.saturating_add(position), part)
+            }),
+            None => read_exact_at(src, offset, &mut buf),
+        };
+        result.map_err(|source| Error::Io {
+            context: "unable to read memory pages",
+            source,
+        })?;
+        return Ok((buf, unreadable));
+    }
+
+    let mut position = 0_u64;
+    for page in buf.chunks_mut(PAGE_SIZE) {
+        let next = position.saturating_add(u64::try_from(page.len())?);
+        if read_exact_at(src, offset.saturating_add(position), page).is_err() {
+            page.fill(0);
+            let bad = range.start.saturating_add(position)..range.start.saturating_add(next);
+            match unreadable.last_mut() {
+                Some(last) if last.end == bad.start => last.end = bad.end,
+                _ => unreadable.push(bad),
+            }
+        }
+        position = next;
+    }
+    Ok((buf, unreadable))
+}
+
+impl<R: Read, W: Write> Image<R, W> {
+    /// Build an `Image` over arbitrary streams.
+    ///
+    /// The source only needs to implement [`Seek`] for
+    /// [`Image::write_blocks`]; format conversion works on plain
+    /// [`Read`] streams such as stdin.
+    pub fn from_streams(format: Format, src: R, dst: W) -> Self {
+        Self {
+            format,
+            align_src: false,
+            read_size: PAGE_SIZE,
+            incremental: None,
+            flush_records: false,
+            rate_limit: None,
+            progress: None,
+            on_read_error: OnReadError::Abort,
+            unreadable: Vec::new(),
+            #[cfg(feature = "async")]
+            cancel: None,
+            src,
+            dst,
+        }
+    }
+
+    /// Report progress on `operation` to `reporter` as records are read and
+    /// written. The report marking the operation done is sent by
+    /// [`Image::finish`].
+    #[must_use]
+    pub fn report_progress(self, reporter: Arc<dyn Reporter>, operation: Operation) -> Self {
+        Self {
+            progress: Some(Tracker::new(Some(reporter), operation)),
+            ..self
+        }
+    }
+
+    /// Read the source in page-aligned reads of `read_size` bytes, rounded
+    /// down to whole pages, as memory devices require.
+    ///
+    /// This turns on page-aligned reads even for a source, such as a regular
+    /// file, that would otherwise be read as a stream.
+    #[must_use]
+    pub fn read_size(self, read_size: NonZeroUsize) -> Self {
+        Self {
+            align_src: true,
+            read_size: whole_pages(read_size),
+            ..self
+        }
+    }
+
+    /// Record that the memory at `range` was processed, writing `written`
+    /// bytes to the destination, for callers that write to `dst` directly.
+    pub fn record_progress(&mut self, range: Range<u64>, written: u64) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.read(range, written);
+        }
+    }
+
+    /// Replace the destination with `f` applied to it, such as to wrap it.
+    pub(crate) fn map_dst<W2: Write, F: FnOnce(W) -> W2>(self, f: F) -> Image<R, W2> {
+        Image {
+            format: self.format,
+            align_src: self.align_src,
+            read_size: self.read_size,
+            incremental: self.incremental,
+            flush_records: self.flush_records,
+            rate_limit: self.rate_limit,
+            progress: self.progress,
+            on_read_error: self.on_read_error,
+            unreadable: self.unreadable,
+            #[cfg(feature = "async")]
+            cancel: self.cancel,
+            src: self.src,
+            dst: f(self.dst),
+        }
+    }
+
+    /// The destination format this `Image` writes.
+    #[must_use]
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn read_header(&mut self) -> Result<Header> {
+        Header::read(&mut self.src)
+    }
+
+    fn write_header(&mut self, range: Range<u64>) -> Result<()> {
+        Header {
+            range,
+            format: self.format,
+        }
+        .write(&mut self.dst)
+    }
+
+    // returns the number of bytes written, which is 0 if the block is
+    // all-zero
+    fn write_if_nonzero(&mut self, range: Range<u64>, buf: &[u8]) -> Result<u64> {
+        if buf.iter().all(|x| x == &0) {
+            return Ok(0);
+        }
+        self.write_record(range, buf)
+    }
+
+    fn write_record(&mut self, range: Range<u64>, buf: &[u8]) -> Result<u64> {
+        let mut dst = Counter::new(&mut self.dst);
+        write_record(&mut dst, self.format, range, buf)?;
+        let written = u64::try_from(dst.count())?;
+        self.end_record()?;
+        Ok(written)
+    }
+
+    /// Copies a memory block from the source reader to the destination writer.
+    ///
+    /// Ranges larger than `MAX_BLOCK_SIZE` are split into `MAX_BLOCK_SIZE`
+    /// chunks. The chunk granularity determines how aggressively zero
+    /// regions are elided (`copy_if_nonzero`) and caps the per-chunk
+    /// in-memory buffer.
+    ///
+    /// This applies to both v1 (`LiME`) and v2 (AVML compressed) output. For
+    /// v1 specifically, a single iomem range larger than `MAX_BLOCK_SIZE`
+    /// now produces multiple `LiME` records in the output instead of one.
+    /// The `LiME` format is a sequence of records walked in order, and
+    /// existing v1 output already contains gaps between iomem ranges, so
+    /// readers that handle the existing inter-range gaps will handle the
+    /// new intra-range gaps the same way. Tools that assume a strict
+    /// one-record-per-iomem-range mapping will see a behavior change.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - Reading from the source fails
+    /// - Writing to the destination fails
+    /// - Size conversion from u64 to usize fails
+    pub fn copy_block(&mut self, range: Range<u64>) -> Result<()>
+    where
+        R: Read,
+        W: Write,
+    {
+        self.copy_chunks(range, Self::copy_if_nonzero)
+    }
+
+    // pass `range` to `copy` in chunks of at most `MAX_BLOCK_SIZE`
+    fn copy_chunks(
+        &mut self,
+        range: Range<u64>,
+        copy: fn(&mut Self, Range<u64>) -> Result<()>,
+    ) -> Result<()> {
+        let mut start = range.start;
+        while start < range.end {
+            self.check_cancelled()?;
+            let end = range
+                .end
+                .min(start.checked_add(MAX_BLOCK_SIZE).ok_or(Error::TooLarge)?);
+            copy(self, start..end)?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    // read the entire block into memory, and only write it if it's not empty.
+    //
+    // Caller (`copy_block`) guarantees `range_len(range) <= MAX_BLOCK_SIZE`,
+    // which bounds the in-memory allocation.
+    fn copy_if_nonzero(&mut self, range: Range<u64>) -> Result<()> {
+        let size = range_usize(range.clone())?;
+        if let Some(limit) = self.rate_limit.as_mut() {
+            limit.consume(range_len(range.clone()));
+        }
+
+        // read the entire block into memory, but still read page by page
+        let mut buf = Cursor::new(vec![0; size]);
+        copy(
+            size,
+            self.align_src,
+            &mut self.read_size,
+            &mut self.src,
+            &mut buf,
+        )?;
+        let buf = buf.into_inner();
+
+        let written = self.write_memory(range.clone(), &buf, &[])?;
+        self.record_progress(range, written);
+        Ok(())
+    }
+
+    // write `buf`, the memory at `range`, as records, leaving out pages
+    // that are all zero or unchanged since the baseline, which includes
+    // the `unreadable` pages it holds. Returns the number of bytes written.
+    fn write_memory(
+        &mut self,
+        range: Range<u64>,
+        buf: &[u8],
+        unreadable: &[Range<u64>],
+    ) -> Result<u64> {
+        if let Some(incremental) = self.incremental.as_mut() {
+            let mut written = 0_u64;
+            for changed in incremental.changed(&range, buf, unreadable)? {
+                let data = sub_slice(buf, range.start, &changed);
+                written = written.saturating_add(self.write_record(changed, data)?);
+            }
+            Ok(written)
+        } else {
+            self.write_if_nonzero(range, buf)
+        }
+    }
+
+    // write `buf`, the memory at `range`, zero-filled or with the pages of
+    // `unreadable` left out as `on_read_error` allows. An incremental
+    // snapshot keeps the baseline of unreadable pages either way.
+    fn write_readable(
+        &mut self,
+        range: Range<u64>,
+        buf: &[u8],
+        unreadable: Vec<Range<u64>>,
+    ) -> Result<()> {
+        let written = if self.on_read_error == OnReadError::Skip && self.incremental.is_none() {
+            let mut written = 0_u64;
+            for readable in complement(&range, &unreadable) {
+                let data = sub_slice(buf, range.start, &readable);
+                written = written.saturating_add(self.write_memory(readable, data, &[])?);
+            }
+            written
+        } else {
+            self.write_memory(range.clone(), buf, &unreadable)?
+        };
+        self.record_progress(range, written);
+
+        for bad in unreadable {
+            match self.unreadable.last_mut() {
+                Some(last) if last.end == bad.start => last.end = bad.end,
+                _ => self.unreadable.push(bad),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        #[cfg(feature = "async")]
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Result<()> {
+        if !self.flush_records {
+            return Ok(());
+        }
+        self.dst.flush().map_err(|source| Error::Io {
+            context: "unable to write record",
+            source,
+        })
+    }
+
+    /// Completes any incremental acquisition state, flushing its manifest,
+    /// and reports the operation as done.
+    ///
+    /// # Errors
+    /// Returns an error if the incremental manifest cannot be written.
+    pub fn finish(&mut self) -> Result<()> {
+        self.incremental
+            .take()
+            .map_or(Ok(()), Incremental::finish)?;
+        if let Some(progress) = self.progress.as_mut() {
+            progress.finish();
+        }
+        Ok(())
+    }
+
+    /// Memory that could not be read and was zero-filled or skipped, as
+    /// allowed by [`OnReadError`].
+    #[must_use]
+    pub fn unreadable(&self) -> &[Range<u64>] {
+        &self.unreadable
+    }
+
+    /// Progress so far, when tracked.
+    pub(crate) fn progress(&self) -> Option<&Progress> {
+        self.progress.as_ref().map(Tracker::progress)
+    }
+
+    /// Reads up to `MAX_BLOCK_SIZE` bytes of raw memory from the source and
+    /// writes them as a block starting at physical address `start`, eliding
+    /// the block if it is entirely zero.
+    ///
+    /// Returns the number of bytes consumed from the source. A return value
+    /// of `0` means the source is exhausted. Unlike [`Image::copy_block`],
+    /// the length of the source does not need to be known up front.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - Reading from the source fails
+    /// - Writing to the destination fails
+    pub fn copy_raw_block(&mut self, start: u64) -> Result<u64> {
+        let mut buf = Vec::new();
+        (&mut self.src)
+            .take(MAX_BLOCK_SIZE)
+            .read_to_end(&mut buf)
+            .map_err(|source| Error::Io {
+                context: "unable to read raw memory block",
+                source,
+            })?;
+        let len = u64::try_from(buf.len())?;
+        if len > 0 {
+            let end = start.checked_add(len).ok_or(Error::TooLarge)?;
+            let written = self.write_if_nonzero(start..end, &buf)?;
+            self.record_progress(start..end, written);
+        }
+        Ok(len)
+    }
+
+    /// Converts a single record from the source into the destination format.
+    ///
+    /// # Errors
+    /// Returns an error if the source does not start with a valid record,
+    /// or reading or writing the record fails.
+    pub fn convert_block(&mut self) -> Result<()> {
+        let header = self.read_header()?;
+        self.convert_record(header)
+    }
+
+    /// Converts every record in the source into the destination format.
+    ///
+    /// The source is read strictly sequentially until it is exhausted at a
+    /// record boundary, so it need not be seekable or of known length.
+    ///
+    /// # Errors
+    /// Returns an error if any record is truncated or invalid, or reading or
+    /// writing a record fails.
+    pub fn convert_blocks(&mut self) -> Result<()> {
+        while let Some(header) = Header::read_next(&mut self.src)? {
+            self.convert_record(header)?;
+        }
+        Ok(())
+    }
+
+    fn convert_record(&mut self, header: Header) -> Result<()> {
+        match header.format {
+            Format::Lime => {
+                self.copy_block(header.range)?;
+            }
+            Format::AvmlCompressed => {
+                self.write_header(header.range.clone())?;
+                let copied = {
+                    let size = range_len(header.range.clone());
+                    let mut decoder = FrameDecoder::new(&mut self.src).take(size);
+                    std::io::copy(&mut decoder, &mut self.dst).map_err(|source| Error::Io {
+                        context: "unable to copy compressed data",
                         source,
-                    })?;
+                    })?
+                };
+                read_compressed_len(&mut self.src)?;
+                let written = u64::try_from(HEADER_LEN)?.saturating_add(copied);
+                self.record_progress(header.range, written);
             }
         }
 
@@ -501,6 +1500,385 @@ impl<R: Read + Seek, W: Write> Image<R, W> {
     }
 }
 
+fn write_if_nonzero<W: Write>(dst: W, format: Format, range: Range<u64>, buf: &[u8]) -> Result<()> {
+    // if the entire block is zero, we can skip it
+    if buf.iter().all(|x| x == &0) {
+        return Ok(());
+    }
+
+    write_record(dst, format, range, buf)
+}
+
+/// Writes `buf` as a single record for `range` in `format`.
+///
+/// Unlike [`Image::copy_block`], all-zero blocks are written rather than
+/// elided, and `buf` is not split into `MAX_BLOCK_SIZE` chunks.
+///
+/// # Errors
+/// Returns an error if:
+/// - `range` is empty, or its length does not match `buf`
+/// - Writing to the destination fails
+pub fn write_record<W: Write>(
+    mut dst: W,
+    format: Format,
+    range: Range<u64>,
+    buf: &[u8],
+) -> Result<()> {
+    if range_usize(range.clone())? != buf.len() {
+        return Err(Error::InvalidRange { range });
+    }
+
+    Header { range, format }.write(&mut dst)?;
+    match format {
+        Format::Lime => {
+            dst.write_all(buf).map_err(|source| Error::Io {
+                context: "unable to write non-zero block",
+                source,
+            })?;
+        }
+        Format::AvmlCompressed => {
+            let mut encoder = SnapCountWriter::new(&mut dst);
+            encoder.write_all(buf).map_err(|source| Error::Io {
+                context: "unable to write compressed block",
+                source,
+            })?;
+            encoder.finalize().map_err(|source| Error::Io {
+                context: "unable to finalize compressed block",
+                source,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds a snapshot with one record per entry of `records`, each filled
+/// with its byte, for tests.
+#[cfg(test)]
+pub(crate) fn filled_snapshot(format: Format, records: &[(Range<u64>, u8)]) -> Result<Vec<u8>> {
+    let mut dst = Vec::new();
+    for &(ref range, fill) in records {
+        write_record(
+            &mut dst,
+            format,
+            range.clone(),
+            &vec![fill; range_usize(range.clone())?],
+        )?;
+    }
+    Ok(dst)
+}
+
+/// Copies the memory at `window` from `src` to `dst` in chunks of at most
+/// `MAX_BLOCK_SIZE`, either as records in `format` or, for `None`, as raw
+/// memory.
+fn copy_window<R: Read, W: Write>(
+    mut src: R,
+    mut dst: W,
+    format: Option<Format>,
+    window: Range<u64>,
+) -> Result<()> {
+    let mut start = window.start;
+    while start < window.end {
+        let end = window
+            .end
+            .min(start.checked_add(MAX_BLOCK_SIZE).ok_or(Error::TooLarge)?);
+        let mut buf = vec![0; range_usize(start..end)?];
+        src.read_exact(&mut buf).map_err(|source| Error::Io {
+            context: "unable to read record data",
+            source,
+        })?;
+        match format {
+            Some(format) => write_if_nonzero(&mut dst, format, start..end, &buf)?,
+            None => dst.write_all(&buf).map_err(|source| Error::Io {
+                context: "unable to write raw memory",
+                source,
+            })?,
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// Reads and drops exactly `len` bytes from `src`.
+fn discard<R: Read>(src: R, len: u64) -> Result<()> {
+    let discarded = io_copy(&mut src.take(len), &mut sink()).map_err(|source| Error::Io {
+        context: "unable to read record data",
+        source,
+    })?;
+    if discarded < len {
+        return Err(Error::Io {
+            context: "unable to read record data",
+            source: ErrorKind::UnexpectedEof.into(),
+        });
+    }
+    Ok(())
+}
+
+// `seek_relative` rather than `seek`, so a `BufReader` keeps its buffer when
+// skipping within it. Seeking past the end succeeds, so the last skipped
+// byte is read rather than seeked over, which catches truncated records.
+fn seek_forward<R: Read + Seek>(mut src: R, len: u64) -> Result<()> {
+    let Some(last) = len.checked_sub(1) else {
+        return Ok(());
+    };
+    let seek_err = |source| Error::Io {
+        context: "unable to seek past record",
+        source,
+    };
+    if last > 0 {
+        src.seek_relative(i64::try_from(last)?).map_err(seek_err)?;
+    }
+    src.read_u8().map(drop).map_err(seek_err)
+}
+
+// Snappy framing format chunk types; see
+// <https://github.com/google/snappy/blob/main/framing_format.txt>
+const SNAPPY_COMPRESSED: u8 = 0x00;
+const SNAPPY_UNCOMPRESSED: u8 = 0x01;
+const SNAPPY_CRC_LEN: u64 = 4;
+const SNAPPY_STREAM_IDENTIFIER_LEN: u64 = 10;
+const SNAPPY_MAX_CHUNK_MEMORY: u64 = 0x10000;
+
+/// The most bytes the record `header` starts can take once written.
+///
+/// A `LiME` record holds its memory as is. An AVML compressed record holds a
+/// Snappy frame, whose chunks each hold at most 64KiB of memory and are
+/// stored uncompressed when compression would not make them smaller, then
+/// the frame's length.
+pub(crate) fn max_record_len(header: &Header) -> u64 {
+    let header_len = u64::try_from(HEADER_LEN).unwrap_or(u64::MAX);
+    let memory = range_len(header.range.clone());
+    let body = match header.format {
+        Format::Lime => memory,
+        Format::AvmlCompressed => {
+            let chunks = memory.div_ceil(SNAPPY_MAX_CHUNK_MEMORY);
+            SNAPPY_STREAM_IDENTIFIER_LEN
+                .saturating_add(chunks.saturating_mul(4_u64.saturating_add(SNAPPY_CRC_LEN)))
+                .saturating_add(memory)
+                .saturating_add(8)
+        }
+    };
+    header_len.saturating_add(body)
+}
+
+/// Splits a Snappy chunk header into the chunk type and the length of the
+/// chunk body that follows.
+fn parse_chunk_header(chunk_header: [u8; 4]) -> (u8, u64) {
+    let [kind, a, b, c] = chunk_header;
+    (kind, u64::from(u32::from_le_bytes([a, b, c, 0])))
+}
+
+fn read_chunk_header<R: Read>(mut src: R) -> std::io::Result<[u8; 4]> {
+    let mut chunk_header = [0; 4];
+    src.read_exact(&mut chunk_header)?;
+    Ok(chunk_header)
+}
+
+/// Reads as much of the body of a chunk of type `kind` and length `len` as
+/// needed to learn how much memory it holds, returning that length along
+/// with the number of body bytes read.
+fn read_chunk_memory_len<R: Read>(mut src: R, kind: u8, len: u64) -> std::io::Result<(u64, u64)> {
+    match kind {
+        SNAPPY_COMPRESSED => {
+            src.read_u32::<LittleEndian>()?;
+            let (memory, varint_len) = read_uvarint(&mut src)?;
+            Ok((memory, SNAPPY_CRC_LEN.saturating_add(varint_len)))
+        }
+        SNAPPY_UNCOMPRESSED => Ok((len.saturating_sub(SNAPPY_CRC_LEN), 0)),
+        // stream identifier, padding, and reserved skippable chunks carry no
+        // memory
+        _ => Ok((0, 0)),
+    }
+}
+
+fn check_compressed_len<R: Read>(src: R, expected: u64) -> Result<()> {
+    if read_compressed_len(src)? != expected {
+        return Err(Error::Io {
+            context: "compressed length does not match record",
+            source: ErrorKind::InvalidData.into(),
+        });
+    }
+    Ok(())
+}
+
+/// Skips an AVML compressed record body holding `size` bytes of memory,
+/// along with its compressed length trailer, without decompressing it.
+///
+/// The Snappy frame is walked chunk by chunk: each chunk header gives the
+/// chunk's length on disk, and the decompressed length is read from the
+/// preamble of compressed chunks. The trailer is then checked against the
+/// number of bytes skipped.
+fn skip_compressed_frame<R: Read + Seek>(mut src: R, size: u64) -> Result<()> {
+    let read_err = |source| Error::Io {
+        context: "unable to read compressed chunk header",
+        source,
+    };
+
+    let mut decoded = 0_u64;
+    let mut skipped = 0_u64;
+    while decoded < size {
+        let (kind, len) = parse_chunk_header(read_chunk_header(&mut src).map_err(read_err)?);
+        let (memory, consumed) = read_chunk_memory_len(&mut src, kind, len).map_err(read_err)?;
+        decoded = decoded.saturating_add(memory);
+        seek_forward(&mut src, len.checked_sub(consumed).ok_or(Error::TooLarge)?)?;
+        skipped = skipped.saturating_add(4).saturating_add(len);
+    }
+    check_compressed_len(&mut src, skipped)
+}
+
+/// Reads an AVML compressed record body holding `size` bytes of memory
+/// without decompressing it, returning the Snappy frame. The compressed
+/// length trailer is consumed and checked but not included.
+fn read_compressed_frame<R: Read>(mut src: R, size: u64) -> Result<Vec<u8>> {
+    let read_err = |source| Error::Io {
+        context: "unable to read compressed chunk",
+        source,
+    };
+
+    let mut frame = Vec::new();
+    let mut decoded = 0_u64;
+    while decoded < size {
+        let chunk_header = read_chunk_header(&mut src).map_err(read_err)?;
+        let (kind, len) = parse_chunk_header(chunk_header);
+        frame.extend_from_slice(&chunk_header);
+
+        let body_start = frame.len();
+        (&mut src)
+            .take(len)
+            .read_to_end(&mut frame)
+            .map_err(read_err)?;
+        let body = frame.get(body_start..).unwrap_or_default();
+        if u64::try_from(body.len())? != len {
+            return Err(read_err(ErrorKind::UnexpectedEof.into()));
+        }
+        let (memory, _) = read_chunk_memory_len(body, kind, len).map_err(read_err)?;
+        decoded = decoded.saturating_add(memory);
+    }
+    check_compressed_len(&mut src, u64::try_from(frame.len())?)?;
+    Ok(frame)
+}
+
+/// Reads a little-endian base-128 varint, as used for the decompressed
+/// length preamble of a Snappy block, returning the value and its encoded
+/// length.
+fn read_uvarint<R: Read>(mut src: R) -> std::io::Result<(u64, u64)> {
+    let mut value = 0_u64;
+    for (len, shift) in (1..=5).zip((0..35).step_by(7)) {
+        let byte = src.read_u8()?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value, len));
+        }
+    }
+    Err(ErrorKind::InvalidData.into())
+}
+
+/// Copies the body of the record described by `header`, which has already
+/// been read from `src`, to `dst` verbatim, without decoding it. Returns the
+/// number of bytes written, including the header.
+///
+/// `LiME` records are streamed. AVML compressed records are buffered in
+/// memory so their encoded length is known before anything is written; the
+/// length is passed to `before_write`, which may switch `dst` (for example,
+/// to start a new file) before the record is written.
+///
+/// # Errors
+/// Returns an error if the record is truncated or its compressed length
+/// trailer does not match, or writing to `dst` fails.
+pub(crate) fn copy_record<R, W, F>(mut src: R, header: &Header, before_write: F) -> Result<u64>
+where
+    R: Read,
+    W: Write,
+    F: FnOnce(u64) -> Result<W>,
+{
+    let header_len = u64::try_from(HEADER_LEN)?;
+    let size = range_len(header.range.clone());
+    match header.format {
+        Format::Lime => {
+            let len = header_len.checked_add(size).ok_or(Error::TooLarge)?;
+            let mut dst = before_write(len)?;
+            header.write(&mut dst)?;
+            let copied =
+                io_copy(&mut (&mut src).take(size), &mut dst).map_err(|source| Error::Io {
+                    context: "unable to copy record",
+                    source,
+                })?;
+            if copied != size {
+                return Err(Error::Io {
+                    context: "unable to copy record",
+                    source: ErrorKind::UnexpectedEof.into(),
+                });
+            }
+            Ok(len)
+        }
+        Format::AvmlCompressed => {
+            let frame = read_compressed_frame(&mut src, size)?;
+            let frame_len = u64::try_from(frame.len())?;
+            let len = header_len.saturating_add(frame_len).saturating_add(8);
+            let mut dst = before_write(len)?;
+            header.write(&mut dst)?;
+            dst.write_all(&frame)
+                .and_then(|()| dst.write_all(&frame_len.to_le_bytes()))
+                .map_err(|source| Error::Io {
+                    context: "unable to copy record",
+                    source,
+                })?;
+            Ok(len)
+        }
+    }
+}
+
+/// Reads the 8-byte little-endian compressed length that trails each
+/// AVML compressed record.
+///
+/// Readers consume the trailer rather than seeking past it so records can
+/// be walked on non-seekable streams.
+///
+/// # Errors
+/// Returns an error if the trailer cannot be read.
+pub fn read_compressed_len<R: Read>(mut src: R) -> Result<u64> {
+    src.read_u64::<LittleEndian>().map_err(|source| Error::Io {
+        context: "unable to read compressed length",
+        source,
+    })
+}
+
+/// The part of `buf`, which holds the memory starting at address `base`,
+/// that holds the memory at `range`.
+pub(crate) fn sub_slice<'a>(buf: &'a [u8], base: u64, range: &Range<u64>) -> &'a [u8] {
+    let start = usize::try_from(range.start.saturating_sub(base)).unwrap_or(usize::MAX);
+    let end = usize::try_from(range.end.saturating_sub(base)).unwrap_or(usize::MAX);
+    buf.get(start..end).unwrap_or_default()
+}
+
+/// The parts of `range` outside the sorted, non-overlapping `holes`.
+fn complement(range: &Range<u64>, holes: &[Range<u64>]) -> Vec<Range<u64>> {
+    let mut parts = Vec::new();
+    let mut start = range.start;
+    for hole in holes {
+        if hole.start > start {
+            parts.push(start..hole.start);
+        }
+        start = start.max(hole.end);
+    }
+    if start < range.end {
+        parts.push(start..range.end);
+    }
+    parts
+}
+
+/// The end of the page holding `addr`.
+pub(crate) fn page_end(addr: u64) -> Result<u64> {
+    let page_mask = u64::try_from(PAGE_SIZE)?.saturating_sub(1);
+    (addr | page_mask).checked_add(1).ok_or(Error::TooLarge)
+}
+
+/// `size` rounded down to whole pages, and at least one page.
+pub(crate) fn whole_pages(size: NonZeroUsize) -> usize {
+    size.get()
+        .saturating_sub(size.get() % PAGE_SIZE)
+        .max(PAGE_SIZE)
+}
+
 fn range_len(value: Range<u64>) -> u64 {
     value.end.saturating_sub(value.start)
 }
@@ -509,7 +1887,7 @@ fn range_usize(value: Range<u64>) -> Result<usize> {
     Ok(usize::try_from(value.end.saturating_sub(value.start))?)
 }
 
-fn open_src(src_filename: &Path) -> Result<(File, bool)> {
+pub(crate) fn open_src(src_filename: &Path) -> Result<(File, bool)> {
     let src_filename = canonicalize(src_filename).map_err(|source| Error::Io {
         context: "unable to canonicalize path",
         source,
@@ -534,9 +1912,199 @@ fn open_src(src_filename: &Path) -> Result<(File, bool)> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Format, Header, Image};
-    use core::ops::Range;
-    use std::io::Cursor;
+    use super::{
+        Block, BlockReader, Format, Header, Image, OnGap, OnReadError, detect_read_size, read_pages,
+    };
+    use crate::{
+        incremental::{Incremental, reconstruct},
+        progress::{Operation, Progress},
+        snapshot::Source,
+        source::FileSource,
+    };
+    use core::{num::NonZeroUsize, ops::Range};
+    #[cfg(target_family = "unix")]
+    use std::{
+        fs::{File, write},
+        path::Path,
+    };
+    use std::{
+        io::{Cursor, Error as IoError, Read, Result as IoResult, Seek, SeekFrom},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    // Records at 0x1000..0x3000 and 0x5000..0x9000 with a gap between them.
+    // Every byte is the low byte of its page number plus one, so no page is
+    // zero and any misplaced byte shows up in a comparison.
+    const RECORDS: [Range<u64>; 2] = [0x1000..0x3000, 0x5000..0x9000];
+
+    fn memory(range: Range<u64>) -> Vec<u8> {
+        range
+            .map(|addr| {
+                u8::try_from((addr >> 12) & 0xff)
+                    .unwrap_or_default()
+                    .wrapping_add(1)
+            })
+            .collect()
+    }
+
+    fn snapshot(format: Format) -> super::Result<Vec<u8>> {
+        let mut dst = Vec::new();
+        for range in RECORDS {
+            super::write_if_nonzero(&mut dst, format, range.clone(), &memory(range))?;
+        }
+        Ok(dst)
+    }
+
+    fn extract(
+        src: Format,
+        dst: Option<Format>,
+        range: Range<u64>,
+        gaps: OnGap,
+    ) -> super::Result<Vec<u8>> {
+        let mut image = Image::from_streams(
+            dst.unwrap_or(Format::Lime),
+            Cursor::new(snapshot(src)?),
+            Vec::new(),
+        );
+        match dst {
+            Some(_) => image.extract(range, gaps)?,
+            None => image.extract_raw(range, gaps)?,
+        }
+        Ok(image.dst)
+    }
+
+    #[test]
+    fn extract_raw_within_and_across_records() -> super::Result<()> {
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            // wholly inside the second record, so the first is skipped
+            assert_eq!(
+                extract(format, None, 0x6800..0x7100, OnGap::Error)?,
+                memory(0x6800..0x7100)
+            );
+
+            // spanning the gap, which is zero-filled
+            let mut expected = memory(0x2800..0x3000);
+            expected.extend(vec![0; 0x2000]);
+            expected.extend(memory(0x5000..0x5100));
+            assert_eq!(
+                extract(format, None, 0x2800..0x5100, OnGap::Zero)?,
+                expected
+            );
+
+            let result = extract(format, None, 0x2800..0x5100, OnGap::Error);
+            assert!(
+                matches!(&result, Err(super::Error::Gap { range }) if *range == (0x3000..0x5000)),
+                "got: {result:?}"
+            );
+        }
+        Ok(())
+    }
+
+    // Counts the seeks that reach the underlying source.
+    struct CountSeeks(Cursor<Vec<u8>>, usize);
+
+    impl Read for CountSeeks {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for CountSeeks {
+        fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+            self.1 = self.1.saturating_add(1);
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn skipping_records_keeps_the_read_buffer() -> super::Result<()> {
+        let src = std::io::BufReader::with_capacity(
+            0x10000,
+            CountSeeks(Cursor::new(snapshot(Format::Lime)?), 0),
+        );
+        let mut image = Image::from_streams(Format::Lime, src, Vec::new());
+        image.extract_raw(0x6800..0x7100, OnGap::Error)?;
+        assert_eq!(image.dst, memory(0x6800..0x7100));
+        assert_eq!(image.src.get_ref().1, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn extract_past_the_last_record_is_a_gap() -> super::Result<()> {
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            let mut expected = memory(0x8000..0x9000);
+            expected.extend(vec![0; 0x1000]);
+            assert_eq!(
+                extract(format, None, 0x8000..0xa000, OnGap::Zero)?,
+                expected
+            );
+            let result = extract(format, None, 0x8000..0xa000, OnGap::Error);
+            assert!(
+                matches!(&result, Err(super::Error::Gap { range }) if *range == (0x9000..0xa000)),
+                "got: {result:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn extract_writes_records_for_the_window_only() -> super::Result<()> {
+        for src in [Format::Lime, Format::AvmlCompressed] {
+            for dst in [Format::Lime, Format::AvmlCompressed] {
+                let extracted = extract(src, Some(dst), 0x2000..0x6000, OnGap::Zero)?;
+
+                let mut expected = Vec::new();
+                for range in [0x2000..0x3000, 0x5000..0x6000] {
+                    super::write_if_nonzero(&mut expected, dst, range.clone(), &memory(range))?;
+                }
+                assert_eq!(extracted, expected);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn skipping_a_truncated_record_fails() -> super::Result<()> {
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            let mut truncated = snapshot(format)?;
+            truncated.truncate(truncated.len().saturating_sub(0x100));
+            // the truncated record is skipped: it ends before the range
+            let mut image = Image::from_streams(format, Cursor::new(truncated), Vec::new());
+            let result = image.extract_raw(0xa000..0xb000, OnGap::Zero);
+            assert!(
+                matches!(&result, Err(super::Error::Io { source, .. }) if source.kind() == std::io::ErrorKind::UnexpectedEof),
+                "got: {result:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn skipping_a_compressed_record_checks_its_trailer() -> super::Result<()> {
+        let mut corrupt = snapshot(Format::AvmlCompressed)?;
+        let mut first = Vec::new();
+        super::write_if_nonzero(
+            &mut first,
+            Format::AvmlCompressed,
+            RECORDS[0].clone(),
+            &memory(RECORDS[0].clone()),
+        )?;
+        // bump the first record's compressed length trailer
+        if let Some(trailer) = corrupt.get_mut(first.len().saturating_sub(8)) {
+            *trailer = trailer.wrapping_add(1);
+        }
+
+        let mut image = Image::from_streams(Format::Lime, Cursor::new(corrupt), Vec::new());
+        let result = image.extract_raw(0x5000..0x6000, OnGap::Error);
+        assert!(
+            matches!(&result, Err(super::Error::Io { source, .. }) if source.kind() == std::io::ErrorKind::InvalidData),
+            "got: {result:?}"
+        );
+        Ok(())
+    }
 
     #[test]
     fn encode_header_lime() {
@@ -579,6 +2147,52 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn reports_progress_per_record() -> super::Result<()> {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let mut memory = vec![0; 0x2000];
+        memory.extend_from_slice(&[1; 0x1000]);
+        let mut image = Image::from_streams(Format::Lime, Cursor::new(memory), Vec::new())
+            .report_progress(
+                Arc::new(move |progress: &Progress| {
+                    if let Ok(mut guard) = sink.lock() {
+                        guard.push(progress.clone());
+                    }
+                }),
+                Operation::Acquire,
+            );
+        let blocks = [
+            Block {
+                offset: 0,
+                range: 0x0..0x2000,
+            },
+            Block {
+                offset: 0x2000,
+                range: 0x8000..0x9000,
+            },
+        ];
+        image.write_blocks(&blocks)?;
+        image.finish()?;
+
+        let reports = reports.lock().map(|r| r.clone()).unwrap_or_default();
+        let summary = reports
+            .iter()
+            .map(|p| (p.bytes_read, p.bytes_written, p.range.clone(), p.done))
+            .collect::<Vec<_>>();
+        // the all-zero block is read but not written
+        assert_eq!(
+            summary,
+            vec![
+                (0x2000, 0, Some(0x0..0x2000), false),
+                (0x3000, 0x1020, Some(0x8000..0x9000), false),
+                (0x3000, 0x1020, Some(0x8000..0x9000), true),
+            ]
+        );
+        assert!(reports.iter().all(|p| p.total == Some(0x3000)));
+        Ok(())
+    }
+
     #[test]
     fn write_rejects_empty_or_inverted_ranges() {
         for range in [
@@ -620,4 +2234,282 @@ mod tests {
             "got: {result:?}"
         );
     }
+
+    // Memory that fails with `EIO` when a read touches any of `bad`.
+    struct FlakyMemory {
+        memory: Cursor<Vec<u8>>,
+        bad: Vec<Range<u64>>,
+    }
+
+    impl Read for FlakyMemory {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            let start = self.memory.position();
+            let end = start.saturating_add(u64::try_from(buf.len()).unwrap_or(u64::MAX));
+            if self
+                .bad
+                .iter()
+                .any(|bad| start < bad.end && bad.start < end)
+            {
+                return Err(IoError::from_raw_os_error(libc::EIO));
+            }
+            self.memory.read(buf)
+        }
+    }
+
+    impl Seek for FlakyMemory {
+        fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+            self.memory.seek(pos)
+        }
+    }
+
+    const UNREADABLE: [Range<u64>; 2] = [0x1000..0x3000, 0x4000..0x5000];
+
+    fn acquire_flaky(on_read_error: OnReadError) -> super::Result<(Vec<u8>, Vec<Range<u64>>)> {
+        let src = FlakyMemory {
+            memory: Cursor::new(memory(0x0..0x6000)),
+            bad: UNREADABLE.to_vec(),
+        };
+        let mut image = Image::from_streams(Format::Lime, src, Vec::new());
+        image.on_read_error = on_read_error;
+        image.write_blocks(&[Block {
+            offset: 0,
+            range: 0x0..0x6000,
+        }])?;
+        Ok((image.dst.clone(), image.unreadable().to_vec()))
+    }
+
+    fn records(snapshot: Vec<u8>) -> super::Result<Vec<(Range<u64>, Vec<u8>)>> {
+        BlockReader::new(Cursor::new(snapshot))
+            .map(|block| block.map(|block| (block.range, block.data)))
+            .collect()
+    }
+
+    #[test]
+    fn read_errors_abort_by_default() {
+        assert!(matches!(
+            acquire_flaky(OnReadError::Abort),
+            Err(super::Error::WriteBlock { .. })
+        ));
+    }
+
+    #[test]
+    fn unreadable_pages_are_zeroed_or_skipped() -> super::Result<()> {
+        let (zeroed, zeroed_ranges) = acquire_flaky(OnReadError::Zero)?;
+        assert_eq!(zeroed_ranges, UNREADABLE);
+        let mut expected = memory(0x0..0x6000);
+        for bad in UNREADABLE {
+            let range = usize::try_from(bad.start)?..usize::try_from(bad.end)?;
+            expected.get_mut(range).unwrap_or_default().fill(0);
+        }
+        assert_eq!(records(zeroed)?, vec![(0x0..0x6000, expected)]);
+
+        let (skipped, skipped_ranges) = acquire_flaky(OnReadError::Skip)?;
+        assert_eq!(skipped_ranges, UNREADABLE);
+        assert_eq!(
+            records(skipped)?,
+            vec![
+                (0x0..0x1000, memory(0x0..0x1000)),
+                (0x3000..0x4000, memory(0x3000..0x4000)),
+                (0x5000..0x6000, memory(0x5000..0x6000)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_pages_keep_the_baseline() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let baseline_manifest = dir.path().join("baseline.manifest");
+        let delta_manifest = dir.path().join("delta.manifest");
+        let blocks = [Block {
+            offset: 0,
+            range: 0x0..0x6000,
+        }];
+
+        let mut baseline =
+            Image::from_streams(Format::Lime, Cursor::new(memory(0x0..0x6000)), Vec::new());
+        baseline.incremental = Incremental::open(None, Some(&baseline_manifest))?;
+        baseline.write_blocks(&blocks)?;
+        baseline.finish()?;
+
+        // only the first page changes, and a page of it stays readable
+        let mut changed = memory(0x0..0x6000);
+        changed.get_mut(..0x2000).unwrap_or_default().fill(0xaa);
+        for on_read_error in [OnReadError::Zero, OnReadError::Skip] {
+            let src = FlakyMemory {
+                memory: Cursor::new(changed.clone()),
+                bad: UNREADABLE.to_vec(),
+            };
+            let mut delta = Image::from_streams(Format::Lime, src, Vec::new());
+            delta.on_read_error = on_read_error;
+            delta.incremental = Incremental::open(Some(&baseline_manifest), Some(&delta_manifest))?;
+            delta.write_blocks(&blocks)?;
+            delta.finish()?;
+            assert_eq!(
+                records(delta.dst.clone())?,
+                vec![(0x0..0x1000, vec![0xaa; 0x1000])]
+            );
+
+            let mut rebuilt = Vec::new();
+            reconstruct(
+                baseline.dst.as_slice(),
+                delta.dst.as_slice(),
+                std::fs::File::open(&delta_manifest)?,
+                Format::Lime,
+                &mut rebuilt,
+            )?;
+            let mut expected = memory(0x0..0x6000);
+            expected.get_mut(..0x1000).unwrap_or_default().fill(0xaa);
+            assert_eq!(records(rebuilt)?, vec![(0x0..0x6000, expected)]);
+        }
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    fn acquire_file(
+        path: &Path,
+        blocks: &[Block],
+        readers: Option<(&FileSource, NonZeroUsize)>,
+    ) -> super::Result<(Vec<u8>, Vec<Range<u64>>)> {
+        let mut image = Image::<File, Vec<u8>>::with_dst(Format::Lime, path, Vec::new())?;
+        image.on_read_error = OnReadError::Zero;
+        match readers {
+            Some((source, readers)) => image.write_blocks_parallel(source, blocks, readers)?,
+            None => image.write_blocks(blocks)?,
+        }
+        Ok((image.dst.clone(), image.unreadable().to_vec()))
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn parallel_reads_match_sequential_reads() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mem");
+        write(&path, memory(0x0..0x3_a000))?;
+
+        // the last two blocks run past the end of the file
+        let blocks = (0..8_u64)
+            .map(|i| Block {
+                offset: i.saturating_mul(0x9000),
+                range: 0x10_0000_u64.saturating_add(i.saturating_mul(0x1_0000))
+                    ..0x10_6000_u64.saturating_add(i.saturating_mul(0x1_0000)),
+            })
+            .collect::<Vec<_>>();
+        let sequential = acquire_file(&path, &blocks, None)?;
+        assert_eq!(sequential.1, [0x16_4000..0x16_6000, 0x17_0000..0x17_6000]);
+        let readers = NonZeroUsize::new(4).ok_or("zero readers")?;
+        let source = FileSource::open(Source::Raw(path.clone()), &[])?;
+        assert_eq!(
+            acquire_file(&path, &blocks, Some((&source, readers)))?,
+            sequential
+        );
+
+        let mut image = Image::<File, Vec<u8>>::with_dst(Format::Lime, &path, Vec::new())?;
+        assert!(matches!(
+            image.write_blocks_parallel(&source, &blocks, readers),
+            Err(super::Error::WriteBlock { .. })
+        ));
+        Ok(())
+    }
+
+    // memory that only allows reads of up to a page, as with some versions
+    // of `/dev/crash`
+    struct PageReads(Cursor<Vec<u8>>);
+
+    impl Read for PageReads {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            if buf.len() > 0x1000 {
+                return Err(IoError::from_raw_os_error(libc::EINVAL));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    impl Seek for PageReads {
+        fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn short_reads_realign_to_pages() -> IoResult<()> {
+        let read_size = AtomicUsize::new(0x4000);
+        let mut offsets = Vec::new();
+        let mut buf = vec![0; 0x8000];
+        // every read stops short, 0x800 bytes into its last page
+        read_pages(&mut buf, &read_size, |part, offset| {
+            offsets.push(offset);
+            Ok(part.len().min(0x1800))
+        })?;
+        assert_eq!(
+            offsets,
+            [0x0, 0x1800, 0x2000, 0x3800, 0x4000, 0x5800, 0x6000, 0x7800]
+        );
+
+        // a read size lowered by one caller is used by the others
+        read_pages(&mut buf, &read_size, |part, _| {
+            if part.len() > 0x1000 {
+                return Err(IoError::from_raw_os_error(libc::EINVAL));
+            }
+            Ok(part.len())
+        })?;
+        assert_eq!(read_size.load(Ordering::Relaxed), 0x1000);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_page_reads_fall_back_to_single_pages() -> super::Result<()> {
+        let blocks = [
+            Block {
+                offset: 0,
+                range: 0x0..0x3_0000,
+            },
+            Block {
+                offset: 0x3_0000,
+                range: 0x10_0000..0x10_1800,
+            },
+        ];
+        let read_size = NonZeroUsize::new(0x1_0800).unwrap_or(NonZeroUsize::MIN);
+
+        let mut image =
+            Image::from_streams(Format::Lime, Cursor::new(memory(0x0..0x3_1800)), Vec::new())
+                .read_size(read_size);
+        assert_eq!(image.read_size, 0x1_0000);
+        image.write_blocks(&blocks)?;
+        assert_eq!(image.read_size, 0x1_0000);
+
+        let mut pages = Image::from_streams(
+            Format::Lime,
+            PageReads(Cursor::new(memory(0x0..0x3_1800))),
+            Vec::new(),
+        )
+        .read_size(read_size);
+        pages.write_blocks(&blocks)?;
+        assert_eq!(pages.read_size, 0x1000);
+        assert_eq!(pages.dst, image.dst);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn detects_the_largest_read_size() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mem");
+        write(&path, memory(0x0..0x2_0000))?;
+        let src = FileSource::open(Source::Raw(path), &[])?;
+
+        let block = |offset, len| Block {
+            offset,
+            range: 0x10_0000..0x10_0000_u64.saturating_add(len),
+        };
+        // no block is long enough for a 1 MiB read
+        assert_eq!(
+            detect_read_size(&src, &[block(0, 0x1000), block(0, 0x2_0000)]),
+            0x1_0000
+        );
+        // a 64 KiB read from here comes up short
+        assert_eq!(detect_read_size(&src, &[block(0x1_8000, 0x2_0000)]), 0x4000);
+        assert_eq!(detect_read_size(&src, &[block(0x1_f000, 0x1000)]), 0x1000);
+        Ok(())
+    }
 }
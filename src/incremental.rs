@@ -0,0 +1,529 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Incremental acquisition relative to a previous snapshot.
+//!
+//! An incremental capture hashes every page it reads and records the
+//! hashes in a *manifest*. Given a baseline, either the image or the
+//! manifest from an earlier run, only pages whose hash differs from the
+//! baseline are written, producing a delta snapshot. [`reconstruct`]
+//! overlays a delta on its baseline image to recover the full snapshot.
+//!
+//! A manifest starts with the `AVMLHASH` magic followed by a version and
+//! the page size as little-endian `u32`s. Then, for every block read
+//! during acquisition, it holds the block's start and end addresses as
+//! little-endian `u64`s and the first 16 bytes of the SHA-256 hash of each
+//! page in the block. Pages are aligned to the page size, so the first and
+//! last pages of a block may be partial.
+
+use crate::image::{
+    BlockData, BlockReader, BlockWriter, Error, Format, PageCursor, open_dst, sub_slice,
+};
+use byteorder::{LittleEndian, ReadBytesExt as _, WriteBytesExt as _};
+use core::ops::Range;
+use sha2::{Digest as _, Sha256};
+use std::{
+    fs::File,
+    io::{BufRead as _, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+const MAGIC: &[u8; 8] = b"AVMLHASH";
+const VERSION: u32 = 1;
+const PAGE_SIZE: u64 = 0x1000;
+const HASH_LEN: usize = 16;
+
+type PageHash = [u8; HASH_LEN];
+
+fn hash_page(data: &[u8]) -> PageHash {
+    let mut hash = [0; HASH_LEN];
+    for (h, d) in hash.iter_mut().zip(Sha256::digest(data)) {
+        *h = d;
+    }
+    hash
+}
+
+fn is_zero(data: &[u8]) -> bool {
+    data.iter().all(|x| x == &0)
+}
+
+/// Splits a range of memory at page boundaries.
+struct PageRanges {
+    next: u64,
+    end: u64,
+}
+
+impl PageRanges {
+    const fn new(range: Range<u64>) -> Self {
+        Self {
+            next: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl Iterator for PageRanges {
+    type Item = Range<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+        let end = (self.next | (PAGE_SIZE - 1))
+            .saturating_add(1)
+            .min(self.end);
+        Some(core::mem::replace(&mut self.next, end)..end)
+    }
+}
+
+/// Reads `buf` in full, or returns `false` if `src` is already exhausted.
+fn read_exact_or_eof<R: Read>(mut src: R, buf: &mut [u8]) -> std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read(buf.get_mut(filled..).unwrap_or_default()) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled = filled.saturating_add(n),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+struct ManifestWriter {
+    dst: BufWriter<File>,
+}
+
+impl ManifestWriter {
+    fn create(path: &Path) -> Result<Self> {
+        let page_size = u32::try_from(PAGE_SIZE)?;
+        let mut dst = BufWriter::new(open_dst(path)?);
+        dst.write_all(MAGIC)
+            .and_then(|()| dst.write_u32::<LittleEndian>(VERSION))
+            .and_then(|()| dst.write_u32::<LittleEndian>(page_size))
+            .map_err(|source| Error::Io {
+                context: "unable to write manifest header",
+                source,
+            })?;
+        Ok(Self { dst })
+    }
+
+    fn write(&mut self, range: &Range<u64>, hashes: &[PageHash]) -> Result<()> {
+        let write = |dst: &mut BufWriter<File>| {
+            dst.write_u64::<LittleEndian>(range.start)?;
+            dst.write_u64::<LittleEndian>(range.end)?;
+            hashes.iter().try_for_each(|hash| dst.write_all(hash))
+        };
+        write(&mut self.dst).map_err(|source| Error::Io {
+            context: "unable to write manifest",
+            source,
+        })
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.dst.flush().map_err(|source| Error::Io {
+            context: "unable to flush manifest",
+            source,
+        })
+    }
+}
+
+/// Reads the page hashes held in a manifest, in ascending address order.
+struct ManifestReader<R: Read> {
+    src: R,
+    pages: PageRanges,
+}
+
+impl<R: Read> ManifestReader<R> {
+    fn open(mut src: R) -> Result<Self> {
+        let read_err = |source| Error::Io {
+            context: "unable to read manifest header",
+            source,
+        };
+        let mut magic = [0; MAGIC.len()];
+        src.read_exact(&mut magic).map_err(read_err)?;
+        if magic != *MAGIC {
+            return Err(Error::InvalidManifest("missing magic"));
+        }
+        if src.read_u32::<LittleEndian>().map_err(read_err)? != VERSION {
+            return Err(Error::InvalidManifest("unsupported version"));
+        }
+        if u64::from(src.read_u32::<LittleEndian>().map_err(read_err)?) != PAGE_SIZE {
+            return Err(Error::InvalidManifest("unsupported page size"));
+        }
+        Ok(Self {
+            src,
+            pages: PageRanges::new(0..0),
+        })
+    }
+
+    fn read_page(&mut self) -> Result<Option<(Range<u64>, PageHash)>> {
+        let read_err = |source| Error::Io {
+            context: "unable to read manifest",
+            source,
+        };
+        loop {
+            if let Some(page) = self.pages.next() {
+                let mut hash = [0; HASH_LEN];
+                self.src.read_exact(&mut hash).map_err(read_err)?;
+                return Ok(Some((page, hash)));
+            }
+
+            let mut start = [0; 8];
+            if !read_exact_or_eof(&mut self.src, &mut start).map_err(read_err)? {
+                return Ok(None);
+            }
+            let start = u64::from_le_bytes(start);
+            let end = self.src.read_u64::<LittleEndian>().map_err(read_err)?;
+            if start >= end || start < self.pages.end {
+                return Err(Error::InvalidManifest("records out of order"));
+            }
+            self.pages = PageRanges::new(start..end);
+        }
+    }
+}
+
+/// Page hashes of a baseline, computed from an image or read from a
+/// manifest.
+enum BaselinePages {
+    Image {
+        blocks: BlockReader<BufReader<File>>,
+        block: Option<BlockData>,
+        pages: PageRanges,
+    },
+    Manifest(ManifestReader<BufReader<File>>),
+}
+
+impl BaselinePages {
+    fn next_page(&mut self) -> Result<Option<(Range<u64>, PageHash)>> {
+        match *self {
+            Self::Image {
+                ref mut blocks,
+                ref mut block,
+                ref mut pages,
+            } => loop {
+                if let Some(data) = block.as_ref()
+                    && let Some(page) = pages.next()
+                {
+                    let hash = hash_page(sub_slice(&data.data, data.range.start, &page));
+                    return Ok(Some((page, hash)));
+                }
+                let Some(next) = blocks.next().transpose()? else {
+                    return Ok(None);
+                };
+                *pages = PageRanges::new(next.range.clone());
+                *block = Some(next);
+            },
+            Self::Manifest(ref mut manifest) => manifest.read_page(),
+        }
+    }
+}
+
+/// Looks up baseline page hashes in ascending address order.
+struct Baseline {
+    pages: BaselinePages,
+    pending: Option<(Range<u64>, PageHash)>,
+}
+
+impl Baseline {
+    /// Opens a baseline, which is read as a manifest if it starts with the
+    /// manifest magic and as a snapshot image otherwise.
+    fn open(path: &Path) -> Result<Self> {
+        let mut src = BufReader::new(File::open(path).map_err(|source| Error::Io {
+            context: "unable to open baseline",
+            source,
+        })?);
+        let is_manifest = src
+            .fill_buf()
+            .map_err(|source| Error::Io {
+                context: "unable to read baseline",
+                source,
+            })?
+            .starts_with(MAGIC);
+        let pages = if is_manifest {
+            BaselinePages::Manifest(ManifestReader::open(src)?)
+        } else {
+            BaselinePages::Image {
+                blocks: BlockReader::new(src),
+                block: None,
+                pages: PageRanges::new(0..0),
+            }
+        };
+        Ok(Self {
+            pages,
+            pending: None,
+        })
+    }
+
+    /// The baseline hash of `page`, if the baseline holds exactly that page.
+    fn get(&mut self, page: &Range<u64>) -> Result<Option<PageHash>> {
+        loop {
+            if self.pending.is_none() {
+                self.pending = self.pages.next_page()?;
+            }
+            match self.pending {
+                Some((ref range, _)) if range.end <= page.start => self.pending = None,
+                Some((ref range, hash)) if range == page => return Ok(Some(hash)),
+                Some(_) | None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Per-acquisition state for incremental snapshots.
+pub(crate) struct Incremental {
+    baseline: Option<Baseline>,
+    manifest: Option<ManifestWriter>,
+}
+
+impl Incremental {
+    /// Returns `None` when neither a baseline nor a manifest is requested,
+    /// in which case acquisition proceeds as usual.
+    pub(crate) fn open(baseline: Option<&Path>, manifest: Option<&Path>) -> Result<Option<Self>> {
+        if baseline.is_none() && manifest.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            baseline: baseline.map(Baseline::open).transpose()?,
+            manifest: manifest.map(ManifestWriter::create).transpose()?,
+        }))
+    }
+
+    /// Records the hash of every page of `buf`, the memory at `range`, and
+    /// returns the runs of pages that changed since the baseline.
+    ///
+    /// Pages missing from the baseline are treated as zero, matching how
+    /// snapshots elide all-zero blocks. Pages that overlap `unreadable`
+    /// keep their baseline contents, and hash, where the baseline has them.
+    pub(crate) fn changed(
+        &mut self,
+        range: &Range<u64>,
+        buf: &[u8],
+        unreadable: &[Range<u64>],
+    ) -> Result<Vec<Range<u64>>> {
+        let mut hashes = Vec::new();
+        let mut runs: Vec<Range<u64>> = Vec::new();
+        for page in PageRanges::new(range.clone()) {
+            let data = sub_slice(buf, range.start, &page);
+            let baseline = match self.baseline.as_mut() {
+                Some(baseline) => baseline.get(&page)?,
+                None => None,
+            };
+            let kept = baseline.filter(|_| {
+                unreadable
+                    .iter()
+                    .any(|bad| bad.start < page.end && page.start < bad.end)
+            });
+            let hash = kept.unwrap_or_else(|| hash_page(data));
+            let changed = baseline.map_or_else(|| !is_zero(data), |b| b != hash);
+            if changed {
+                match runs.last_mut() {
+                    Some(last) if last.end == page.start => last.end = page.end,
+                    _ => runs.push(page),
+                }
+            }
+            hashes.push(hash);
+        }
+        if let Some(manifest) = self.manifest.as_mut() {
+            manifest.write(range, &hashes)?;
+        }
+        Ok(runs)
+    }
+
+    pub(crate) fn finish(self) -> Result<()> {
+        self.manifest.map_or(Ok(()), ManifestWriter::finish)
+    }
+}
+
+/// Rebuild the full snapshot described by `manifest` from the `baseline`
+/// image its delta was taken against and the `delta` itself, writing it to
+/// `dst` in `format`.
+///
+/// Each page listed in the manifest is taken from `delta` if present there
+/// and from `baseline` otherwise, then checked against its hash. As with
+/// acquisition, all-zero pages are elided. To apply a chain of deltas,
+/// reconstruct each in turn, using the previous result as the baseline.
+///
+/// # Errors
+/// Returns an error if:
+/// - Any input cannot be read or decoded
+/// - A reconstructed page does not match its hash in the manifest
+/// - Writing to `dst` fails
+pub fn reconstruct<B: Read, D: Read, M: Read, W: Write>(
+    baseline: B,
+    delta: D,
+    manifest: M,
+    format: Format,
+    dst: W,
+) -> Result<()> {
+    let mut baseline = PageCursor::new(baseline);
+    let mut delta = PageCursor::new(delta);
+    let mut manifest = ManifestReader::open(manifest)?;
+    let mut dst = BlockWriter::new(dst, format);
+    let mut zero = Vec::new();
+
+    while let Some((page, hash)) = manifest.read_page()? {
+        let data = if let Some(data) = delta.read(&page)? {
+            data
+        } else if let Some(data) = baseline.read(&page)? {
+            data
+        } else {
+            zero.resize(usize::try_from(page.end.saturating_sub(page.start))?, 0);
+            &zero
+        };
+        if hash_page(data) != hash {
+            return Err(Error::ManifestMismatch { range: page });
+        }
+        if !is_zero(data) {
+            dst.push(page, data)?;
+        }
+    }
+    dst.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Incremental, reconstruct};
+    use crate::image::{Block, BlockData, BlockReader, Error, Format, Image};
+    use core::ops::Range;
+    use std::{fs::File, io::Cursor, path::Path};
+    use tempfile::tempdir;
+
+    // Two ranges of raw memory with a hole between them.
+    const RANGES: [Range<u64>; 2] = [0x0..0x8000, 0xc000..0x10000];
+
+    fn memory(seed: u8) -> Vec<u8> {
+        (0..0x10000_u32)
+            .map(|i| u8::try_from(i >> 12).unwrap_or_default().wrapping_add(seed))
+            .collect()
+    }
+
+    fn acquire(
+        raw: &[u8],
+        baseline: Option<&Path>,
+        manifest: Option<&Path>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut image = Image::from_streams(Format::AvmlCompressed, Cursor::new(raw), Vec::new());
+        image.incremental = Incremental::open(baseline, manifest)?;
+        image.write_blocks(&blocks_at(&RANGES))?;
+        image.finish()?;
+        Ok(image.dst)
+    }
+
+    fn blocks_at(ranges: &[Range<u64>]) -> Vec<Block> {
+        ranges
+            .iter()
+            .map(|range| Block {
+                offset: range.start,
+                range: range.clone(),
+            })
+            .collect()
+    }
+
+    fn range_usize(range: &Range<u64>) -> Range<usize> {
+        usize::try_from(range.start).unwrap_or_default()
+            ..usize::try_from(range.end).unwrap_or_default()
+    }
+
+    fn blocks(snapshot: &[u8]) -> Result<Vec<BlockData>, Error> {
+        BlockReader::new(snapshot).collect()
+    }
+
+    #[test]
+    fn delta_holds_changed_pages_and_reconstructs() -> Result<(), Error> {
+        let dir = tempdir().map_err(|source| Error::Io {
+            context: "unable to create temporary directory",
+            source,
+        })?;
+        let first_image = dir.path().join("first.lime");
+        let first_manifest = dir.path().join("first.manifest");
+        let second_manifest = dir.path().join("second.manifest");
+
+        let old = memory(1);
+        let full = acquire(&old, None, Some(&first_manifest))?;
+        std::fs::write(&first_image, &full).map_err(|source| Error::Io {
+            context: "unable to write baseline",
+            source,
+        })?;
+
+        // page 0x2000 changes and page 0xd000 becomes zero
+        let mut new = old.clone();
+        if let Some(page) = new.get_mut(0x2000..0x3000) {
+            page.fill(0xaa);
+        }
+        if let Some(page) = new.get_mut(0xd000..0xe000) {
+            page.fill(0);
+        }
+
+        let changed = vec![
+            BlockData {
+                range: 0x2000..0x3000,
+                data: vec![0xaa; 0x1000],
+            },
+            BlockData {
+                range: 0xd000..0xe000,
+                data: vec![0; 0x1000],
+            },
+        ];
+        let from_image = acquire(&new, Some(&first_image), Some(&second_manifest))?;
+        assert_eq!(blocks(&from_image)?, changed);
+        let from_manifest = acquire(&new, Some(&first_manifest), None)?;
+        assert_eq!(blocks(&from_manifest)?, changed);
+
+        let manifest = File::open(&second_manifest).map_err(|source| Error::Io {
+            context: "unable to open manifest",
+            source,
+        })?;
+        let mut rebuilt = Vec::new();
+        reconstruct(
+            full.as_slice(),
+            from_image.as_slice(),
+            manifest,
+            Format::Lime,
+            &mut rebuilt,
+        )?;
+
+        // the page that became zero is elided
+        let expected: Vec<BlockData> = [0x0..0x8000, 0xc000..0xd000, 0xe000..0x10000]
+            .into_iter()
+            .map(|range| BlockData {
+                data: new.get(range_usize(&range)).unwrap_or_default().to_vec(),
+                range,
+            })
+            .collect();
+        assert_eq!(blocks(&rebuilt)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn reconstruct_rejects_the_wrong_baseline() -> Result<(), Error> {
+        let dir = tempdir().map_err(|source| Error::Io {
+            context: "unable to create temporary directory",
+            source,
+        })?;
+        let manifest_path = dir.path().join("manifest");
+
+        acquire(&memory(1), None, Some(&manifest_path))?;
+        let other = acquire(&memory(2), None, None)?;
+        let manifest = File::open(&manifest_path).map_err(|source| Error::Io {
+            context: "unable to open manifest",
+            source,
+        })?;
+
+        let result = reconstruct(
+            other.as_slice(),
+            [].as_slice(),
+            manifest,
+            Format::Lime,
+            Vec::new(),
+        );
+        assert!(
+            matches!(&result, Err(Error::ManifestMismatch { range }) if *range == (0x0..0x1000)),
+            "got: {result:?}"
+        );
+        Ok(())
+    }
+}
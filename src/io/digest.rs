@@ -0,0 +1,70 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use core::fmt::Write as _;
+use sha2::{Digest as _, Sha256};
+use std::io::{Result, Write};
+
+/// Write implementation that computes the SHA-256 of the bytes successfully
+/// written.
+pub struct DigestWriter<W> {
+    inner: W,
+    hash: Sha256,
+}
+
+impl<W> DigestWriter<W> {
+    /// Creates a new `DigestWriter` wrapping the given writer.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hash: Sha256::new(),
+        }
+    }
+
+    /// Consumes this `DigestWriter`, returning the underlying writer and the
+    /// hex-encoded SHA-256 of everything written to it.
+    pub fn finish(self) -> (W, String) {
+        (self.inner, to_hex(&self.hash.finalize()))
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.hash.update(buf.get(..count).unwrap_or_default());
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Lowercase hex encoding, as printed by `sha256sum`.
+#[must_use]
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn hashes_what_is_written() -> Result<()> {
+        let mut writer = DigestWriter::new(Cursor::new(vec![]));
+        writer.write_all(b"hello world")?;
+        let (inner, digest) = writer.finish();
+        assert_eq!(inner.into_inner(), b"hello world");
+        assert_eq!(
+            digest,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        Ok(())
+    }
+}
@@ -1,2 +1,4 @@
 pub mod counter;
+pub mod digest;
+pub mod parallel;
 pub mod snappy;
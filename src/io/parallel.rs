@@ -0,0 +1,158 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex, PoisonError, mpsc::channel},
+    thread,
+};
+
+struct State {
+    // the next item for a reader to take
+    claimed: usize,
+    // the number of items passed to `write` so far
+    written: usize,
+    stopped: bool,
+}
+
+/// Reads each of `items` on one of a thread per entry of `readers`, passing
+/// what was read to `write` in the order of `items` on the calling thread.
+///
+/// Readers run at most `window` items ahead of `write`, which bounds how
+/// many items are held in memory at once. The first error from `write`
+/// stops the readers and is returned.
+pub fn read_in_order<S, I, T, E, R, F>(
+    readers: Vec<S>,
+    items: &[I],
+    window: usize,
+    read: R,
+    mut write: F,
+) -> Result<(), E>
+where
+    S: Send,
+    I: Sync,
+    T: Send,
+    R: Fn(&mut S, &I) -> T + Sync,
+    F: FnMut(&I, T) -> Result<(), E>,
+{
+    let count = items.len();
+    let window = window.max(1);
+    let state = Mutex::new(State {
+        claimed: 0,
+        written: 0,
+        stopped: false,
+    });
+    let advanced = Condvar::new();
+    let (tx, rx) = channel();
+
+    thread::scope(|scope| {
+        let (state, advanced, read) = (&state, &advanced, &read);
+        for mut reader in readers {
+            let tx = tx.clone();
+            scope.spawn(move || {
+                loop {
+                    let (index, item) = {
+                        let mut guard = state.lock().unwrap_or_else(PoisonError::into_inner);
+                        while !guard.stopped
+                            && guard.claimed < count
+                            && guard.claimed >= guard.written.saturating_add(window)
+                        {
+                            guard = advanced.wait(guard).unwrap_or_else(PoisonError::into_inner);
+                        }
+                        if guard.stopped {
+                            return;
+                        }
+                        let index = guard.claimed;
+                        let Some(item) = items.get(index) else {
+                            return;
+                        };
+                        guard.claimed = index.saturating_add(1);
+                        (index, item)
+                    };
+                    if tx.send((index, read(&mut reader, item))).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0_usize;
+        let mut result = Ok(());
+        'received: for (index, received) in &rx {
+            pending.insert(index, received);
+            while let Some(data) = pending.remove(&next) {
+                let Some(item) = items.get(next) else {
+                    break 'received;
+                };
+                if let Err(e) = write(item, data) {
+                    result = Err(e);
+                    break 'received;
+                }
+                next = next.saturating_add(1);
+                state.lock().unwrap_or_else(PoisonError::into_inner).written = next;
+                advanced.notify_all();
+            }
+        }
+
+        state.lock().unwrap_or_else(PoisonError::into_inner).stopped = true;
+        advanced.notify_all();
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_in_order;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::sleep,
+        time::Duration,
+    };
+
+    #[test]
+    fn writes_in_order_within_the_window() -> Result<(), String> {
+        let written = AtomicUsize::new(0);
+        let mut order = Vec::new();
+        read_in_order(
+            vec![(); 4],
+            &(0..100_usize).collect::<Vec<_>>(),
+            3,
+            |&mut (), &index| {
+                // later items finish first
+                let delay = u64::try_from(index).map_or(0, |i| 100_u64.saturating_sub(i));
+                sleep(Duration::from_micros(delay));
+                let ahead = index.saturating_sub(written.load(Ordering::SeqCst));
+                (index, ahead)
+            },
+            |&index, (item, ahead)| {
+                if ahead >= 3 {
+                    return Err(format!("item {item} read {ahead} ahead"));
+                }
+                order.push(index);
+                written.store(index.saturating_add(1), Ordering::SeqCst);
+                Ok(())
+            },
+        )?;
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn stops_at_the_first_write_error() {
+        let read = AtomicUsize::new(0);
+        let result = read_in_order(
+            vec![(); 2],
+            &(0..1000_usize).collect::<Vec<_>>(),
+            2,
+            |&mut (), &index| {
+                read.fetch_add(1, Ordering::SeqCst);
+                index
+            },
+            |&index, _| if index == 5 { Err(index) } else { Ok(()) },
+        );
+        assert_eq!(result, Err(5));
+        assert!(read.load(Ordering::SeqCst) < 1000);
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
+use crate::errors::FailureKind;
 use core::{num::ParseIntError, ops::Range};
 use std::{fs::read_to_string, io::Error as IoError, path::Path};
 
@@ -16,6 +17,29 @@ pub enum Error {
     PermissionDenied,
 }
 
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Io(_) => "iomem_io",
+            Self::Parse(_) | Self::ParseLine(_) => "iomem_parse",
+            Self::PermissionDenied => "iomem_permission_denied",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io(ref e) => FailureKind::from_io(e),
+            Self::Parse(_) | Self::ParseLine(_) => FailureKind::Other,
+            Self::PermissionDenied => FailureKind::PermissionDenied,
+        }
+    }
+}
+
 /// Parse /proc/iomem and return System RAM memory ranges
 ///
 /// # Errors
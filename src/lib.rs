@@ -1,26 +1,49 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
+#[cfg(target_family = "unix")]
+pub mod artifacts;
+pub mod diff;
 #[cfg(target_family = "unix")]
 mod disk_usage;
 pub mod errors;
+pub mod guest;
 pub mod image;
+pub mod incremental;
 pub mod io;
 pub mod iomem;
+pub mod merge;
+#[cfg(target_family = "unix")]
+pub mod probe;
+#[cfg(target_family = "unix")]
+pub mod process;
+pub mod progress;
 mod snapshot;
+pub mod source;
+pub mod split;
+#[cfg(target_family = "unix")]
+pub mod swap;
+pub mod throttle;
 mod upload;
+mod volumes;
 
 #[cfg(feature = "blobstore")]
 pub use crate::upload::blobstore::{BlobUploader, DEFAULT_CONCURRENCY, Error as BlobError};
+#[cfg(feature = "blobstore")]
+pub use crate::upload::credential::{ClientCredential, Error as CredentialError, ImdsCredential};
 #[cfg(feature = "put")]
 pub use crate::upload::http::put;
 #[cfg(feature = "blobstore")]
+pub use crate::upload::reader::BlobReader;
+#[cfg(feature = "blobstore")]
 pub use crate::upload::stream::{BLOB_MAX_BLOCKS, BlockBlobStream};
 pub use crate::{
     errors::Error,
     image::Format,
-    snapshot::{Snapshot, Source},
+    snapshot::{Snapshot, Source, Summary},
 };
+#[cfg(feature = "async")]
+pub use tokio_util::sync::CancellationToken;
 
 pub const ONE_MIB: usize = 1024 * 1024;
 
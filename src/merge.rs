@@ -0,0 +1,192 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Combining several snapshots into one.
+//!
+//! Every input is decoded sequentially with [`BlockReader`] and the inputs
+//! are walked together by physical address, as with [`crate::diff`]. Records
+//! within each input must be in ascending address order and must not
+//! overlap one another, as `acquire` writes them. Where inputs overlap, the
+//! [`Conflict`] policy picks which one's memory is kept.
+//!
+//! [`BlockReader`]: crate::image::BlockReader
+
+use crate::image::{BlockWriter, Error, Format, PageCursor, page_end};
+use std::io::{Read, Write};
+
+type Result<T> = core::result::Result<T, Error>;
+
+/// Which input's memory to keep where several inputs cover the same range.
+///
+/// Overlapping inputs holding identical memory are never a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keep the memory from the earliest input covering the range.
+    First,
+    /// Keep the memory from the latest input covering the range.
+    Last,
+    /// Fail with [`Error::Conflict`].
+    Error,
+}
+
+/// Merge `srcs` into a single snapshot in `format` with ordered,
+/// non-overlapping records, written to `dst`.
+///
+/// # Errors
+/// Returns an error if:
+/// - Any input cannot be read or decoded
+/// - An input's records are out of order or overlap ([`Error::OutOfOrder`])
+/// - Inputs hold different memory for the same range and `conflict` is
+///   [`Conflict::Error`]
+/// - Writing to `dst` fails
+pub fn merge<R: Read, W: Write>(
+    srcs: Vec<R>,
+    format: Format,
+    conflict: Conflict,
+    dst: W,
+) -> Result<()> {
+    let mut inputs = srcs
+        .into_iter()
+        .map(|src| (PageCursor::new(src), 0_u64))
+        .collect::<Vec<_>>();
+    let mut dst = BlockWriter::new(dst, format);
+
+    loop {
+        let mut available = Vec::with_capacity(inputs.len());
+        for &mut (ref mut cursor, consumed) in &mut inputs {
+            let range = cursor.peek()?;
+            if let Some(r) = range.as_ref()
+                && r.start < consumed
+            {
+                return Err(Error::OutOfOrder { range: r.clone() });
+            }
+            available.push(range);
+        }
+
+        let Some(start) = available.iter().flatten().map(|r| r.start).min() else {
+            break;
+        };
+        // stop at the end of the page, the end of any block covering
+        // `start`, or the start of a block that begins later, whichever
+        // comes first
+        let mut end = page_end(start)?;
+        for r in available.iter().flatten() {
+            end = end.min(if r.start == start { r.end } else { r.start });
+        }
+
+        let mut covering = Vec::new();
+        for (&mut (ref mut cursor, ref mut consumed), range) in inputs.iter_mut().zip(&available) {
+            if range.as_ref().is_some_and(|r| r.start == start) {
+                *consumed = end;
+                covering.push(cursor.take(end));
+            }
+        }
+
+        let data = match conflict {
+            Conflict::First => covering.first(),
+            Conflict::Last => covering.last(),
+            Conflict::Error => {
+                let first = covering.first();
+                if covering.iter().any(|data| Some(data) != first) {
+                    return Err(Error::Conflict { range: start..end });
+                }
+                first
+            }
+        };
+        dst.push(start..end, data.copied().unwrap_or_default())?;
+    }
+
+    dst.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conflict, merge};
+    use crate::image::{BlockData, BlockReader, Error, Format, filled_snapshot};
+    use core::ops::Range;
+
+    type Blocks = Vec<(Range<u64>, Vec<u8>)>;
+
+    fn merged(srcs: &[&[u8]], format: Format, conflict: Conflict) -> Result<Blocks, Error> {
+        let mut dst = Vec::new();
+        merge(srcs.to_vec(), format, conflict, &mut dst)?;
+        BlockReader::new(dst.as_slice())
+            .map(|block| block.map(|BlockData { range, data }| (range, data)))
+            .collect()
+    }
+
+    #[test]
+    fn merges_disjoint_inputs_in_address_order() -> Result<(), Error> {
+        let a = filled_snapshot(Format::Lime, &[(0x0..0x2000, 1), (0x8000..0x9000, 3)])?;
+        let b = filled_snapshot(
+            Format::AvmlCompressed,
+            &[(0x2000..0x3000, 2), (0xa000..0xb000, 4)],
+        )?;
+
+        for format in [Format::Lime, Format::AvmlCompressed] {
+            // adjacent ranges from different inputs are joined
+            let mut expected = vec![1; 0x2000];
+            expected.extend_from_slice(&[2; 0x1000]);
+            assert_eq!(
+                merged(&[&b, &a], format, Conflict::Error)?,
+                vec![
+                    (0x0..0x3000, expected),
+                    (0x8000..0x9000, vec![3; 0x1000]),
+                    (0xa000..0xb000, vec![4; 0x1000]),
+                ]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_overlaps_by_policy() -> Result<(), Error> {
+        let a = filled_snapshot(Format::Lime, &[(0x0..0x2000, 1)])?;
+        let b = filled_snapshot(Format::Lime, &[(0x1000..0x3000, 2)])?;
+
+        let mut first = vec![1; 0x2000];
+        first.extend_from_slice(&[2; 0x1000]);
+        assert_eq!(
+            merged(&[&a, &b], Format::Lime, Conflict::First)?,
+            vec![(0x0..0x3000, first)]
+        );
+
+        let mut last = vec![1; 0x1000];
+        last.extend_from_slice(&[2; 0x2000]);
+        assert_eq!(
+            merged(&[&a, &b], Format::Lime, Conflict::Last)?,
+            vec![(0x0..0x3000, last)]
+        );
+
+        let result = merged(&[&a, &b], Format::Lime, Conflict::Error);
+        assert!(
+            matches!(result, Err(Error::Conflict { ref range }) if *range == (0x1000..0x2000)),
+            "{result:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identical_overlaps_are_not_conflicts() -> Result<(), Error> {
+        let a = filled_snapshot(Format::Lime, &[(0x0..0x2000, 1)])?;
+        let b = filled_snapshot(Format::AvmlCompressed, &[(0x1000..0x3000, 1)])?;
+
+        assert_eq!(
+            merged(&[&a, &b], Format::Lime, Conflict::Error)?,
+            vec![(0x0..0x3000, vec![1; 0x3000])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_out_of_order_records() -> Result<(), Error> {
+        let a = filled_snapshot(Format::Lime, &[(0x2000..0x3000, 1), (0x0..0x1000, 1)])?;
+
+        let result = merged(&[&a], Format::Lime, Conflict::First);
+        assert!(
+            matches!(result, Err(Error::OutOfOrder { ref range }) if *range == (0x0..0x1000)),
+            "{result:?}"
+        );
+        Ok(())
+    }
+}
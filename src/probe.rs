@@ -0,0 +1,331 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Checking which memory sources will work on this host, without writing a
+//! snapshot.
+//!
+//! [`probe`] tries each [`Source`] in the order [`Snapshot::create`] does,
+//! reads the kernel lockdown mode and the capabilities of this process, and
+//! estimates how large a snapshot would be.
+//!
+//! [`Snapshot::create`]: crate::Snapshot::create
+
+use crate::{
+    disk_usage, iomem,
+    snapshot::{Error, Source, is_kcore_ok, test_read_above_first_mib},
+};
+use core::{
+    fmt::{Display as FmtDisplay, Formatter, Result as FmtResult},
+    ops::Range,
+};
+use std::{
+    fs::{File, read_to_string},
+    io::ErrorKind,
+    path::Path,
+};
+
+const CAP_SYS_RAWIO: u32 = 17;
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Whether a source can be used, and if not, why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Memory can be read from the source.
+    Usable,
+    /// The source does not exist.
+    Missing,
+    /// The source exists but cannot be opened.
+    Unopenable(String),
+    /// `/proc/kcore` exists but does not expose physical memory.
+    LockedDown,
+    /// The source opens, but reading memory above the first MiB fails, as
+    /// with `CONFIG_STRICT_DEVMEM`.
+    Restricted(String),
+}
+
+impl Status {
+    #[must_use]
+    pub const fn is_usable(&self) -> bool {
+        matches!(*self, Self::Usable)
+    }
+}
+
+impl FmtDisplay for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match *self {
+            Self::Usable => write!(f, "usable"),
+            Self::Missing => write!(f, "missing"),
+            Self::Unopenable(ref reason) => write!(f, "unable to open: {reason}"),
+            Self::LockedDown => write!(f, "locked down"),
+            Self::Restricted(ref reason) => {
+                write!(f, "unable to read above the first MiB: {reason}")
+            }
+        }
+    }
+}
+
+/// The kernel lockdown mode, from `/sys/kernel/security/lockdown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lockdown {
+    None,
+    /// Blocks modifying the running kernel, including writes to `/dev/mem`.
+    Integrity,
+    /// Additionally blocks reading kernel memory, through any source.
+    Confidentiality,
+}
+
+impl Lockdown {
+    // the mode in brackets, as in `none [integrity] confidentiality`
+    fn parse(contents: &str) -> Option<Self> {
+        let start = contents.find('[')?;
+        let end = contents.get(start..)?.find(']')?;
+        match contents.get(start.saturating_add(1)..start.saturating_add(end))? {
+            "none" => Some(Self::None),
+            "integrity" => Some(Self::Integrity),
+            "confidentiality" => Some(Self::Confidentiality),
+            _ => None,
+        }
+    }
+}
+
+impl FmtDisplay for Lockdown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match *self {
+            Self::None => write!(f, "none"),
+            Self::Integrity => write!(f, "integrity"),
+            Self::Confidentiality => write!(f, "confidentiality"),
+        }
+    }
+}
+
+/// The effective capabilities of this process, from `/proc/self/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub effective: u64,
+}
+
+impl Capabilities {
+    fn parse(status: &str) -> Option<Self> {
+        let line = status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))?;
+        let effective = u64::from_str_radix(line.trim(), 16).ok()?;
+        Some(Self { effective })
+    }
+
+    const fn has(self, capability: u32) -> bool {
+        self.effective & (1 << capability) != 0
+    }
+
+    /// Needed to read `/proc/iomem`.
+    #[must_use]
+    pub const fn sys_admin(self) -> bool {
+        self.has(CAP_SYS_ADMIN)
+    }
+
+    /// Needed to read `/dev/mem` and `/proc/kcore`.
+    #[must_use]
+    pub const fn sys_rawio(self) -> bool {
+        self.has(CAP_SYS_RAWIO)
+    }
+}
+
+/// What [`probe`] found.
+#[derive(Debug)]
+pub struct Report {
+    /// Each source, in the order a snapshot tries them.
+    pub sources: Vec<(Source, Status)>,
+    /// `None` if the kernel does not report a lockdown mode.
+    pub lockdown: Option<Lockdown>,
+    /// `None` if the capabilities of this process cannot be read.
+    pub capabilities: Option<Capabilities>,
+    /// The System RAM ranges of `/proc/iomem`.
+    pub memory: Result<Vec<Range<u64>>, iomem::Error>,
+    /// The estimated size of an uncompressed snapshot of `memory`.
+    pub estimated_size: Option<u64>,
+}
+
+impl Report {
+    /// The source a snapshot would use, if any.
+    #[must_use]
+    pub fn recommended(&self) -> Option<&Source> {
+        self.sources.iter().find_map(|entry| {
+            let (ref source, ref status) = *entry;
+            status.is_usable().then_some(source)
+        })
+    }
+
+    /// The source a snapshot would use, failing as a snapshot would if
+    /// there is none.
+    ///
+    /// # Errors
+    /// Returns an error of the `NoSource` kind if no source is usable.
+    pub fn require_source(&self) -> crate::Result<&Source> {
+        self.recommended()
+            .ok_or(crate::Error::Memory(Error::NoSourceAvailable))
+    }
+}
+
+/// Check each memory source on this host without writing a snapshot.
+#[must_use]
+pub fn probe() -> Report {
+    let memory = iomem::parse();
+    let ranges = memory.as_deref().unwrap_or_default();
+    let sources = [Source::DevCrash, Source::ProcKcore, Source::DevMem]
+        .into_iter()
+        .map(|source| {
+            let status = check(&source, ranges);
+            (source, status)
+        })
+        .collect();
+    let estimated_size = memory.as_deref().ok().map(disk_usage::estimate);
+
+    Report {
+        sources,
+        lockdown: read_to_string("/sys/kernel/security/lockdown")
+            .ok()
+            .and_then(|contents| Lockdown::parse(&contents)),
+        capabilities: read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| Capabilities::parse(&status)),
+        memory,
+        estimated_size,
+    }
+}
+
+/// Check whether `source` can be read. Physical memory sources are test
+/// read at pages of `ranges` above the first MiB, and are restricted if
+/// those reads fail or return only zeros.
+#[must_use]
+pub fn check(source: &Source, ranges: &[Range<u64>]) -> Status {
+    match *source {
+        Source::ProcKcore => check_kcore(Path::new("/proc/kcore")),
+        Source::DevCrash => check_phys(Path::new("/dev/crash"), ranges),
+        Source::DevMem => check_phys(Path::new("/dev/mem"), ranges),
+        Source::Raw(ref path) => check_phys(path, ranges),
+    }
+}
+
+fn open(path: &Path) -> Result<File, Status> {
+    File::open(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => Status::Missing,
+        _ => Status::Unopenable(e.to_string()),
+    })
+}
+
+fn check_kcore(path: &Path) -> Status {
+    if let Err(status) = open(path) {
+        return status;
+    }
+    // once it opens, kcore too small to hold memory has been locked down
+    if is_kcore_ok(path) {
+        Status::Usable
+    } else {
+        Status::LockedDown
+    }
+}
+
+fn check_phys(path: &Path, ranges: &[Range<u64>]) -> Status {
+    let mut file = match open(path) {
+        Ok(file) => file,
+        Err(status) => return status,
+    };
+    match test_read_above_first_mib(&mut file, ranges) {
+        Ok(()) => Status::Usable,
+        Err(e) => Status::Restricted(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, Lockdown, Report, Status, check, check_kcore};
+    use crate::snapshot::Source;
+    use std::{fs::write, io::Result};
+
+    #[test]
+    fn parses_lockdown_mode() {
+        assert_eq!(
+            Lockdown::parse("[none] integrity confidentiality\n"),
+            Some(Lockdown::None)
+        );
+        assert_eq!(
+            Lockdown::parse("none integrity [confidentiality]\n"),
+            Some(Lockdown::Confidentiality)
+        );
+        assert_eq!(Lockdown::parse("none integrity\n"), None);
+    }
+
+    #[test]
+    fn parses_effective_capabilities() {
+        let status = "Name:\tavml\nCapInh:\t0000000000000000\nCapEff:\t0000000000220000\n";
+        let caps = Capabilities::parse(status);
+        assert_eq!(caps.map(Capabilities::sys_admin), Some(true));
+        assert_eq!(caps.map(Capabilities::sys_rawio), Some(true));
+
+        let none = Capabilities::parse("CapEff:\t0000000000000000\n");
+        assert_eq!(none.map(Capabilities::sys_admin), Some(false));
+        assert_eq!(Capabilities::parse("Name:\tavml\n"), None);
+    }
+
+    #[test]
+    fn test_reads_above_the_first_mib() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mem");
+        write(&path, vec![0xff; 0x10_2000])?;
+        let source = Source::Raw(path);
+        assert_eq!(
+            check(&source, &[0x1000..0x2000, 0x10_0000..0x10_2000]),
+            Status::Usable
+        );
+        assert!(matches!(
+            check(&source, &[0x1000..0x2000, 0x20_0000..0x30_0000]),
+            Status::Restricted(_)
+        ));
+
+        let zeros = dir.path().join("zeros");
+        write(&zeros, vec![0; 0x10_2000])?;
+        assert!(matches!(
+            check(
+                &Source::Raw(zeros),
+                &[0x10_0000..0x10_2000, 0x20_0000..0x20_0000]
+            ),
+            Status::Restricted(_)
+        ));
+
+        let missing = Source::Raw(dir.path().join("missing"));
+        assert_eq!(check(&missing, &[]), Status::Missing);
+        Ok(())
+    }
+
+    #[test]
+    fn small_kcore_is_locked_down() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let kcore = dir.path().join("kcore");
+        write(&kcore, vec![0; 0x1000])?;
+        assert_eq!(check_kcore(&kcore), Status::LockedDown);
+        write(&kcore, vec![0; 0x3000])?;
+        assert_eq!(check_kcore(&kcore), Status::Usable);
+        Ok(())
+    }
+
+    #[test]
+    fn requires_a_usable_source() {
+        let mut report = Report {
+            sources: vec![
+                (Source::DevCrash, Status::Missing),
+                (Source::ProcKcore, Status::LockedDown),
+            ],
+            lockdown: None,
+            capabilities: None,
+            memory: Ok(Vec::new()),
+            estimated_size: None,
+        };
+        assert!(
+            report
+                .require_source()
+                .is_err_and(|e| e.failure_kind() == crate::errors::FailureKind::NoSource)
+        );
+        report.sources.push((Source::DevMem, Status::Usable));
+        assert!(matches!(report.require_source(), Ok(&Source::DevMem)));
+    }
+}
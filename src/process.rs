@@ -0,0 +1,346 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Acquiring the memory of a single process.
+//!
+//! [`ProcessMemory`] reads the mappings listed in `/proc/<pid>/maps`
+//! through `/proc/<pid>/mem`, so a snapshot of it has a record per run of
+//! non-zero memory keyed by virtual rather than physical address.
+
+use crate::{
+    errors::FailureKind,
+    image::open_dst,
+    source::{MemorySource, read_file_at},
+};
+use core::{num::ParseIntError, ops::Range};
+use std::{
+    fs::{File, read_to_string},
+    io::{Error as IoError, Result as IoResult, Write as _},
+    path::Path,
+};
+
+/// Mappings the kernel provides that cannot be read through
+/// `/proc/<pid>/mem`.
+const UNREADABLE_MAPPINGS: [&str; 3] = ["[vvar]", "[vvar_vclock]", "[vsyscall]"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("unable to read the memory map of process {pid}")]
+    Maps {
+        pid: u32,
+        #[source]
+        source: IoError,
+    },
+
+    #[error("unable to open the memory of process {pid}")]
+    Mem {
+        pid: u32,
+        #[source]
+        source: IoError,
+    },
+
+    #[error("unable to parse value")]
+    Parse(#[from] ParseIntError),
+
+    #[error("unable to parse line: {0}")]
+    ParseLine(String),
+
+    #[error("unable to write the memory map")]
+    Sidecar(#[from] crate::image::Error),
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Maps { .. } => "process_maps",
+            Self::Mem { .. } => "process_mem",
+            Self::Parse(_) | Self::ParseLine(_) => "process_maps_parse",
+            Self::Sidecar(ref e) => e.code(),
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Maps { ref source, .. } | Self::Mem { ref source, .. } => {
+                FailureKind::from_io(source)
+            }
+            Self::Parse(_) | Self::ParseLine(_) => FailureKind::Other,
+            Self::Sidecar(ref e) => e.failure_kind(),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A mapping of a process's memory, as listed in `/proc/<pid>/maps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// The virtual addresses mapped.
+    pub range: Range<u64>,
+    pub readable: bool,
+    /// Whether the mapping is shared with other processes, rather than
+    /// private copy-on-write.
+    pub shared: bool,
+    /// The inode of the file mapped, or 0 for anonymous memory.
+    pub inode: u64,
+    /// The file mapped, or a name such as `[heap]` or `[stack]`.
+    pub pathname: Option<String>,
+    // the line of /proc/<pid>/maps this was parsed from
+    line: String,
+}
+
+impl Mapping {
+    /// Whether the mapping is of a file, rather than anonymous memory.
+    #[must_use]
+    pub fn is_file_backed(&self) -> bool {
+        self.inode != 0
+    }
+
+    // Parse a line of /proc/<pid>/maps, such as:
+    //
+    //   55818255a000-55818255f000 r-xp 00002000 fe:00 317563   /usr/bin/cat
+    fn parse(line: &str) -> Result<Self> {
+        let invalid = || Error::ParseLine(line.to_string());
+        let mut rest = line;
+        let (start, end) = next_field(&mut rest)
+            .and_then(|range| range.split_once('-'))
+            .ok_or_else(invalid)?;
+        let perms = next_field(&mut rest).ok_or_else(invalid)?.as_bytes();
+        let _offset = next_field(&mut rest).ok_or_else(invalid)?;
+        let _dev = next_field(&mut rest).ok_or_else(invalid)?;
+        let inode = next_field(&mut rest).ok_or_else(invalid)?.parse()?;
+        // the pathname is the rest of the line, and may contain spaces
+        let pathname = Some(rest.trim_start())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+
+        Ok(Self {
+            range: u64::from_str_radix(start, 16)?..u64::from_str_radix(end, 16)?,
+            readable: perms.first() == Some(&b'r'),
+            shared: perms.get(3) == Some(&b's'),
+            inode,
+            pathname,
+            line: line.to_string(),
+        })
+    }
+}
+
+// the next whitespace-separated field of `rest`, leaving the text after it
+fn next_field<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let trimmed = rest.trim_start();
+    let (field, tail) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+    *rest = tail;
+    Some(field).filter(|field| !field.is_empty())
+}
+
+/// Parse the contents of `/proc/<pid>/maps`.
+///
+/// # Errors
+/// Returns an error if a line cannot be parsed.
+pub fn parse_maps(maps: &str) -> Result<Vec<Mapping>> {
+    maps.lines()
+        .filter(|line| !line.is_empty())
+        .map(Mapping::parse)
+        .collect()
+}
+
+/// Which mappings of a process to acquire. Mappings that are not readable
+/// are always left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Filter {
+    /// Leave out mappings of files, such as executables and libraries.
+    pub skip_file_backed: bool,
+    /// Leave out mappings shared with other processes.
+    pub skip_shared: bool,
+}
+
+impl Filter {
+    fn includes(self, mapping: &Mapping) -> bool {
+        if !mapping.readable
+            || (self.skip_file_backed && mapping.is_file_backed())
+            || (self.skip_shared && mapping.shared)
+        {
+            return false;
+        }
+        !mapping
+            .pathname
+            .as_deref()
+            .is_some_and(|name| UNREADABLE_MAPPINGS.contains(&name))
+    }
+}
+
+/// The memory of a running process, read through `/proc/<pid>/mem`.
+pub struct ProcessMemory {
+    pid: u32,
+    mem: File,
+    mappings: Vec<Mapping>,
+}
+
+impl ProcessMemory {
+    /// Open the memory of process `pid`, keeping the mappings `filter`
+    /// includes. Reading another process's memory needs the same access
+    /// as attaching to it with `ptrace`.
+    ///
+    /// # Errors
+    /// Returns an error if the maps of the process cannot be read or
+    /// parsed, or its memory cannot be opened.
+    pub fn open(pid: u32, filter: Filter) -> Result<Self> {
+        let proc = Path::new("/proc").join(pid.to_string());
+        let maps =
+            read_to_string(proc.join("maps")).map_err(|source| Error::Maps { pid, source })?;
+        let mappings = parse_maps(&maps)?
+            .into_iter()
+            .filter(|mapping| filter.includes(mapping))
+            .collect();
+        let mem = File::open(proc.join("mem")).map_err(|source| Error::Mem { pid, source })?;
+        Ok(Self { pid, mem, mappings })
+    }
+
+    /// The mappings acquired.
+    #[must_use]
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Write the lines of `/proc/<pid>/maps` for the mappings acquired to
+    /// `path`, so the records of a snapshot can be matched to the files
+    /// and names they were mapped from.
+    ///
+    /// # Errors
+    /// Returns an error if `path` cannot be written.
+    pub fn write_maps(&self, path: &Path) -> Result<()> {
+        let mut dst = open_dst(path)?;
+        for mapping in &self.mappings {
+            writeln!(dst, "{}", mapping.line).map_err(|source| crate::image::Error::Io {
+                context: "unable to write memory map",
+                source,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl MemorySource for ProcessMemory {
+    fn name(&self) -> String {
+        format!("process {}", self.pid)
+    }
+
+    // the mappings of the process, rather than physical memory
+    fn ranges(&self, _memory_ranges: &[Range<u64>]) -> IoResult<Vec<Range<u64>>> {
+        Ok(self
+            .mappings
+            .iter()
+            .map(|mapping| mapping.range.clone())
+            .collect())
+    }
+
+    fn read_at(&self, addr: u64, buf: &mut [u8]) -> IoResult<usize> {
+        read_file_at(&self.mem, addr, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, parse_maps};
+
+    const MAPS: &str = "\
+558182558000-55818255a000 r--p 00000000 fe:00 317563                     /usr/bin/cat
+558182563000-558182564000 rw-p 0000a000 fe:00 317563                     /usr/bin/cat
+7f38e4700000-7f38e4721000 rw-s 00000000 00:01 1024                       /dev/shm/ring buffer (deleted)
+7f38e4750000-7f38e4751000 ---p 00000000 00:00 0
+7f38e4751000-7f38e4760000 rw-p 00000000 00:00 0
+7fff5fb5e000-7fff5fb7f000 rw-p 00000000 00:00 0                          [stack]
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]
+";
+
+    #[test]
+    fn parses_maps() -> super::Result<()> {
+        let mappings = parse_maps(MAPS)?;
+        assert_eq!(mappings.len(), 7);
+        let shm = mappings.get(2);
+        assert!(shm.is_some_and(|m| m.shared
+            && m.is_file_backed()
+            && m.pathname.as_deref() == Some("/dev/shm/ring buffer (deleted)")));
+        let guard = mappings.get(3);
+        assert!(guard.is_some_and(|m| !m.readable && m.pathname.is_none()));
+        Ok(())
+    }
+
+    #[test]
+    fn filters_mappings() -> super::Result<()> {
+        let mappings = parse_maps(MAPS)?;
+        let kept = |filter: Filter| {
+            mappings
+                .iter()
+                .filter(|mapping| filter.includes(mapping))
+                .count()
+        };
+        assert_eq!(kept(Filter::default()), 5);
+        assert_eq!(
+            kept(Filter {
+                skip_file_backed: true,
+                skip_shared: false,
+            }),
+            2
+        );
+        assert_eq!(
+            kept(Filter {
+                skip_file_backed: false,
+                skip_shared: true,
+            }),
+            4
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn acquires_this_process() -> Result<(), Box<dyn core::error::Error>> {
+        use super::ProcessMemory;
+        use crate::{Snapshot, source::MemorySource as _};
+        use std::sync::Arc;
+
+        let marker = vec![0x5a_u8; 0x4000];
+        let addr = u64::try_from(marker.as_ptr().addr())?;
+
+        let process = ProcessMemory::open(
+            std::process::id(),
+            Filter {
+                skip_file_backed: true,
+                skip_shared: true,
+            },
+        )?;
+        assert!(
+            process
+                .mappings()
+                .iter()
+                .any(|mapping| mapping.range.contains(&addr))
+        );
+        let mut buf = [0; 0x10];
+        process.read_at(addr, &mut buf)?;
+        assert_eq!(buf, [0x5a; 0x10]);
+
+        let dir = tempfile::tempdir()?;
+        let maps = dir.path().join("memory.lime.maps");
+        process.write_maps(&maps)?;
+        assert_eq!(
+            std::fs::read_to_string(&maps)?.lines().count(),
+            process.mappings().len()
+        );
+
+        let ranges = process.ranges(&[])?;
+        let mut snapshot = Vec::new();
+        Snapshot::new(&dir.path().join("memory.lime"), ranges)
+            .memory_source(Some(Arc::new(process)))
+            .create_to_writer(&mut snapshot)?;
+        assert!(!snapshot.is_empty());
+        Ok(())
+    }
+}
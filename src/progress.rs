@@ -0,0 +1,351 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Reporting how far an acquisition, conversion, or upload has got.
+//!
+//! [`Image`] reports each record it reads and writes, and
+//! [`BlockBlobStream`], [`BlobUploader`] and [`put`] the bytes they upload,
+//! to a [`Reporter`]. Any `Fn(&Progress)` closure is one, and with the
+//! `status` feature, [`Bar`] draws one for an interactive terminal.
+//!
+//! [`Image`]: crate::image::Image
+//! [`BlockBlobStream`]: crate::BlockBlobStream
+//! [`BlobUploader`]: crate::BlobUploader
+//! [`put`]: crate::put
+
+use core::{ops::Range, time::Duration};
+#[cfg(feature = "status")]
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+#[cfg(feature = "status")]
+use std::{io::IsTerminal as _, sync::OnceLock};
+use std::{sync::Arc, time::Instant};
+
+/// The kind of work being reported on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Reading memory into a snapshot.
+    Acquire,
+    /// Rewriting a snapshot in another format.
+    Convert,
+    /// Uploading a snapshot as it is written.
+    Upload,
+}
+
+impl Operation {
+    /// A lowercase name for the operation, for machine-readable reports.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Acquire => "acquire",
+            Self::Convert => "convert",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+/// How far an operation has got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub operation: Operation,
+    /// Bytes of memory read so far. Uploads only count bytes written.
+    pub bytes_read: u64,
+    /// Bytes written to the destination so far.
+    pub bytes_written: u64,
+    /// Bytes of memory to be read in all, or for an upload of a file, its
+    /// size, when known up front.
+    pub total: Option<u64>,
+    /// The memory most recently read.
+    pub range: Option<Range<u64>>,
+    pub elapsed: Duration,
+    /// Whether the operation has completed.
+    pub done: bool,
+}
+
+impl Progress {
+    /// Estimated time until every byte of `total` has been read (or for
+    /// uploads, written), assuming the rate so far holds.
+    #[must_use]
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        let done = match self.operation {
+            Operation::Upload => self.bytes_written,
+            Operation::Acquire | Operation::Convert => self.bytes_read,
+        };
+        if done == 0 {
+            return None;
+        }
+        let remaining = total.saturating_sub(done);
+        let nanos = self
+            .elapsed
+            .as_nanos()
+            .saturating_mul(u128::from(remaining))
+            .checked_div(u128::from(done))?;
+        Some(Duration::from_nanos(
+            u64::try_from(nanos).unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// Bytes of memory read per byte written, such as `4.0` when the
+    /// output is a quarter the size of the memory it holds. Memory elided
+    /// as all-zero counts as read but not written.
+    #[must_use]
+    #[expect(
+        clippy::cast_precision_loss,
+        clippy::as_conversions,
+        reason = "the ratio is for display, where precision loss is harmless"
+    )]
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.bytes_read == 0 || self.bytes_written == 0 {
+            return None;
+        }
+        Some(self.bytes_read as f64 / self.bytes_written as f64)
+    }
+}
+
+/// Receives progress as an operation runs.
+///
+/// Reporting never fails the operation, so implementations should drop
+/// any errors of their own.
+pub trait Reporter: Send + Sync {
+    fn report(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress) + Send + Sync> Reporter for F {
+    fn report(&self, progress: &Progress) {
+        self(progress);
+    }
+}
+
+/// Draws a progress bar on stderr, when stderr is a terminal.
+///
+/// Only the first operation reported is drawn, so one `Bar` can be shared
+/// by an acquisition and the upload it feeds.
+#[cfg(feature = "status")]
+pub struct Bar {
+    bar: ProgressBar,
+    operation: OnceLock<Operation>,
+}
+
+#[cfg(feature = "status")]
+impl Bar {
+    /// # Panics
+    /// Never: the template of the bar is a literal known to be valid.
+    #[must_use]
+    pub fn new() -> Self {
+        let target = if std::io::stderr().is_terminal() {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        };
+        let bar = ProgressBar::with_draw_target(None, target)
+            .with_style(
+                #[expect(
+                    clippy::expect_used,
+                    reason = "template string is a compile-time literal known to be valid"
+                )]
+                ProgressStyle::default_bar()
+                    .template("{bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}")
+                    .expect("progress bar build failed"),
+            )
+            .with_finish(ProgressFinish::AndLeave);
+        Self {
+            bar,
+            operation: OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(feature = "status")]
+impl Default for Bar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "status")]
+impl Reporter for Bar {
+    fn report(&self, progress: &Progress) {
+        if *self.operation.get_or_init(|| progress.operation) != progress.operation {
+            return;
+        }
+        let position = match progress.operation {
+            Operation::Upload => progress.bytes_written,
+            Operation::Acquire | Operation::Convert => progress.bytes_read,
+        };
+        self.bar
+            .set_length(progress.total.unwrap_or(position).max(position));
+        self.bar.set_position(position);
+        if let Some(ratio) = progress.compression_ratio() {
+            self.bar.set_message(format!("{ratio:.1}:1"));
+        }
+        if progress.done {
+            self.bar.finish();
+        }
+    }
+}
+
+/// Memory below this address is readable even when a source hides the
+/// rest, as `/dev/mem` does with `CONFIG_STRICT_DEVMEM`.
+const FIRST_MIB: u64 = 0x10_0000;
+
+/// Accumulates the progress of one operation, passing it to a
+/// [`Reporter`] if there is one.
+pub(crate) struct Tracker {
+    reporter: Option<Arc<dyn Reporter>>,
+    start: Instant,
+    progress: Progress,
+    // whether memory above the first MiB was read, and whether any of it
+    // produced output
+    read_above_first_mib: bool,
+    wrote_above_first_mib: bool,
+}
+
+impl Tracker {
+    pub(crate) fn new(reporter: Option<Arc<dyn Reporter>>, operation: Operation) -> Self {
+        Self {
+            reporter,
+            start: Instant::now(),
+            progress: Progress {
+                operation,
+                bytes_read: 0,
+                bytes_written: 0,
+                total: None,
+                range: None,
+                elapsed: Duration::ZERO,
+                done: false,
+            },
+            read_above_first_mib: false,
+            wrote_above_first_mib: false,
+        }
+    }
+
+    /// Add `bytes` to the memory expected to be read.
+    pub(crate) fn add_total(&mut self, bytes: u64) {
+        self.progress.total = Some(self.progress.total.unwrap_or(0).saturating_add(bytes));
+    }
+
+    /// Record that the memory at `range` was read, producing `written`
+    /// bytes of output.
+    pub(crate) fn read(&mut self, range: Range<u64>, written: u64) {
+        if range.start >= FIRST_MIB {
+            self.read_above_first_mib = true;
+            self.wrote_above_first_mib |= written > 0;
+        }
+        self.progress.bytes_read = self
+            .progress
+            .bytes_read
+            .saturating_add(range.end.saturating_sub(range.start));
+        self.progress.range = Some(range);
+        self.written(written);
+    }
+
+    /// Record that `bytes` of output were written.
+    pub(crate) fn written(&mut self, bytes: u64) {
+        self.progress.bytes_written = self.progress.bytes_written.saturating_add(bytes);
+        self.report();
+    }
+
+    /// Start counting again, as when an upload reads a file from the start
+    /// again to retry it.
+    #[cfg(feature = "blobstore")]
+    pub(crate) fn restart(&mut self) {
+        self.progress.bytes_read = 0;
+        self.progress.bytes_written = 0;
+        self.progress.range = None;
+        self.report();
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.progress.done = true;
+        self.report();
+    }
+
+    pub(crate) fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Whether memory above the first MiB was read but produced no output,
+    /// as when every page of it was zero.
+    pub(crate) const fn only_zeros_above_first_mib(&self) -> bool {
+        self.read_above_first_mib && !self.wrote_above_first_mib
+    }
+
+    fn report(&mut self) {
+        self.progress.elapsed = self.start.elapsed();
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.report(&self.progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Operation, Progress, Tracker};
+    use core::time::Duration;
+    use std::sync::{Arc, Mutex};
+
+    fn progress(bytes_read: u64, bytes_written: u64, total: Option<u64>) -> Progress {
+        Progress {
+            operation: Operation::Acquire,
+            bytes_read,
+            bytes_written,
+            total,
+            range: Some(0x1000..0x2000),
+            elapsed: Duration::from_secs(10),
+            done: false,
+        }
+    }
+
+    #[test]
+    fn derives_eta_and_compression_ratio() {
+        let quarter = progress(100, 25, Some(400));
+        assert_eq!(quarter.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(quarter.compression_ratio(), Some(4.0));
+
+        let upload = Progress {
+            operation: Operation::Upload,
+            ..progress(0, 100, Some(400))
+        };
+        assert_eq!(upload.eta(), Some(Duration::from_secs(30)));
+
+        let unknown = progress(100, 0, None);
+        assert_eq!(unknown.eta(), None);
+        assert_eq!(unknown.compression_ratio(), None);
+    }
+
+    #[test]
+    fn tracker_accumulates_reports() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let mut tracker = Tracker::new(
+            Some(Arc::new(move |progress: &Progress| {
+                if let Ok(mut guard) = sink.lock() {
+                    guard.push((progress.bytes_read, progress.bytes_written, progress.done));
+                }
+            })),
+            Operation::Acquire,
+        );
+        tracker.add_total(0x2000);
+        tracker.read(0x0..0x1000, 10);
+        tracker.read(0x1000..0x2000, 0);
+        tracker.finish();
+        assert!(!tracker.only_zeros_above_first_mib());
+
+        let seen = seen.lock().map(|seen| seen.clone()).unwrap_or_default();
+        assert_eq!(
+            seen,
+            vec![(0x1000, 10, false), (0x2000, 10, false), (0x2000, 10, true)]
+        );
+    }
+
+    #[test]
+    fn tracker_notices_zeros_above_the_first_mib() {
+        let mut tracker = Tracker::new(None, Operation::Acquire);
+        tracker.read(0x1000..0x9_f000, 0x9_e020);
+        tracker.read(0x10_0000..0x20_0000, 0);
+        assert!(tracker.only_zeros_above_first_mib());
+
+        tracker.read(0x20_0000..0x30_0000, 0x10_0020);
+        assert!(!tracker.only_zeros_above_first_mib());
+    }
+}
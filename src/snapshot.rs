@@ -4,23 +4,40 @@
 #[cfg(target_family = "unix")]
 use crate::disk_usage;
 use crate::{
-    errors::format_error,
-    image::{Block, Format, Image},
+    errors::{FailureKind, format_error},
+    image::{Block, Format, Image, OnReadError, detect_read_size, open_dst, whole_pages},
+    incremental::Incremental,
+    io::digest::DigestWriter,
+    progress::{Operation, Reporter, Tracker},
+    source::{FileSource, MemorySource, SourceReader},
+    throttle::RateLimit,
+    volumes::Volumes,
 };
 use clap::ValueEnum;
 use core::{
     fmt::{Debug as FmtDebug, Display as FmtDisplay, Formatter, Result as FmtResult},
-    num::NonZeroU64,
+    num::{NonZeroU64, NonZeroUsize},
     ops::Range,
+    str::FromStr,
+    time::Duration,
 };
-use elf::{abi::PT_LOAD, endian::NativeEndian, segment::ProgramHeader};
+use elf::{abi::PT_LOAD, segment::ProgramHeader};
 #[cfg(not(target_family = "unix"))]
 use std::env::consts::OS;
 use std::{
+    borrow::Cow,
     fs::{File, OpenOptions, metadata},
-    io::{Read, Seek, Write},
+    io::{Read, Seek, SeekFrom, Sink, Write, sink},
     path::{Path, PathBuf},
+    sync::Arc,
 };
+#[cfg(feature = "async")]
+use {
+    tokio::{io::AsyncWrite, task::spawn_blocking},
+    tokio_util::{io::SyncIoBridge, sync::CancellationToken},
+};
+
+const ONE_MIB_NZ: NonZeroU64 = NonZeroU64::new(1024 * 1024).expect("ONE_MIB must be non-zero");
 
 #[derive(thiserror::Error)]
 pub enum Error {
@@ -45,6 +62,13 @@ pub enum Error {
         source: Box<Error>,
     },
 
+    #[error("unable to create memory snapshot from source: {name}")]
+    UnableToCreateSnapshotFromMemorySource {
+        name: String,
+        #[source]
+        source: Box<Error>,
+    },
+
     #[error("no memory source available")]
     NoSourceAvailable,
 
@@ -78,6 +102,12 @@ pub enum Error {
 
     #[error("disk error")]
     Disk(#[source] std::io::Error),
+
+    #[error("/dev/mem is restricted to the first MiB, as with CONFIG_STRICT_DEVMEM")]
+    StrictDevMem(#[source] std::io::Error),
+
+    #[error("unable to list the memory ranges of the source")]
+    MemoryRanges(#[source] std::io::Error),
 }
 
 fn fmt_all_sources(crash: &Error, kcore: &Error, devmem: &Error) -> String {
@@ -106,6 +136,88 @@ impl FmtDebug for Error {
 pub type Result<T> = core::result::Result<T, Error>;
 
 impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Elf(_) => "elf_parse",
+            Self::LockedDownKcore => "locked_down_kcore",
+            Self::DiskUsageEstimateExceeded { .. } => "disk_usage_estimate_exceeded",
+            Self::UnableToCreateMemorySnapshot(ref source) => source.code(),
+            Self::UnableToCreateSnapshotFromSource { ref source, .. }
+            | Self::UnableToCreateSnapshotFromMemorySource { ref source, .. } => source.code(),
+            Self::NoSourceAvailable => "no_source_available",
+            Self::AllSourcesFailed { .. } => "all_sources_failed",
+            Self::KcoreParse(_) => "kcore_parse",
+            Self::F64Conversion { .. } | Self::U64Conversion { .. } => "numeric_conversion",
+            Self::PathContainsNul(_) => "path_contains_nul",
+            Self::BlockSize { .. } => "block_size",
+            Self::UnsupportedPlatform { .. } => "unsupported_platform",
+            Self::Disk(_) => "disk_io",
+            Self::StrictDevMem(_) => "strict_devmem",
+            Self::MemoryRanges(_) => "memory_ranges",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::LockedDownKcore => FailureKind::LockedDownKcore,
+            Self::DiskUsageEstimateExceeded { .. } => FailureKind::DiskUsageExceeded,
+            Self::UnableToCreateMemorySnapshot(ref source) => source.failure_kind(),
+            Self::UnableToCreateSnapshotFromSource { ref source, .. }
+            | Self::UnableToCreateSnapshotFromMemorySource { ref source, .. } => {
+                source.failure_kind()
+            }
+            Self::NoSourceAvailable | Self::StrictDevMem(_) => FailureKind::NoSource,
+            // the first source, in the order tried, that failed for a reason
+            // more telling than not being there, such as lockdown
+            Self::AllSourcesFailed { .. } => self
+                .source_failures()
+                .into_iter()
+                .map(|(_, failure)| failure.failure_kind())
+                .find(|kind| !matches!(*kind, FailureKind::Other | FailureKind::NoSource))
+                .unwrap_or(FailureKind::NoSource),
+            Self::PathContainsNul(_) => FailureKind::Usage,
+            Self::Disk(ref e) | Self::MemoryRanges(ref e) => FailureKind::from_io(e),
+            Self::Elf(_)
+            | Self::KcoreParse(_)
+            | Self::F64Conversion { .. }
+            | Self::U64Conversion { .. }
+            | Self::BlockSize { .. }
+            | Self::UnsupportedPlatform { .. } => FailureKind::Other,
+        }
+    }
+
+    /// Why each source failed, when every source tried failed.
+    #[must_use]
+    pub fn source_failures(&self) -> Vec<(Source, &Self)> {
+        match *self {
+            Self::AllSourcesFailed {
+                ref crash,
+                ref kcore,
+                ref devmem,
+            } => [
+                (Source::DevCrash, &**crash),
+                (Source::ProcKcore, &**kcore),
+                (Source::DevMem, &**devmem),
+            ]
+            .into_iter()
+            .map(|(src, err)| match *err {
+                Self::UnableToCreateSnapshotFromSource { ref source, .. } => (src, &**source),
+                _ => (src, err),
+            })
+            .collect(),
+            Self::UnableToCreateSnapshotFromSource { ref source, .. }
+            | Self::UnableToCreateSnapshotFromMemorySource { ref source, .. } => {
+                source.source_failures()
+            }
+            _ => Vec::new(),
+        }
+    }
+
     /// True when the underlying failure is a pre-acquisition disk-usage
     /// rejection. These are surfaced immediately rather than aggregated:
     /// trying the next source won't change the answer.
@@ -116,6 +228,12 @@ impl Error {
                 if matches!(**inner, Error::DiskUsageEstimateExceeded { .. })
         )
     }
+
+    /// True when the caller cancelled the acquisition, which trying the
+    /// next source must not undo.
+    fn is_cancelled(&self) -> bool {
+        self.failure_kind() == FailureKind::Cancelled
+    }
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -149,11 +267,46 @@ pub enum Source {
     #[value(name = "/proc/kcore")]
     ProcKcore,
 
-    /// User-specified path to a raw memory file
+    /// User-specified path to a raw memory file, in which the offset of
+    /// each byte is its physical address. Given as `path:<file>` on the
+    /// command line.
     #[value(skip)]
     Raw(PathBuf),
 }
 
+impl Source {
+    /// The device or file memory is read from.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match *self {
+            Self::DevCrash => Path::new("/dev/crash"),
+            Self::DevMem => Path::new("/dev/mem"),
+            Self::ProcKcore => Path::new("/proc/kcore"),
+            Self::Raw(ref path) => path,
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    /// Parse `/dev/crash`, `/dev/mem`, `/proc/kcore`, or `path:<file>` for
+    /// a raw memory file.
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("path:") {
+            if path.is_empty() {
+                return Err("`path:` needs a file, as in `path:memory.raw`".to_string());
+            }
+            return Ok(Self::Raw(PathBuf::from(path)));
+        }
+        <Self as ValueEnum>::from_str(s, false).map_err(|_| {
+            format!(
+                "`{s}` isn't a valid source; use /dev/crash, /dev/mem, /proc/kcore, or path:<file>"
+            )
+        })
+    }
+}
+
 impl FmtDisplay for Source {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match *self {
@@ -188,18 +341,138 @@ fn can_open(src: &Path) -> bool {
 // /dev/mem and /dev/crash, if available, are devices, rather than virtual
 // files.  As such, we don't check those for size.
 #[must_use]
-fn is_kcore_ok() -> bool {
-    metadata(Path::new("/proc/kcore")).is_ok_and(|x| x.len() > 0x2000)
-        && can_open(Path::new("/proc/kcore"))
+pub fn is_kcore_ok(kcore: &Path) -> bool {
+    metadata(kcore).is_ok_and(|x| x.len() > 0x2000) && can_open(kcore)
+}
+
+/// With `CONFIG_STRICT_DEVMEM`, `/dev/mem` only allows reading memory
+/// below this address. Reads above it fail, or on some architectures
+/// return zeros.
+const STRICT_DEVMEM_LIMIT: u64 = 0x10_0000;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// How many pages, spread evenly across memory above the first MiB, to
+/// test read before deciding a source hides that memory.
+const TEST_READ_PAGES: u64 = 16;
+
+/// Test read pages of `ranges` above the first MiB from `src`, failing if
+/// any read fails or every page read is zero. Succeeds without reading if
+/// no range holds a whole page above the first MiB.
+pub fn test_read_above_first_mib(src: &mut File, ranges: &[Range<u64>]) -> std::io::Result<()> {
+    let pages = test_read_pages(ranges);
+    if pages.is_empty() {
+        return Ok(());
+    }
+    let mut page = [0; 0x1000];
+    for addr in pages {
+        src.seek(SeekFrom::Start(addr))?;
+        src.read_exact(&mut page)?;
+        if page.iter().any(|x| *x != 0) {
+            return Ok(());
+        }
+    }
+    Err(std::io::Error::other(
+        "memory above the first MiB reads as zeros",
+    ))
+}
+
+// page-aligned addresses spread evenly across the whole pages of `ranges`
+// above the first MiB
+fn test_read_pages(ranges: &[Range<u64>]) -> Vec<u64> {
+    let high = ranges
+        .iter()
+        .filter_map(|range| {
+            let start = range
+                .start
+                .max(STRICT_DEVMEM_LIMIT)
+                .checked_next_multiple_of(PAGE_SIZE)?;
+            let end = range.end.saturating_sub(range.end % PAGE_SIZE);
+            (start < end).then_some(start..end)
+        })
+        .collect::<Vec<_>>();
+    let total = high
+        .iter()
+        .map(|range| range.end.saturating_sub(range.start) / PAGE_SIZE)
+        .fold(0_u64, u64::saturating_add);
+    let step = total.div_ceil(TEST_READ_PAGES).max(1);
+
+    let mut pages = Vec::new();
+    let mut skip = 0_u64;
+    for range in high {
+        let mut addr = range.start.saturating_add(skip.saturating_mul(PAGE_SIZE));
+        while addr < range.end {
+            pages.push(addr);
+            addr = addr.saturating_add(step.saturating_mul(PAGE_SIZE));
+        }
+        skip = addr.saturating_sub(range.end) / PAGE_SIZE;
+    }
+    pages
+}
+
+/// What a completed snapshot captured.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    /// The built-in source memory was read from, or `None` for a
+    /// [`MemorySource`] given to [`Snapshot::memory_source`].
+    pub source: Option<Source>,
+    /// The name of the [`MemorySource`] memory was read from, if one was
+    /// given to [`Snapshot::memory_source`].
+    pub memory_source: Option<String>,
+    pub format: Format,
+    /// Bytes of memory read, including all-zero memory left out of the
+    /// snapshot.
+    pub bytes_read: u64,
+    /// Bytes written to the destination.
+    pub bytes_written: u64,
+    /// The ranges of physical memory read.
+    pub ranges: Vec<Range<u64>>,
+    /// Time spent reading memory and writing the snapshot.
+    pub elapsed: Duration,
+    /// SHA-256 of the snapshot as written, unless it was split.
+    pub sha256: Option<String>,
+    /// Each volume of a split snapshot, with its SHA-256.
+    pub volumes: Vec<(PathBuf, String)>,
+    /// Whether every page read above the first MiB was zero. Real memory
+    /// is never entirely zero, so this suggests the source hid memory
+    /// rather than reading it. Always `false` for incremental snapshots.
+    pub zero_filled: bool,
+    /// Memory that could not be read, and was zeroed or left out as
+    /// [`Snapshot::on_read_error`] allowed.
+    pub unreadable: Vec<Range<u64>>,
+}
+
+impl Summary {
+    /// Describes the source memory was read from, whether built-in or a
+    /// [`MemorySource`].
+    #[must_use]
+    pub fn source_name(&self) -> String {
+        match (self.source.as_ref(), self.memory_source.as_ref()) {
+            (Some(source), _) => source.to_string(),
+            (None, Some(name)) => name.clone(),
+            (None, None) => String::new(),
+        }
+    }
 }
 
 pub struct Snapshot<'a> {
     source: Option<Source>,
-    destination: &'a Path,
+    destination: Cow<'a, Path>,
     memory_ranges: Vec<Range<u64>>,
     format: Format,
     max_disk_usage: Option<NonZeroU64>,
     max_disk_usage_percentage: Option<f64>,
+    baseline: Option<Cow<'a, Path>>,
+    manifest: Option<Cow<'a, Path>>,
+    split_size: Option<NonZeroU64>,
+    max_rate: Option<NonZeroU64>,
+    progress: Option<Arc<dyn Reporter>>,
+    on_read_error: OnReadError,
+    readers: NonZeroUsize,
+    read_size: Option<NonZeroUsize>,
+    #[cfg(feature = "async")]
+    cancel: Option<CancellationToken>,
+    memory_source: Option<Arc<dyn MemorySource>>,
 }
 
 impl<'a> Snapshot<'a> {
@@ -210,11 +483,22 @@ impl<'a> Snapshot<'a> {
     pub fn new(destination: &'a Path, memory_ranges: Vec<Range<u64>>) -> Self {
         Self {
             source: None,
-            destination,
+            destination: Cow::Borrowed(destination),
             memory_ranges,
             format: Format::Lime,
             max_disk_usage: None,
             max_disk_usage_percentage: None,
+            baseline: None,
+            manifest: None,
+            split_size: None,
+            max_rate: None,
+            progress: None,
+            on_read_error: OnReadError::Abort,
+            readers: NonZeroUsize::MIN,
+            read_size: None,
+            #[cfg(feature = "async")]
+            cancel: None,
+            memory_source: None,
         }
     }
 
@@ -246,26 +530,213 @@ impl<'a> Snapshot<'a> {
         Self { source, ..self }
     }
 
+    /// Acquire from `memory_source` rather than [`Self::source`] or any of
+    /// the built-in sources. The summary names the source in
+    /// [`Summary::memory_source`], and errors by [`MemorySource::name`].
+    #[must_use]
+    pub fn memory_source(self, memory_source: Option<Arc<dyn MemorySource>>) -> Self {
+        Self {
+            memory_source,
+            ..self
+        }
+    }
+
     /// Specify the snapshot format.
     #[must_use]
     pub fn format(self, format: Format) -> Self {
         Self { format, ..self }
     }
 
-    fn create_source(&self, src: &Source) -> Result<()> {
-        match *src {
-            Source::ProcKcore => self.kcore(),
-            Source::DevCrash => self.phys(Path::new("/dev/crash")),
-            Source::DevMem => self.phys(Path::new("/dev/mem")),
-            Source::Raw(ref s) => self.phys(s),
+    /// Only write pages that changed since `baseline`, which is either a
+    /// snapshot image or a manifest from an earlier run.
+    ///
+    /// All-zero pages that changed are still written. Pages missing from the
+    /// baseline are treated as zero. The disk usage estimate still assumes
+    /// a full snapshot.
+    #[must_use]
+    pub fn baseline(self, baseline: Option<&'a Path>) -> Self {
+        Self {
+            baseline: baseline.map(Cow::Borrowed),
+            ..self
+        }
+    }
+
+    /// Write a hash of every page read to `manifest`, for use as the
+    /// baseline of a later run and to reconstruct the full snapshot from a
+    /// delta with [`crate::incremental::reconstruct`].
+    ///
+    /// Zero regions are elided per page rather than per block, so the
+    /// snapshot may be split into more records.
+    #[must_use]
+    pub fn manifest(self, manifest: Option<&'a Path>) -> Self {
+        Self {
+            manifest: manifest.map(Cow::Borrowed),
+            ..self
+        }
+    }
+
+    /// Split the snapshot into volumes of at most `split_size` MiB, named
+    /// after the destination with `.001`, `.002`, and so on appended.
+    ///
+    /// Records never span volumes, so each volume is a valid snapshot on its
+    /// own; a record larger than `split_size` gets a volume to itself. The
+    /// destination with `.parts` appended lists every volume and its
+    /// SHA-256. The disk usage limits apply to each volume rather than to
+    /// the snapshot as a whole.
+    #[must_use]
+    pub fn split_size(self, split_size: Option<NonZeroU64>) -> Self {
+        Self { split_size, ..self }
+    }
+
+    /// Read memory at no more than `max_rate` MiB per second on average.
+    ///
+    /// The reader sleeps between blocks rather than spinning. Output is
+    /// derived from the memory read, so this also bounds the rate of writes
+    /// to the destination.
+    #[must_use]
+    pub fn max_rate(self, max_rate: Option<NonZeroU64>) -> Self {
+        Self { max_rate, ..self }
+    }
+
+    /// Report progress reading memory to `reporter`.
+    ///
+    /// Each source tried starts its report over.
+    #[must_use]
+    pub fn progress(self, progress: Option<Arc<dyn Reporter>>) -> Self {
+        Self { progress, ..self }
+    }
+
+    /// Specify what to do with memory that cannot be read. Memory zeroed or
+    /// skipped is listed in [`Summary::unreadable`]. With a baseline, pages
+    /// that cannot be read keep the baseline's contents either way.
+    #[must_use]
+    pub fn on_read_error(self, on_read_error: OnReadError) -> Self {
+        Self {
+            on_read_error,
+            ..self
+        }
+    }
+
+    /// Read memory on `readers` threads at once, which can be faster on
+    /// hosts with a lot of memory. Records are still written in ascending
+    /// order, and up to `2 * readers` blocks of 16 MiB are held in memory at
+    /// a time.
+    #[must_use]
+    pub fn readers(self, readers: NonZeroUsize) -> Self {
+        Self { readers, ..self }
+    }
+
+    /// Read `/dev/crash`, `/dev/mem`, `/proc/kcore`, and any
+    /// [`MemorySource`] that needs page-aligned reads `read_size` bytes at a
+    /// time, rounded down to whole pages. If `None`, the largest read
+    /// size the source accepts is detected with a test read.
+    ///
+    /// A source that rejects multi-page reads with `EINVAL` is read a page
+    /// at a time.
+    #[must_use]
+    pub fn read_size(self, read_size: Option<NonZeroUsize>) -> Self {
+        Self { read_size, ..self }
+    }
+
+    /// Stop between blocks once `cancel` is cancelled, failing with an error
+    /// whose [`FailureKind`] is [`FailureKind::Cancelled`]. Other sources
+    /// are not tried after a cancelled one.
+    #[cfg(feature = "async")]
+    #[must_use]
+    pub fn cancel(self, cancel: Option<CancellationToken>) -> Self {
+        Self { cancel, ..self }
+    }
+
+    /// Apply the incremental, rate limiting, progress, and read error
+    /// settings to `image`.
+    fn configure<R: Read, W: Write>(&self, image: &mut Image<R, W>) -> Result<()> {
+        image.incremental = Incremental::open(self.baseline.as_deref(), self.manifest.as_deref())?;
+        image.progress = Some(Tracker::new(self.progress.clone(), Operation::Acquire));
+        image.rate_limit = self
+            .max_rate
+            .map(|rate| RateLimit::new(rate.saturating_mul(ONE_MIB_NZ)));
+        image.on_read_error = self.on_read_error;
+        #[cfg(feature = "async")]
+        image.cancel.clone_from(&self.cancel);
+        Ok(())
+    }
+
+    /// Write `blocks` of `memory_source` to `image`, with the read size and
+    /// as many readers as configured.
+    fn write_blocks<W: Write>(
+        &self,
+        image: &mut Image<SourceReader<'_>, W>,
+        memory_source: &dyn MemorySource,
+        blocks: &[Block],
+    ) -> Result<()> {
+        if image.align_src {
+            image.read_size = self
+                .read_size
+                .map_or_else(|| detect_read_size(memory_source, blocks), whole_pages);
+        }
+        if self.readers.get() > 1 {
+            image.write_blocks_parallel(memory_source, blocks, self.readers)?;
+        } else {
+            image.write_blocks(blocks)?;
+        }
+        Ok(())
+    }
+
+    /// Describe what `image` captured from `source`, having read `blocks`.
+    fn summarize<R: Read, W: Write>(
+        &self,
+        source: Option<&Source>,
+        image: &Image<R, W>,
+        blocks: &[Block],
+    ) -> Summary {
+        let progress = image.progress();
+        Summary {
+            source: source.cloned(),
+            memory_source: self
+                .memory_source
+                .as_ref()
+                .map(|memory_source| memory_source.name()),
+            format: self.format,
+            bytes_read: progress.map_or(0, |p| p.bytes_read),
+            bytes_written: progress.map_or(0, |p| p.bytes_written),
+            ranges: blocks.iter().map(|block| block.range.clone()).collect(),
+            elapsed: progress.map_or(Duration::ZERO, |p| p.elapsed),
+            sha256: None,
+            volumes: Vec::new(),
+            zero_filled: self.baseline.is_none()
+                && image
+                    .progress
+                    .as_ref()
+                    .is_some_and(Tracker::only_zeros_above_first_mib),
+            unreadable: image.unreadable().to_vec(),
+        }
+    }
+
+    /// As [`Self::summarize`], adding the digest of the destination.
+    fn summarize_digest<R: Read, W: Write>(
+        &self,
+        source: Option<&Source>,
+        image: Image<R, DigestWriter<W>>,
+        blocks: &[Block],
+    ) -> Summary {
+        let summary = self.summarize(source, &image, blocks);
+        let (_, sha256) = image.dst.finish();
+        Summary {
+            sha256: Some(sha256),
+            ..summary
         }
-        .map_err(|e| Error::UnableToCreateSnapshotFromSource {
-            src: src.clone(),
-            source: Box::new(e),
-        })
     }
 
-    /// Create a memory snapshot
+    fn create_source(&self, src: &Source) -> Result<Summary> {
+        FileSource::open_source(src.clone(), &self.memory_ranges)
+            .and_then(|file_source| self.acquire(Some(src), &file_source))
+            .map_err(|e| Error::UnableToCreateSnapshotFromSource {
+                src: src.clone(),
+                source: Box::new(e),
+            })
+    }
+
+    /// Create a memory snapshot, returning what it captured.
     ///
     /// # Errors
     /// Returns an error if:
@@ -273,37 +744,44 @@ impl<'a> Snapshot<'a> {
     /// - There is a failure reading from the specified source
     /// - The estimated disk usage exceeds the specified limits
     /// - Failed to create or write to the destination file
-    pub fn create(&self) -> Result<()> {
+    pub fn create(&self) -> Result<Summary> {
+        if let Some(ref memory_source) = self.memory_source {
+            return self.acquire(None, &**memory_source).map_err(|e| {
+                Error::UnableToCreateSnapshotFromMemorySource {
+                    name: memory_source.name(),
+                    source: Box::new(e),
+                }
+            });
+        }
         if let Some(ref src) = self.source {
-            self.create_source(src)?;
-        } else if self.destination == Path::new("/dev/stdout") {
+            return self.create_source(src);
+        }
+        if *self.destination == *Path::new("/dev/stdout") {
             let src = Self::probe_single_source()?;
-            self.create_source(&src)?;
-        } else {
-            let crash = match self.create_source(&Source::DevCrash) {
-                Ok(()) => return Ok(()),
-                Err(e) if e.is_disk_usage_exceeded() => return Err(e),
-                Err(e) => Box::new(e),
-            };
-            let kcore = match self.create_source(&Source::ProcKcore) {
-                Ok(()) => return Ok(()),
-                Err(e) if e.is_disk_usage_exceeded() => return Err(e),
-                Err(e) => Box::new(e),
-            };
-            let devmem = match self.create_source(&Source::DevMem) {
-                Ok(()) => return Ok(()),
-                Err(e) if e.is_disk_usage_exceeded() => return Err(e),
-                Err(e) => Box::new(e),
-            };
-
-            return Err(Error::AllSourcesFailed {
-                crash,
-                kcore,
-                devmem,
-            });
+            return self.create_source(&src);
         }
 
-        Ok(())
+        let crash = match self.create_source(&Source::DevCrash) {
+            Ok(summary) => return Ok(summary),
+            Err(e) if e.is_disk_usage_exceeded() || e.is_cancelled() => return Err(e),
+            Err(e) => Box::new(e),
+        };
+        let kcore = match self.create_source(&Source::ProcKcore) {
+            Ok(summary) => return Ok(summary),
+            Err(e) if e.is_disk_usage_exceeded() || e.is_cancelled() => return Err(e),
+            Err(e) => Box::new(e),
+        };
+        let devmem = match self.create_source(&Source::DevMem) {
+            Ok(summary) => return Ok(summary),
+            Err(e) if e.is_disk_usage_exceeded() || e.is_cancelled() => return Err(e),
+            Err(e) => Box::new(e),
+        };
+
+        Err(Error::AllSourcesFailed {
+            crash,
+            kcore,
+            devmem,
+        })
     }
 
     /// Probe for an available source without trying multiple. Used when the
@@ -315,7 +793,7 @@ impl<'a> Snapshot<'a> {
     /// # Errors
     /// Returns `NoSourceAvailable` if none of the three probes succeed.
     pub fn probe_single_source() -> Result<Source> {
-        if is_kcore_ok() {
+        if is_kcore_ok(Path::new("/proc/kcore")) {
             Ok(Source::ProcKcore)
         } else if can_open(Path::new("/dev/crash")) {
             Ok(Source::DevCrash)
@@ -343,22 +821,96 @@ impl<'a> Snapshot<'a> {
     /// - No source is available
     /// - There is a failure reading from the source
     /// - Writing to `dst` fails
-    pub fn create_to_writer<W: Write>(&self, dst: W) -> Result<()> {
+    pub fn create_to_writer<W: Write>(&self, dst: W) -> Result<Summary> {
+        if let Some(ref memory_source) = self.memory_source {
+            return self
+                .acquire_to_writer(None, &**memory_source, dst)
+                .map_err(|e| Error::UnableToCreateSnapshotFromMemorySource {
+                    name: memory_source.name(),
+                    source: Box::new(e),
+                });
+        }
         let source = match self.source {
             Some(ref s) => s.clone(),
             None => Self::probe_single_source()?,
         };
 
-        match source {
-            Source::ProcKcore => self.kcore_to_writer(dst),
-            Source::DevCrash => self.phys_to_writer(Path::new("/dev/crash"), dst),
-            Source::DevMem => self.phys_to_writer(Path::new("/dev/mem"), dst),
-            Source::Raw(ref s) => self.phys_to_writer(s, dst),
-        }
-        .map_err(|e| Error::UnableToCreateSnapshotFromSource {
-            src: source,
-            source: Box::new(e),
+        FileSource::open_source(source.clone(), &self.memory_ranges)
+            .and_then(|file_source| self.acquire_to_writer(Some(&source), &file_source, dst))
+            .map_err(|e| Error::UnableToCreateSnapshotFromSource {
+                src: source,
+                source: Box::new(e),
+            })
+    }
+
+    /// Stream a memory snapshot to an asynchronous writer, as
+    /// [`Self::create_to_writer`] does.
+    ///
+    /// Memory is read on a blocking thread of the current Tokio runtime.
+    /// Once the token given to [`Self::cancel`] is cancelled, or the
+    /// returned future is dropped, the acquisition stops before the next
+    /// block, and fails with an error whose [`FailureKind`] is
+    /// [`FailureKind::Cancelled`]. `dst` is shut down once the snapshot is
+    /// written.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - No source is available
+    /// - There is a failure reading from the source
+    /// - Writing to or shutting down `dst` fails
+    /// - The acquisition is cancelled
+    #[cfg(feature = "async")]
+    pub async fn create_async<W>(self, dst: W) -> crate::Result<Summary>
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        // cancel the acquisition, but not the caller's token, if this
+        // future is dropped
+        let cancel = self
+            .cancel
+            .as_ref()
+            .map_or_else(CancellationToken::new, CancellationToken::child_token);
+        let _cancel_on_drop = cancel.clone().drop_guard();
+        let snapshot = self.into_owned().cancel(Some(cancel));
+        let mut bridge = SyncIoBridge::new(dst);
+
+        spawn_blocking(move || -> crate::Result<Summary> {
+            let summary = snapshot.create_to_writer(&mut bridge)?;
+            bridge.shutdown().map_err(|source| crate::Error::Io {
+                context: "unable to finish writing snapshot",
+                source,
+            })?;
+            Ok(summary)
         })
+        .await
+        .map_err(|e| crate::Error::Io {
+            context: "snapshot task failed",
+            source: std::io::Error::other(e),
+        })?
+    }
+
+    /// The same snapshot, owning its paths.
+    #[must_use]
+    pub fn into_owned(self) -> Snapshot<'static> {
+        Snapshot {
+            source: self.source,
+            destination: Cow::Owned(self.destination.into_owned()),
+            memory_ranges: self.memory_ranges,
+            format: self.format,
+            max_disk_usage: self.max_disk_usage,
+            max_disk_usage_percentage: self.max_disk_usage_percentage,
+            baseline: self.baseline.map(|path| Cow::Owned(path.into_owned())),
+            manifest: self.manifest.map(|path| Cow::Owned(path.into_owned())),
+            split_size: self.split_size,
+            max_rate: self.max_rate,
+            progress: self.progress,
+            on_read_error: self.on_read_error,
+            readers: self.readers,
+            read_size: self.read_size,
+            #[cfg(feature = "async")]
+            cancel: self.cancel,
+            memory_source: self.memory_source,
+        }
     }
 
     // given a set of ranges from iomem and a set of Blocks derived from the
@@ -370,7 +922,7 @@ impl<'a> Snapshot<'a> {
     // Block whose `offset` points at the corresponding position inside the
     // kcore source. Sections of `range` that fall in gaps between PT_LOAD
     // segments are skipped -- those addresses are not readable via kcore.
-    fn find_kcore_blocks(ranges: &[Range<u64>], headers: &[Block]) -> Vec<Block> {
+    pub(crate) fn find_kcore_blocks(ranges: &[Range<u64>], headers: &[Block]) -> Vec<Block> {
         let mut result = vec![];
 
         'outer: for range in ranges {
@@ -415,7 +967,7 @@ impl<'a> Snapshot<'a> {
     #[cfg(target_family = "unix")]
     fn check_disk_usage<R: Read + Seek, W: Write>(&self, _: &Image<R, W>) -> Result<()> {
         disk_usage::check(
-            self.destination,
+            &self.destination,
             &self.memory_ranges,
             self.max_disk_usage,
             self.max_disk_usage_percentage,
@@ -433,47 +985,6 @@ impl<'a> Snapshot<'a> {
         Ok(())
     }
 
-    fn kcore(&self) -> Result<()> {
-        if !is_kcore_ok() {
-            return Err(Error::LockedDownKcore);
-        }
-
-        let mut image =
-            Image::<File, File>::new(self.format, Path::new("/proc/kcore"), self.destination)?;
-        self.check_disk_usage(&image)?;
-        Self::write_kcore_blocks(&mut image, &self.memory_ranges)
-    }
-
-    fn kcore_to_writer<W: Write>(&self, dst: W) -> Result<()> {
-        if !is_kcore_ok() {
-            return Err(Error::LockedDownKcore);
-        }
-
-        let mut image = Image::<File, W>::with_dst(self.format, Path::new("/proc/kcore"), dst)?;
-        Self::write_kcore_blocks(&mut image, &self.memory_ranges)
-    }
-
-    fn write_kcore_blocks<W: Write>(
-        image: &mut Image<File, W>,
-        memory_ranges: &[Range<u64>],
-    ) -> Result<()> {
-        let file = elf::ElfStream::<NativeEndian, _>::open_stream(&mut image.src)?;
-        let physical_ranges = Self::physical_ranges_from_segments(file.segments());
-
-        if physical_ranges.is_empty() {
-            return Err(Error::KcoreParse(
-                "no usable PT_LOAD segments in /proc/kcore",
-            ));
-        }
-        if memory_ranges.is_empty() {
-            return Err(Error::KcoreParse("no initial memory range"));
-        }
-
-        let blocks = Self::find_kcore_blocks(memory_ranges, &physical_ranges);
-        image.write_blocks(&blocks)?;
-        Ok(())
-    }
-
     // Translate /proc/kcore PT_LOAD segments into physical-address Blocks,
     // sorted ascending by p_paddr.
     //
@@ -481,7 +992,7 @@ impl<'a> Snapshot<'a> {
     // ELFCLASS64, u32::MAX widened on ELFCLASS32) are kernel virtual-only
     // mappings (vmalloc, modules) with no physical backing; skip them.
     // Zero-length segments are also skipped.
-    fn physical_ranges_from_segments<I>(segments: I) -> Vec<Block>
+    pub(crate) fn physical_ranges_from_segments<I>(segments: I) -> Vec<Block>
     where
         I: IntoIterator,
         I::Item: core::borrow::Borrow<ProgramHeader>,
@@ -515,22 +1026,112 @@ impl<'a> Snapshot<'a> {
         blocks
     }
 
-    fn phys(&self, mem: &Path) -> Result<()> {
-        let blocks = Self::phys_blocks(mem, &self.memory_ranges);
-        let mut image = Image::<File, File>::new(self.format, mem, self.destination)?;
+    // `write` acquires memory into the image `open` returns, with its
+    // destination replaced by the volumes of a split snapshot, returning the
+    // blocks read.
+    fn create_volumes<R, O, F>(
+        &self,
+        source: Option<&Source>,
+        split_size: NonZeroU64,
+        open: O,
+        write: F,
+    ) -> Result<Summary>
+    where
+        R: Read,
+        O: FnOnce() -> Result<Image<R, Sink>>,
+        F: FnOnce(&mut Image<R, &mut Volumes>) -> Result<Vec<Block>>,
+    {
+        let mut volumes = Volumes::create(
+            &self.destination,
+            split_size.saturating_mul(ONE_MIB_NZ),
+            &self.memory_ranges,
+            self.max_disk_usage,
+            self.max_disk_usage_percentage,
+        )?;
+        let mut image = open()?.map_dst(|_| &mut volumes);
+        image.flush_records = true;
+        self.configure(&mut image)?;
+        let blocks = write(&mut image)?;
+        let summary = self.summarize(source, &image, &blocks);
+        Ok(Summary {
+            volumes: volumes.finish()?,
+            ..summary
+        })
+    }
+
+    // acquire from `memory_source`, read as the built-in `source` if given
+    fn acquire(
+        &self,
+        source: Option<&Source>,
+        memory_source: &dyn MemorySource,
+    ) -> Result<Summary> {
+        let blocks = Self::source_blocks(memory_source, &self.memory_ranges)?;
+        if let Some(split_size) = self.split_size {
+            return self.create_volumes(
+                source,
+                split_size,
+                || Ok(self.source_image(memory_source, sink())),
+                |image| {
+                    self.write_blocks(image, memory_source, &blocks)?;
+                    image.finish()?;
+                    Ok(blocks)
+                },
+            );
+        }
+
+        let dst = open_dst(&self.destination)?;
+        let mut image = self.source_image(memory_source, DigestWriter::new(dst));
         self.check_disk_usage(&image)?;
-        image.write_blocks(&blocks)?;
-        Ok(())
+        self.configure(&mut image)?;
+        self.write_blocks(&mut image, memory_source, &blocks)?;
+        image.finish()?;
+        Ok(self.summarize_digest(source, image, &blocks))
     }
 
-    fn phys_to_writer<W: Write>(&self, mem: &Path, dst: W) -> Result<()> {
-        let blocks = Self::phys_blocks(mem, &self.memory_ranges);
-        let mut image = Image::<File, W>::with_dst(self.format, mem, dst)?;
-        image.write_blocks(&blocks)?;
-        Ok(())
+    fn acquire_to_writer<W: Write>(
+        &self,
+        source: Option<&Source>,
+        memory_source: &dyn MemorySource,
+        dst: W,
+    ) -> Result<Summary> {
+        let blocks = Self::source_blocks(memory_source, &self.memory_ranges)?;
+        let mut image = self.source_image(memory_source, DigestWriter::new(dst));
+        self.configure(&mut image)?;
+        self.write_blocks(&mut image, memory_source, &blocks)?;
+        image.finish()?;
+        Ok(self.summarize_digest(source, image, &blocks))
     }
 
-    fn phys_blocks(mem: &Path, memory_ranges: &[Range<u64>]) -> Vec<Block> {
+    // an image reading from `memory_source`, in page-aligned reads if the
+    // source needs them
+    fn source_image<'s, W: Write>(
+        &self,
+        memory_source: &'s dyn MemorySource,
+        dst: W,
+    ) -> Image<SourceReader<'s>, W> {
+        let mut image = Image::from_streams(self.format, SourceReader::new(memory_source), dst);
+        image.align_src = memory_source.page_aligned();
+        image
+    }
+
+    // the ranges of `memory_ranges` `memory_source` can read, as blocks
+    // addressed by physical address
+    fn source_blocks(
+        memory_source: &dyn MemorySource,
+        memory_ranges: &[Range<u64>],
+    ) -> Result<Vec<Block>> {
+        Ok(memory_source
+            .ranges(memory_ranges)
+            .map_err(Error::MemoryRanges)?
+            .into_iter()
+            .map(|range| Block {
+                offset: range.start,
+                range,
+            })
+            .collect())
+    }
+
+    pub(crate) fn phys_blocks(mem: &Path, memory_ranges: &[Range<u64>]) -> Vec<Block> {
         let is_crash = mem == Path::new("/dev/crash");
         memory_ranges
             .iter()
@@ -769,4 +1370,221 @@ mod tests {
 
         assert_eq!(Snapshot::find_kcore_blocks(&ranges, &core_ranges), expected);
     }
+
+    #[test]
+    fn spreads_test_reads_above_the_first_mib() {
+        assert_eq!(
+            test_read_pages(&[0x1000..0x9_f000, 0xa_0000..0x10_0000]),
+            Vec::<u64>::new()
+        );
+        assert_eq!(
+            test_read_pages(&[0x1000..0x9_f000, 0x10_0800..0x10_3800]),
+            vec![0x10_1000, 0x10_2000]
+        );
+
+        // 32 pages in two ranges, read every other page
+        let pages = test_read_pages(&[0x10_0000..0x10_f000, 0x20_0000..0x21_1000]);
+        assert_eq!(pages.len(), 16);
+        assert_eq!(pages.get(7), Some(&0x10_e000));
+        assert_eq!(pages.get(8), Some(&0x20_1000));
+    }
+
+    #[test]
+    fn test_read_fails_on_zeros_or_short_reads() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(Error::Disk)?;
+        let zeros = dir.path().join("zeros.raw");
+        let mut memory = vec![1; 0x10_0000];
+        memory.resize(0x11_0000, 0);
+        std::fs::write(&zeros, &memory).map_err(Error::Disk)?;
+        let mut src = File::open(&zeros).map_err(Error::Disk)?;
+
+        let low = 0x1000..0x9_f000;
+        test_read_above_first_mib(&mut src, &[low.clone(), 0x9_f000..0xa_0000])
+            .map_err(Error::Disk)?;
+        let zero_read = test_read_above_first_mib(&mut src, &[low.clone(), 0x10_0000..0x11_0000]);
+        assert!(zero_read.is_err_and(|e| e.kind() == std::io::ErrorKind::Other));
+        let short_read = test_read_above_first_mib(&mut src, &[low.clone(), 0x10_0000..0x20_0000]);
+        assert!(short_read.is_err_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof));
+
+        let data = dir.path().join("data.raw");
+        memory.extend_from_slice(&[1; 0x1000]);
+        std::fs::write(&data, &memory).map_err(Error::Disk)?;
+        let mut src_with_data = File::open(&data).map_err(Error::Disk)?;
+        test_read_above_first_mib(&mut src_with_data, &[low, 0x10_0000..0x11_1000])
+            .map_err(Error::Disk)?;
+        Ok(())
+    }
+
+    #[test]
+    fn summarizes_a_raw_snapshot() -> Result<()> {
+        use crate::io::digest::to_hex;
+        use sha2::{Digest as _, Sha256};
+
+        let dir = tempfile::tempdir().map_err(Error::Disk)?;
+        let src = dir.path().join("memory.raw");
+        let dst = dir.path().join("memory.lime");
+        let mut memory = vec![0; 0x2000];
+        memory.extend_from_slice(&[1; 0x1000]);
+        std::fs::write(&src, &memory).map_err(Error::Disk)?;
+
+        let summary = Snapshot::new(&dst, vec![0x0..0x2000, 0x2000..0x3000])
+            .source(Some(Source::Raw(src.clone())))
+            .create()?;
+
+        let written = std::fs::read(&dst).map_err(Error::Disk)?;
+        assert!(matches!(summary.source, Some(Source::Raw(ref path)) if *path == src));
+        assert_eq!(summary.format, Format::Lime);
+        assert_eq!(summary.bytes_read, 0x3000);
+        assert_eq!(summary.bytes_written, 32 + 0x1000);
+        assert_eq!(summary.ranges, vec![0x0..0x2000, 0x2000..0x3000]);
+        assert_eq!(summary.sha256, Some(to_hex(&Sha256::digest(&written))));
+        assert!(summary.volumes.is_empty());
+        assert!(!summary.zero_filled);
+        Ok(())
+    }
+
+    #[test]
+    fn flags_zeros_above_the_first_mib() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(Error::Disk)?;
+        let src = dir.path().join("memory.raw");
+        let dst = dir.path().join("memory.lime");
+        let mut memory = vec![1; 0x1000];
+        memory.resize(0x20_0000, 0);
+        std::fs::write(&src, &memory).map_err(Error::Disk)?;
+
+        let summary = Snapshot::new(&dst, vec![0x0..0x1000, 0x10_0000..0x20_0000])
+            .source(Some(Source::Raw(src)))
+            .create()?;
+        assert!(summary.zero_filled);
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn creates_a_snapshot_asynchronously()
+    -> core::result::Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("memory.raw");
+        let expected = dir.path().join("expected.lime");
+        let dst = dir.path().join("memory.lime");
+        let mut memory = vec![0; 0x2000];
+        memory.extend_from_slice(&[1; 0x1000]);
+        std::fs::write(&src, &memory)?;
+        let ranges = vec![0x0..0x2000, 0x2000..0x3000];
+
+        Snapshot::new(&expected, ranges.clone())
+            .source(Some(Source::Raw(src.clone())))
+            .create()?;
+        let cancel = CancellationToken::new();
+        let summary = Snapshot::new(&dst, ranges.clone())
+            .source(Some(Source::Raw(src.clone())))
+            .cancel(Some(cancel.clone()))
+            .create_async(tokio::fs::File::create(&dst).await?)
+            .await?;
+        assert_eq!(summary.bytes_read, 0x3000);
+        assert_eq!(std::fs::read(&dst)?, std::fs::read(&expected)?);
+        assert!(!cancel.is_cancelled());
+
+        cancel.cancel();
+        let cancelled = Snapshot::new(&dst, ranges)
+            .source(Some(Source::Raw(src)))
+            .cancel(Some(cancel))
+            .create_async(tokio::fs::File::create(&dst).await?)
+            .await;
+        assert!(cancelled.is_err_and(|e| e.failure_kind() == FailureKind::Cancelled));
+        Ok(())
+    }
+
+    #[test]
+    fn acquires_from_a_memory_source() -> core::result::Result<(), Box<dyn core::error::Error>> {
+        use crate::source::{FileSource, MemorySource};
+
+        // memory whose byte at each address is the low byte of its page
+        struct Pages;
+
+        impl MemorySource for Pages {
+            fn name(&self) -> String {
+                "pages".to_string()
+            }
+
+            fn ranges(&self, memory_ranges: &[Range<u64>]) -> std::io::Result<Vec<Range<u64>>> {
+                Ok(memory_ranges.to_vec())
+            }
+
+            fn read_at(&self, addr: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+                let page = u8::try_from((addr / PAGE_SIZE) % 256).unwrap_or_default();
+                let len = buf.len().min(0x100);
+                buf.iter_mut().take(len).for_each(|x| *x = page);
+                Ok(len)
+            }
+        }
+
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("memory.raw");
+        let expected = dir.path().join("expected.lime");
+        let dst = dir.path().join("memory.lime");
+        let memory = (0..0x3000_u64)
+            .map(|addr| u8::try_from(addr / PAGE_SIZE).unwrap_or_default())
+            .collect::<Vec<_>>();
+        std::fs::write(&src, &memory)?;
+        let ranges = vec![0x1000..0x2000, 0x2000..0x3000];
+
+        Snapshot::new(&expected, ranges.clone())
+            .source(Some(Source::Raw(src.clone())))
+            .create()?;
+        let summary = Snapshot::new(&dst, ranges.clone())
+            .memory_source(Some(Arc::new(Pages)))
+            .create()?;
+        assert!(summary.source.is_none());
+        assert_eq!(summary.memory_source.as_deref(), Some("pages"));
+        assert_eq!(std::fs::read(&dst)?, std::fs::read(&expected)?);
+
+        let mut streamed = Vec::new();
+        Snapshot::new(&dst, ranges)
+            .memory_source(Some(Arc::new(FileSource::open(Source::Raw(src), &[])?)))
+            .readers(NonZeroUsize::new(4).ok_or("zero readers")?)
+            .create_to_writer(&mut streamed)?;
+        assert_eq!(streamed, std::fs::read(&expected)?);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert!(matches!(
+            "/proc/kcore".parse::<Source>(),
+            Ok(Source::ProcKcore)
+        ));
+        assert!(matches!(
+            "path:/tmp/memory.raw".parse::<Source>(),
+            Ok(Source::Raw(ref path)) if path == Path::new("/tmp/memory.raw")
+        ));
+        assert!(
+            "path:"
+                .parse::<Source>()
+                .is_err_and(|e| e.contains("needs a file"))
+        );
+        assert!(
+            "memory.raw"
+                .parse::<Source>()
+                .is_err_and(|e| e.contains("isn't a valid source"))
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn stops_once_cancelled() -> Result<()> {
+        let dir = tempfile::tempdir().map_err(Error::Disk)?;
+        let src = dir.path().join("memory.raw");
+        let dst = dir.path().join("memory.lime");
+        std::fs::write(&src, [1; 0x2000]).map_err(Error::Disk)?;
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = Snapshot::new(&dst, vec![0x0..0x1000, 0x1000..0x2000])
+            .source(Some(Source::Raw(src)))
+            .cancel(Some(cancel))
+            .create();
+        assert!(result.is_err_and(|e| e.code() == "cancelled" && e.is_cancelled()));
+        Ok(())
+    }
 }
@@ -0,0 +1,267 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Pluggable sources of physical memory.
+//!
+//! [`Snapshot::memory_source`] acquires from any [`MemorySource`], such as
+//! a memory file provided by a hypervisor, a test fixture, or a custom
+//! driver. [`FileSource`] implements it for each built-in [`Source`], and
+//! is how [`Snapshot`] reads them.
+//!
+//! [`Snapshot::memory_source`]: crate::Snapshot::memory_source
+
+use crate::{
+    image::{Block, open_src},
+    snapshot::{Error, Snapshot, Source, is_kcore_ok, test_read_above_first_mib},
+};
+use core::ops::Range;
+use elf::endian::NativeEndian;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::FileExt as _;
+#[cfg(target_family = "windows")]
+use std::os::windows::fs::FileExt as _;
+use std::{
+    fs::File,
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+};
+
+/// A source of physical memory to acquire a snapshot from.
+pub trait MemorySource: Send + Sync {
+    /// Describes the source in summaries and error messages.
+    fn name(&self) -> String;
+
+    /// The ranges of physical memory to acquire, given the System RAM
+    /// ranges of `/proc/iomem`. Ranges must be sorted ascending and must
+    /// not overlap.
+    ///
+    /// # Errors
+    /// Returns an error if the layout of the source cannot be determined.
+    fn ranges(&self, memory_ranges: &[Range<u64>]) -> IoResult<Vec<Range<u64>>>;
+
+    /// Read memory at physical address `addr` into `buf`, returning how
+    /// many bytes were read, as [`Read::read`] does.
+    ///
+    /// # Errors
+    /// Returns an error if the memory cannot be read.
+    fn read_at(&self, addr: u64, buf: &mut [u8]) -> IoResult<usize>;
+
+    /// Whether reads must be page-aligned and a whole number of pages, as
+    /// memory devices require.
+    fn page_aligned(&self) -> bool {
+        false
+    }
+}
+
+/// A built-in [`Source`], read through its device or file.
+pub struct FileSource {
+    source: Source,
+    file: File,
+    // for `/proc/kcore`, where each PT_LOAD segment's physical memory is in
+    // the file
+    segments: Option<Vec<Block>>,
+    // for memory devices, which need page-aligned reads
+    aligned: bool,
+}
+
+impl FileSource {
+    /// Open `source` for reading the System RAM ranges of `/proc/iomem`
+    /// given in `memory_ranges`.
+    ///
+    /// `/dev/mem` is test read above the first MiB, so a kernel built with
+    /// `CONFIG_STRICT_DEVMEM` is caught before anything is acquired.
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - `/proc/kcore` is locked down, or has no usable `PT_LOAD` segments
+    /// - `memory_ranges` is empty for `/proc/kcore`
+    /// - `/dev/mem` is restricted to the first MiB
+    /// - The file cannot be opened
+    pub fn open(source: Source, memory_ranges: &[Range<u64>]) -> crate::Result<Self> {
+        Ok(Self::open_source(source, memory_ranges)?)
+    }
+
+    /// As [`Self::open`], failing with the error [`Snapshot`] reports.
+    pub(crate) fn open_source(source: Source, memory_ranges: &[Range<u64>]) -> Result<Self, Error> {
+        if matches!(source, Source::ProcKcore) && !is_kcore_ok(source.path()) {
+            return Err(Error::LockedDownKcore);
+        }
+        let (mut file, aligned) = open_src(source.path())?;
+        let segments = match source {
+            Source::ProcKcore => {
+                let elf = elf::ElfStream::<NativeEndian, _>::open_stream(&mut file)?;
+                let segments = Snapshot::physical_ranges_from_segments(elf.segments());
+                if segments.is_empty() {
+                    return Err(Error::KcoreParse(
+                        "no usable PT_LOAD segments in /proc/kcore",
+                    ));
+                }
+                if memory_ranges.is_empty() {
+                    return Err(Error::KcoreParse("no initial memory range"));
+                }
+                Some(segments)
+            }
+            Source::DevMem => {
+                test_read_above_first_mib(&mut file, memory_ranges).map_err(Error::StrictDevMem)?;
+                None
+            }
+            Source::DevCrash | Source::Raw(_) => None,
+        };
+        Ok(Self {
+            source,
+            file,
+            segments,
+            aligned,
+        })
+    }
+}
+
+impl MemorySource for FileSource {
+    fn name(&self) -> String {
+        self.source.to_string()
+    }
+
+    fn ranges(&self, memory_ranges: &[Range<u64>]) -> IoResult<Vec<Range<u64>>> {
+        if let Some(ref segments) = self.segments {
+            return Ok(Snapshot::find_kcore_blocks(memory_ranges, segments)
+                .into_iter()
+                .map(|block| block.range)
+                .collect());
+        }
+        Ok(Snapshot::phys_blocks(self.source.path(), memory_ranges)
+            .into_iter()
+            .map(|block| block.range)
+            .collect())
+    }
+
+    fn read_at(&self, addr: u64, buf: &mut [u8]) -> IoResult<usize> {
+        match self.segments {
+            Some(ref segments) => read_mapped(&self.file, segments, addr, buf),
+            None => read_file_at(&self.file, addr, buf),
+        }
+    }
+
+    fn page_aligned(&self) -> bool {
+        self.aligned
+    }
+}
+
+/// Read the memory at physical address `addr` from `file`, in which
+/// `blocks` gives the offset of each range of physical memory. Reads stop
+/// at the end of the block `addr` is in.
+pub(crate) fn read_mapped(
+    file: &File,
+    blocks: &[Block],
+    addr: u64,
+    buf: &mut [u8],
+) -> IoResult<usize> {
+    let block = blocks
+        .iter()
+        .find(|block| block.range.contains(&addr))
+        .ok_or_else(|| {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                format!("{addr:#x} is not mapped by the source"),
+            )
+        })?;
+    let left = usize::try_from(block.range.end.saturating_sub(addr)).unwrap_or(usize::MAX);
+    let len = buf.len().min(left);
+    let offset = block
+        .offset
+        .saturating_add(addr.saturating_sub(block.range.start));
+    read_file_at(file, offset, buf.get_mut(..len).unwrap_or_default())
+}
+
+/// Fill `buf` with the memory at physical address `addr` of `source`.
+pub(crate) fn read_exact_at(
+    source: &dyn MemorySource,
+    mut addr: u64,
+    mut buf: &mut [u8],
+) -> IoResult<()> {
+    while !buf.is_empty() {
+        match source.read_at(addr, buf) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                addr = addr.saturating_add(u64::try_from(read).map_err(IoError::other)?);
+                buf = buf.get_mut(read..).unwrap_or_default();
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read_file_at(file: &File, offset: u64, buf: &mut [u8]) -> IoResult<usize> {
+    #[cfg(target_family = "unix")]
+    return file.read_at(buf, offset);
+    #[cfg(target_family = "windows")]
+    return file.seek_read(buf, offset);
+}
+
+/// Reads a [`MemorySource`] as a stream whose position is the physical
+/// address.
+pub(crate) struct SourceReader<'a> {
+    source: &'a dyn MemorySource,
+    position: u64,
+}
+
+impl<'a> SourceReader<'a> {
+    pub(crate) fn new(source: &'a dyn MemorySource) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+}
+
+impl Read for SourceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.source.read_at(self.position, buf)?;
+        self.position = self
+            .position
+            .saturating_add(u64::try_from(read).map_err(IoError::other)?);
+        Ok(read)
+    }
+}
+
+impl Seek for SourceReader<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => {
+                return Err(IoError::new(
+                    ErrorKind::Unsupported,
+                    "memory sources have no end to seek from",
+                ));
+            }
+        }
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileSource, MemorySource as _};
+    use crate::snapshot::Source;
+
+    #[test]
+    fn reads_a_raw_file_at_physical_addresses() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("memory.raw");
+        let memory = (0..0x2000_u32)
+            .map(|i| u8::try_from(i % 251).unwrap_or_default())
+            .collect::<Vec<_>>();
+        std::fs::write(&path, &memory)?;
+
+        let ranges = vec![0x0..0x800, 0x1000..0x1800];
+        let source = FileSource::open(Source::Raw(path), &ranges)?;
+        assert!(!source.page_aligned());
+        assert_eq!(source.ranges(&ranges)?, ranges);
+        let mut buf = [0; 0x10];
+        assert_eq!(source.read_at(0x1000, &mut buf)?, 0x10);
+        assert_eq!(Some(buf.as_slice()), memory.get(0x1000..0x1010));
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Cutting one snapshot into several smaller ones.
+//!
+//! Records are copied verbatim, without decoding or re-encoding, so every
+//! piece is an independently valid snapshot in the source's format.
+
+use crate::image::{Error, Header, copy_record};
+use core::num::NonZeroU64;
+use std::{
+    ffi::OsString,
+    fs::remove_file,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+/// Split the snapshot `src` at record boundaries into pieces of at most
+/// `max_size` bytes each, returning the number of pieces.
+///
+/// `next_dst` is called with the index of each piece, starting at 0, to
+/// open its destination. A record larger than `max_size` gets a piece to
+/// itself. An empty `src` produces no pieces.
+///
+/// # Errors
+/// Returns an error if `src` cannot be read or decoded, or opening or
+/// writing a destination fails.
+pub fn split<R, W, F>(mut src: R, max_size: NonZeroU64, mut next_dst: F) -> Result<usize>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(usize) -> Result<W>,
+{
+    let mut pieces = 0_usize;
+    let mut current: Option<W> = None;
+    let mut written = 0_u64;
+
+    while let Some(header) = Header::read_next(&mut src)? {
+        copy_record(&mut src, &header, |len| {
+            let fits = written
+                .checked_add(len)
+                .is_some_and(|total| total <= max_size.get());
+            if current.is_none() || (written > 0 && !fits) {
+                if let Some(mut dst) = current.take() {
+                    flush(&mut dst)?;
+                }
+                current = Some(next_dst(pieces)?);
+                pieces = pieces.checked_add(1).ok_or(Error::TooLarge)?;
+                written = 0;
+            }
+            written = written.saturating_add(len);
+            current.as_mut().ok_or(Error::TooLarge)
+        })?;
+    }
+
+    if let Some(mut dst) = current {
+        flush(&mut dst)?;
+    }
+    Ok(pieces)
+}
+
+/// The path of piece `number` of a snapshot split at `base`, counting from
+/// 1: `base.001`, `base.002`, and so on.
+///
+/// This naming is shared by `avml split` and by acquisitions with a
+/// maximum volume size.
+#[must_use]
+pub fn volume_path(base: &Path, number: usize) -> PathBuf {
+    let mut path = OsString::from(base.as_os_str());
+    path.push(format!(".{number:03}"));
+    PathBuf::from(path)
+}
+
+/// Remove the pieces of a snapshot split at `base` numbered after `count`,
+/// left behind by an earlier split into more pieces, returning their paths.
+///
+/// # Errors
+/// Returns an error if a piece cannot be removed.
+pub fn remove_volumes_after(base: &Path, count: usize) -> std::io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let mut number = count.saturating_add(1);
+    loop {
+        let path = volume_path(base, number);
+        match remove_file(&path) {
+            Ok(()) => removed.push(path),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(e),
+        }
+        number = number.saturating_add(1);
+    }
+}
+
+fn flush<W: Write>(mut dst: W) -> Result<()> {
+    dst.flush().map_err(|source| Error::Io {
+        context: "unable to flush destination",
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remove_volumes_after, split, volume_path};
+    use crate::image::{BlockData, BlockReader, Error, Format, filled_snapshot};
+    use core::{cell::RefCell, num::NonZeroU64, ops::Range};
+    use std::io::Write;
+
+    /// Appends to the last of a shared list of pieces.
+    struct Piece<'a>(&'a RefCell<Vec<Vec<u8>>>);
+
+    impl Write for Piece<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if let Some(piece) = self.0.borrow_mut().last_mut() {
+                piece.extend_from_slice(buf);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn split_all(src: &[u8], max_size: u64) -> Result<Vec<Vec<u8>>, Error> {
+        let max_size = NonZeroU64::new(max_size).ok_or(Error::TooLarge)?;
+        let pieces = RefCell::new(Vec::new());
+        let count = split(src, max_size, |index| {
+            let mut pieces_mut = pieces.borrow_mut();
+            assert_eq!(index, pieces_mut.len());
+            pieces_mut.push(Vec::new());
+            Ok(Piece(&pieces))
+        })?;
+        let pieces = pieces.into_inner();
+        assert_eq!(count, pieces.len());
+        Ok(pieces)
+    }
+
+    fn ranges(src: &[u8]) -> Result<Vec<Range<u64>>, Error> {
+        BlockReader::new(src)
+            .map(|block| block.map(|BlockData { range, .. }| range))
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_record_boundaries() -> Result<(), Error> {
+        let src = filled_snapshot(
+            Format::Lime,
+            &[
+                (0x0..0x1000, 1),
+                (0x1000..0x2000, 2),
+                (0x4000..0x5000, 3),
+                (0x8000..0xa000, 4),
+                (0xa000..0xb000, 5),
+            ],
+        )?;
+
+        // room for two records of a 32-byte header and a page each
+        let pieces = split_all(&src, 2 * (32 + 0x1000))?
+            .iter()
+            .map(|piece| ranges(piece))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            pieces,
+            vec![
+                vec![0x0..0x1000, 0x1000..0x2000],
+                vec![0x4000..0x5000],
+                // larger than `max_size` on its own
+                vec![0x8000..0xa000],
+                vec![0xa000..0xb000],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_pieces_are_valid_snapshots() -> Result<(), Error> {
+        let records = [(0x0..0x3000, 1), (0x3000..0x4000, 2), (0x9000..0xa000, 3)];
+        let src = filled_snapshot(Format::AvmlCompressed, &records)?;
+
+        let pieces = split_all(&src, 1)?;
+        assert_eq!(pieces.len(), records.len());
+        assert_eq!(pieces.concat(), src);
+        for (piece, &(ref range, fill)) in pieces.iter().zip(&records) {
+            let blocks = BlockReader::new(piece.as_slice()).collect::<Result<Vec<_>, _>>()?;
+            let len = usize::try_from(range.end.saturating_sub(range.start))?;
+            assert_eq!(
+                blocks,
+                vec![BlockData {
+                    range: range.clone(),
+                    data: vec![fill; len],
+                }]
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn volumes_are_numbered_from_one() -> Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let base = dir.path().join("memory.lime");
+        assert_eq!(volume_path(&base, 1), dir.path().join("memory.lime.001"));
+
+        for number in 1..=4 {
+            std::fs::write(volume_path(&base, number), [])?;
+        }
+        assert_eq!(
+            remove_volumes_after(&base, 2)?,
+            [volume_path(&base, 3), volume_path(&base, 4)]
+        );
+        assert!(volume_path(&base, 2).exists());
+        assert!(!volume_path(&base, 3).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn empty_source_has_no_pieces() -> Result<(), Error> {
+        assert_eq!(split_all(&[], 1)?, Vec::<Vec<u8>>::new());
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Acquiring the active swap areas of the host.
+//!
+//! Pages swapped out are missing from physical memory. [`SwapMemory`]
+//! reads each area listed in `/proc/swaps`, whether a partition, a swap
+//! file or a zram device, so a snapshot of it holds the swapped pages. The
+//! n-th area is at address `n * AREA_SPACING`, so each record's address
+//! gives the area and the offset within it.
+
+use crate::{
+    errors::FailureKind,
+    source::{MemorySource, read_file_at},
+};
+use core::{num::ParseIntError, ops::Range};
+use std::{
+    fs::{File, read_to_string},
+    io::{Error as IoError, ErrorKind, Result as IoResult, Seek as _, SeekFrom},
+    path::PathBuf,
+};
+
+/// The distance between the addresses of successive swap areas in a
+/// snapshot of them, which bounds the size of an area.
+pub const AREA_SPACING: u64 = 1 << 40;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("unable to read /proc/swaps")]
+    Swaps(#[source] IoError),
+
+    #[error("unable to open swap area {}", path.display())]
+    Open {
+        path: PathBuf,
+        #[source]
+        source: IoError,
+    },
+
+    #[error("swap area {} is larger than {AREA_SPACING} bytes", path.display())]
+    TooLarge { path: PathBuf },
+
+    #[error("unable to parse value")]
+    Parse(#[from] ParseIntError),
+
+    #[error("unable to parse line: {0}")]
+    ParseLine(String),
+}
+
+impl Error {
+    /// A stable identifier for this kind of failure, for tools that act on
+    /// failures without parsing messages.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Self::Swaps(_) => "swaps",
+            Self::Open { .. } => "swap_open",
+            Self::TooLarge { .. } => "swap_too_large",
+            Self::Parse(_) | Self::ParseLine(_) => "swaps_parse",
+        }
+    }
+
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Swaps(ref source) | Self::Open { ref source, .. } => FailureKind::from_io(source),
+            Self::TooLarge { .. } | Self::Parse(_) | Self::ParseLine(_) => FailureKind::Other,
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// An active swap area, as listed in `/proc/swaps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapArea {
+    /// The partition, file or zram device swapped to.
+    pub path: PathBuf,
+    /// `partition` or `file`.
+    pub kind: String,
+    /// Usable swap space, in bytes.
+    pub size: u64,
+    /// Swap space in use, in bytes.
+    pub used: u64,
+    pub priority: i32,
+}
+
+impl SwapArea {
+    // Parse a line of /proc/swaps, such as:
+    //
+    //   /dev/zram0    partition    8388604    1024    100
+    fn parse(line: &str) -> Result<Self> {
+        let invalid = || Error::ParseLine(line.to_string());
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().ok_or_else(invalid);
+        Ok(Self {
+            path: PathBuf::from(unescape(next()?)),
+            kind: next()?.to_string(),
+            size: next()?.parse::<u64>()?.saturating_mul(1024),
+            used: next()?.parse::<u64>()?.saturating_mul(1024),
+            priority: next()?.parse()?,
+        })
+    }
+}
+
+// /proc/swaps escapes whitespace and backslashes in paths as octal, such as
+// `\040` for a space
+fn unescape(path: &str) -> String {
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..3)
+            .and_then(|octal| core::str::from_utf8(octal).ok())
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+        match escaped {
+            Some(value) if byte == b'\\' => {
+                unescaped.push(value);
+                rest = tail.get(3..).unwrap_or_default();
+            }
+            _ => {
+                unescaped.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Parse the contents of `/proc/swaps`.
+///
+/// # Errors
+/// Returns an error if a line cannot be parsed.
+pub fn parse_swaps(swaps: &str) -> Result<Vec<SwapArea>> {
+    swaps
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(SwapArea::parse)
+        .collect()
+}
+
+/// The active swap areas of this host, in the order `/proc/swaps` lists
+/// them.
+///
+/// # Errors
+/// Returns an error if `/proc/swaps` cannot be read or parsed.
+pub fn areas() -> Result<Vec<SwapArea>> {
+    parse_swaps(&read_to_string("/proc/swaps").map_err(Error::Swaps)?)
+}
+
+/// The swap areas of a host, read through their devices and files.
+pub struct SwapMemory {
+    // each area, with the file it is read through and its size on disk,
+    // which includes the swap header and any bad pages
+    areas: Vec<(SwapArea, File, u64)>,
+}
+
+impl SwapMemory {
+    /// Open `areas` for reading.
+    ///
+    /// # Errors
+    /// Returns an error if an area cannot be opened, or is larger than
+    /// [`AREA_SPACING`].
+    pub fn open(areas: Vec<SwapArea>) -> Result<Self> {
+        let areas = areas
+            .into_iter()
+            .map(|area| {
+                let open_error = |source| Error::Open {
+                    path: area.path.clone(),
+                    source,
+                };
+                let mut file = File::open(&area.path).map_err(open_error)?;
+                let size = file.seek(SeekFrom::End(0)).map_err(open_error)?;
+                if size > AREA_SPACING {
+                    return Err(Error::TooLarge { path: area.path });
+                }
+                Ok((area, file, size))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { areas })
+    }
+
+    /// The areas acquired, with the address each starts at.
+    pub fn areas(&self) -> impl Iterator<Item = (u64, &SwapArea)> {
+        self.areas
+            .iter()
+            .zip(0_u64..)
+            .map(|(opened, index)| (index.saturating_mul(AREA_SPACING), &opened.0))
+    }
+}
+
+impl MemorySource for SwapMemory {
+    fn name(&self) -> String {
+        "swap".to_string()
+    }
+
+    // each area at its own address, rather than physical memory
+    fn ranges(&self, _memory_ranges: &[Range<u64>]) -> IoResult<Vec<Range<u64>>> {
+        Ok(self
+            .areas
+            .iter()
+            .zip(0_u64..)
+            .filter(|&(&(_, _, size), _)| size > 0)
+            .map(|(&(_, _, size), index)| {
+                let start = index.saturating_mul(AREA_SPACING);
+                start..start.saturating_add(size)
+            })
+            .collect())
+    }
+
+    fn read_at(&self, addr: u64, buf: &mut [u8]) -> IoResult<usize> {
+        let unmapped = || {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                format!("{addr:#x} is not in a swap area"),
+            )
+        };
+        let index = usize::try_from(addr / AREA_SPACING).map_err(|_| unmapped())?;
+        let offset = addr % AREA_SPACING;
+        let &(_, ref file, size) = self.areas.get(index).ok_or_else(unmapped)?;
+        let left = usize::try_from(size.saturating_sub(offset)).unwrap_or(usize::MAX);
+        let len = buf.len().min(left);
+        read_file_at(file, offset, buf.get_mut(..len).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AREA_SPACING, Error, SwapArea, SwapMemory, parse_swaps};
+    use crate::{Snapshot, image::BlockReader, source::MemorySource as _};
+    use std::{path::PathBuf, sync::Arc};
+
+    const SWAPS: &str = "\
+Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority
+/dev/zram0                              partition\t8388604\t\t1024\t\t100
+/var/swap\\040file                       file\t\t2097148\t\t0\t\t-2
+";
+
+    fn area(path: PathBuf) -> SwapArea {
+        SwapArea {
+            path,
+            kind: "file".to_string(),
+            size: 0,
+            used: 0,
+            priority: -2,
+        }
+    }
+
+    #[test]
+    fn parses_swaps() -> super::Result<()> {
+        let areas = parse_swaps(SWAPS)?;
+        assert_eq!(
+            areas,
+            [
+                SwapArea {
+                    path: PathBuf::from("/dev/zram0"),
+                    kind: "partition".to_string(),
+                    size: 8_388_604 * 1024,
+                    used: 1024 * 1024,
+                    priority: 100,
+                },
+                SwapArea {
+                    path: PathBuf::from("/var/swap file"),
+                    kind: "file".to_string(),
+                    size: 2_097_148 * 1024,
+                    used: 0,
+                    priority: -2,
+                },
+            ]
+        );
+        assert!(parse_swaps("Filename Type Size Used Priority\n").is_ok_and(|a| a.is_empty()));
+        assert!(
+            parse_swaps("Filename\n/dev/sda2 partition\n")
+                .is_err_and(|e| matches!(e, Error::ParseLine(_)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn acquires_swap_areas() -> Result<(), Box<dyn core::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        std::fs::write(&first, vec![0x11; 0x2000])?;
+        std::fs::write(&second, vec![0x22; 0x1000])?;
+
+        let swap = SwapMemory::open(vec![area(first), area(second)])?;
+        let ranges = swap.ranges(&[])?;
+        assert_eq!(ranges, [0..0x2000, AREA_SPACING..AREA_SPACING + 0x1000]);
+        assert_eq!(
+            swap.areas().map(|(start, _)| start).collect::<Vec<_>>(),
+            [0, AREA_SPACING]
+        );
+
+        let mut snapshot = Vec::new();
+        Snapshot::new(&dir.path().join("swap.lime"), ranges)
+            .memory_source(Some(Arc::new(swap)))
+            .create_to_writer(&mut snapshot)?;
+        let blocks = BlockReader::new(snapshot.as_slice()).collect::<Result<Vec<_>, _>>()?;
+        assert!(
+            blocks
+                .iter()
+                .any(|block| block.range.start == AREA_SPACING && block.data.first() == Some(&0x22))
+        );
+        Ok(())
+    }
+}
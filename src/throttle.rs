@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Keeping acquisition from competing with the workloads on the host.
+//!
+//! [`RateLimit`] caps the rate memory is read at by sleeping between
+//! blocks, so time spent waiting is not charged against a cgroup's CPU
+//! quota. Since every byte written is derived from memory read, the cap
+//! also bounds the rate of writes to disk or to a stream; uploads of a
+//! finished snapshot to blob storage are capped separately, with
+//! [`RateLimit::reserve`] telling the async uploader how long to wait.
+//! [`set_nice`] and [`set_io_priority`] lower the scheduling priority of
+//! the calling thread and of any threads it starts afterwards.
+
+use crate::errors::FailureKind;
+use core::{num::NonZeroU64, time::Duration};
+use std::{thread::sleep, time::Instant};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error: {context}")]
+    Io {
+        context: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid nice value {0}: must be between -20 and 19")]
+    InvalidNice(i32),
+
+    #[error("invalid best-effort I/O priority level {0}: must be between 0 and 7")]
+    InvalidIoLevel(u8),
+}
+
+impl Error {
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io { ref source, .. } => FailureKind::from_io(source),
+            Self::InvalidNice(_) | Self::InvalidIoLevel(_) => FailureKind::Usage,
+        }
+    }
+}
+
+type Result<T> = core::result::Result<T, Error>;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Caps the average rate of a stream of bytes.
+///
+/// Each call to [`RateLimit::consume`] reserves the time its bytes take at
+/// the configured rate and sleeps until the reservation begins. Time spent
+/// idle between calls is not saved up, so a slow stretch is never followed
+/// by a burst above the rate.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    bytes_per_second: NonZeroU64,
+    // when the bytes consumed so far will have been sent at the allowed rate
+    next: Option<Instant>,
+}
+
+impl RateLimit {
+    #[must_use]
+    pub fn new(bytes_per_second: NonZeroU64) -> Self {
+        Self {
+            bytes_per_second,
+            next: None,
+        }
+    }
+
+    /// The configured rate.
+    #[must_use]
+    pub fn bytes_per_second(&self) -> NonZeroU64 {
+        self.bytes_per_second
+    }
+
+    /// Account for `bytes` about to be transferred, sleeping first if the
+    /// bytes before them have not yet taken their share of time.
+    pub fn consume(&mut self, bytes: u64) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            sleep(wait);
+        }
+    }
+
+    /// Account for `bytes` about to be transferred, returning how long to
+    /// wait before transferring them, for callers that cannot block.
+    pub fn reserve(&mut self, bytes: u64) -> Duration {
+        let now = Instant::now();
+        let start = self.next.map_or(now, |next| next.max(now));
+        let nanos = u128::from(bytes)
+            .saturating_mul(NANOS_PER_SEC)
+            .checked_div(u128::from(self.bytes_per_second.get()))
+            .unwrap_or_default();
+        let cost = Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX));
+        self.next = Some(start.checked_add(cost).unwrap_or(start));
+        start.saturating_duration_since(now)
+    }
+}
+
+/// Set the nice value of the calling thread, from -20 (highest priority)
+/// to 19 (lowest). Threads started afterwards inherit it.
+///
+/// # Errors
+/// Returns an error if `nice` is out of range or the kernel refuses the
+/// change, such as when raising priority without `CAP_SYS_NICE`.
+#[cfg(target_family = "unix")]
+pub fn set_nice(nice: i32) -> Result<()> {
+    if !(-20..=19).contains(&nice) {
+        return Err(Error::InvalidNice(nice));
+    }
+    // SAFETY: setpriority only reads its integer arguments
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    if ret < 0 {
+        return Err(Error::Io {
+            context: "unable to set nice value",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+
+/// Disk I/O scheduling class, as set by `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Only perform I/O when no other process has asked for disk time.
+    Idle,
+    /// Share disk time with other processes, at a level from 0 (highest
+    /// priority) to 7 (lowest).
+    BestEffort(u8),
+}
+
+/// Set the disk I/O scheduling class of the calling thread. Threads
+/// started afterwards inherit it.
+///
+/// # Errors
+/// Returns an error if the best-effort level is out of range or the kernel
+/// refuses the change.
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(priority: IoPriority) -> Result<()> {
+    const IOPRIO_CLASS_SHIFT: u8 = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+    let ioprio = match priority {
+        IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        IoPriority::BestEffort(level @ 0..=7) => {
+            (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level)
+        }
+        IoPriority::BestEffort(level) => return Err(Error::InvalidIoLevel(level)),
+    };
+    // SAFETY: ioprio_set only reads its integer arguments; `who` of 0 is
+    // the calling thread
+    let ret = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+    if ret < 0 {
+        return Err(Error::Io {
+            context: "unable to set I/O priority",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimit;
+    #[cfg(target_os = "linux")]
+    use super::{Error, IoPriority, set_io_priority, set_nice};
+    use core::{num::NonZeroU64, time::Duration};
+    use std::{thread::sleep, time::Instant};
+
+    const RATE: NonZeroU64 = NonZeroU64::new(1000).expect("rate is non-zero");
+
+    #[test]
+    fn sleeps_to_hold_the_rate() {
+        let mut limit = RateLimit::new(RATE);
+        let start = Instant::now();
+        // the first call never waits; the later ones wait for the
+        // 100 bytes before them
+        limit.consume(100);
+        limit.consume(100);
+        limit.consume(100);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    // lowering priority never needs privileges, but can't be undone, so it
+    // is done on a thread of its own rather than one the harness may reuse
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lowers_priority() -> Result<(), Error> {
+        std::thread::spawn(|| -> Result<(), Error> {
+            set_nice(19)?;
+            set_io_priority(IoPriority::Idle)?;
+            assert!(matches!(set_nice(20), Err(Error::InvalidNice(20))));
+            assert!(matches!(
+                set_io_priority(IoPriority::BestEffort(8)),
+                Err(Error::InvalidIoLevel(8))
+            ));
+            Ok(())
+        })
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    #[test]
+    fn reserve_returns_the_wait() {
+        let mut limit = RateLimit::new(RATE);
+        assert_eq!(limit.reserve(100), Duration::ZERO);
+        let wait = limit.reserve(100);
+        assert!(wait > Duration::from_millis(50) && wait <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn idle_time_is_not_saved_up() {
+        let mut limit = RateLimit::new(RATE);
+        limit.consume(10);
+        sleep(Duration::from_millis(100));
+        let start = Instant::now();
+        limit.consume(100);
+        limit.consume(100);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
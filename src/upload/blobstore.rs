@@ -1,22 +1,33 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use crate::upload::status::Status;
+use crate::{
+    errors::FailureKind,
+    progress::{Operation, Reporter, Tracker},
+    throttle::RateLimit,
+};
 use azure_core::{
     Bytes,
-    error::Error as AzureError,
+    credentials::TokenCredential,
+    error::{Error as AzureError, ErrorKind as AzureErrorKind},
     http::{Body, NoFormat, RequestContent},
     stream::SeekableStream,
 };
 use azure_storage_blob::{BlobClient, models::BlobClientUploadOptions, stream::tokio::FileStream};
 use core::{
-    cmp,
+    cmp, fmt,
     num::{NonZeroU64, NonZeroUsize},
     pin::Pin,
     task::{Context, Poll},
 };
-use std::{path::Path, sync::Arc};
-use tokio::fs::File;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    fs::File,
+    time::{Sleep, sleep},
+};
 use url::Url;
 
 #[derive(thiserror::Error, Debug)]
@@ -32,6 +43,34 @@ pub enum Error {
 
     #[error(transparent)]
     IntConversion(#[from] core::num::TryFromIntError),
+
+    #[error(
+        "a buffer memory limit of {max_buffer_memory} bytes cannot hold the {blocks} blocks of {block_size} bytes needed"
+    )]
+    BufferMemory {
+        max_buffer_memory: u64,
+        block_size: u64,
+        blocks: u64,
+    },
+}
+
+impl Error {
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::TooLarge => FailureKind::UploadFailed,
+            Self::Io(ref e) => FailureKind::from_io(e),
+            Self::Azure(ref e) if matches!(*e.kind(), AzureErrorKind::Credential) => {
+                FailureKind::UploadDenied
+            }
+            Self::Azure(ref e) => e.http_status().map_or(FailureKind::UploadFailed, |status| {
+                FailureKind::from_upload_status(status.into())
+            }),
+            Self::IntConversion(_) => FailureKind::Other,
+            Self::BufferMemory { .. } => FailureKind::Usage,
+        }
+    }
 }
 
 type Result<T> = core::result::Result<T, Error>;
@@ -103,7 +142,8 @@ fn calc_block_size(file_size: NonZeroU64, block_size: Option<NonZeroU64>) -> Res
 
 /// Computed Azure Blob upload parameters: block partitioning and worker
 /// concurrency derived from file size, caller hints, and the
-/// `MEMORY_THRESHOLD` / `MAX_CONCURRENCY` caps.
+/// memory limit (`MEMORY_THRESHOLD` unless the caller gives one) and
+/// `MAX_CONCURRENCY` caps.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct UploadPlan {
     block_size: NonZeroU64,
@@ -114,15 +154,26 @@ fn calc_concurrency(
     file_size: NonZeroU64,
     block_size: Option<NonZeroU64>,
     upload_concurrency: Option<NonZeroUsize>,
+    max_buffer_memory: Option<NonZeroU64>,
 ) -> Result<UploadPlan> {
     if file_size > BLOB_MAX_FILE_SIZE {
         return Err(Error::TooLarge);
     }
     let block_size = calc_block_size(file_size, block_size)?;
+    if let Some(max_buffer_memory) = max_buffer_memory
+        && block_size > max_buffer_memory
+    {
+        return Err(Error::BufferMemory {
+            max_buffer_memory: max_buffer_memory.get(),
+            block_size: block_size.get(),
+            blocks: 1,
+        });
+    }
 
     let memory_limited_concurrency = NonZeroUsize::new(
         usize::try_from(
-            MEMORY_THRESHOLD
+            max_buffer_memory
+                .unwrap_or(MEMORY_THRESHOLD)
                 .get()
                 .checked_div(block_size.get())
                 .unwrap_or(0),
@@ -148,35 +199,69 @@ fn upload_parameters(
     file_size: NonZeroU64,
     block_size: Option<NonZeroU64>,
     upload_concurrency: Option<NonZeroUsize>,
+    max_buffer_memory: Option<NonZeroU64>,
 ) -> Result<UploadPlan> {
     let block_size = block_size.map(|x| x.saturating_mul(ONE_MIB_NZ));
-    calc_concurrency(file_size, block_size, upload_concurrency)
+    let max_buffer_memory = max_buffer_memory.map(|x| x.saturating_mul(ONE_MIB_NZ));
+    calc_concurrency(file_size, block_size, upload_concurrency, max_buffer_memory)
 }
 
 /// A [`SeekableStream`] wrapper that delegates to
 /// [`azure_storage_blob::stream::tokio::FileStream`] and reports upload
-/// progress via [`Status`] as bytes are read by the Azure SDK.
-#[derive(Debug, Clone)]
+/// progress to a [`Tracker`] as bytes are read by the Azure SDK. With a
+/// [`RateLimit`], reads wait until the bytes read before them have taken
+/// their share of time, which holds the upload to the rate.
 struct ProgressStream {
     inner: FileStream,
-    status: Status,
+    // both shared by clones, which the SDK may read in parallel
+    progress: Option<Arc<Mutex<Tracker>>>,
+    rate_limit: Option<Arc<Mutex<RateLimit>>>,
+    delay: Option<Pin<Box<Sleep>>>,
 }
 
 impl ProgressStream {
-    async fn new(file: File, file_size: u64) -> Result<Self> {
+    async fn new(
+        file: File,
+        progress: Option<Arc<Mutex<Tracker>>>,
+        rate_limit: Option<RateLimit>,
+    ) -> Result<Self> {
         let inner = FileStream::builder(file).build().await?;
         Ok(Self {
             inner,
-            status: Status::new(Some(file_size)),
+            progress,
+            rate_limit: rate_limit.map(|limit| Arc::new(Mutex::new(limit))),
+            delay: None,
         })
     }
 }
 
+impl Clone for ProgressStream {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            progress: self.progress.clone(),
+            rate_limit: self.rate_limit.clone(),
+            delay: None,
+        }
+    }
+}
+
+impl fmt::Debug for ProgressStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressStream")
+            .field("inner", &self.inner)
+            .field("rate_limit", &self.rate_limit)
+            .finish_non_exhaustive()
+    }
+}
+
 #[async_trait::async_trait]
 impl SeekableStream for ProgressStream {
     async fn reset(&mut self) -> azure_core::Result<()> {
         self.inner.reset().await?;
-        self.status.reset();
+        if let Some(mut progress) = self.progress.as_ref().and_then(|p| p.lock().ok()) {
+            progress.restart();
+        }
         Ok(())
     }
 
@@ -196,9 +281,28 @@ impl futures::io::AsyncRead for ProgressStream {
         slice: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
         let this = self.get_mut();
+        if let Some(delay) = this.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.delay = None;
+        }
         match Pin::new(&mut this.inner).poll_read(cx, slice) {
             Poll::Ready(Ok(n)) => {
-                this.status.inc(n);
+                if let Some(mut progress) = this.progress.as_ref().and_then(|p| p.lock().ok()) {
+                    progress.written(u64::try_from(n).unwrap_or(u64::MAX));
+                }
+                if let Some(wait) = this
+                    .rate_limit
+                    .as_ref()
+                    .and_then(|limit| limit.lock().ok())
+                    .map(|mut limit| limit.reserve(u64::try_from(n).unwrap_or(u64::MAX)))
+                    .filter(|wait| !wait.is_zero())
+                {
+                    // the bytes already read are returned now; the next
+                    // read waits for their share of time
+                    this.delay = Some(Box::pin(sleep(wait)));
+                }
                 Poll::Ready(Ok(n))
             }
             other => other,
@@ -206,7 +310,8 @@ impl futures::io::AsyncRead for ProgressStream {
     }
 }
 
-/// Upload a file to Azure Blob Store using a SAS URL.
+/// Upload a file to Azure Blob Store using a SAS URL, or a blob URL and an
+/// Entra ID credential.
 ///
 /// ```rust,no_run
 /// use avml::BlobUploader;
@@ -217,7 +322,7 @@ impl futures::io::AsyncRead for ProgressStream {
 /// let sas_url = Url::parse("https://contoso.com/container_name/blob_name?sas_token_here=1")
 ///     .expect("url parsing failed");
 /// let path = Path::new("/tmp/image.lime");
-/// let uploader = BlobUploader::new(&sas_url)?
+/// let uploader = BlobUploader::new(&sas_url, None)?
 ///     .block_size(NonZeroU64::new(100))
 ///     .concurrency(NonZeroUsize::new(5));
 /// uploader.upload_file(&path).await?;
@@ -229,22 +334,27 @@ pub struct BlobUploader {
     client: Arc<BlobClient>,
     block_size: Option<NonZeroU64>,
     concurrency: Option<NonZeroUsize>,
+    max_buffer_memory: Option<NonZeroU64>,
+    max_rate: Option<NonZeroU64>,
+    reporter: Option<Arc<dyn Reporter>>,
 }
 
 impl BlobUploader {
-    /// Create a new ``BlobUploader`` from a SAS URL.
+    /// Create a new ``BlobUploader`` from a SAS URL, or from the URL of a
+    /// blob and a `credential` such as an
+    /// [`ImdsCredential`](crate::ImdsCredential).
     ///
     /// The URL must point at a specific blob (e.g. `https://<account>.blob.core.windows.net/<container>/<blob>?<sas-token>`).
-    /// SAS authentication is carried inline in the URL's query string; this
-    /// constructor does not attach a separate credential.
+    /// Without a credential, SAS authentication is carried inline in the
+    /// URL's query string.
     ///
     /// # Errors
     /// Propagates any error returned by
     /// [`BlobClient::new`](azure_storage_blob::BlobClient::new) when
-    /// constructing the client from `sas`, for example if the URL shape is not
-    /// supported by the Azure SDK.
-    pub fn new(sas: &Url) -> Result<Self> {
-        let blob_client = BlobClient::new(sas.clone(), None, None)?;
+    /// constructing the client from `url`, for example if the URL shape is
+    /// not supported by the Azure SDK.
+    pub fn new(url: &Url, credential: Option<Arc<dyn TokenCredential>>) -> Result<Self> {
+        let blob_client = BlobClient::new(url.clone(), credential, None)?;
         Ok(Self::with_blob_client(blob_client))
     }
 
@@ -257,6 +367,9 @@ impl BlobUploader {
             client: Arc::new(client),
             block_size: None,
             concurrency: None,
+            max_buffer_memory: None,
+            max_rate: None,
+            reporter: None,
         }
     }
 
@@ -275,6 +388,29 @@ impl BlobUploader {
         }
     }
 
+    /// Specify the most block data, in multiples of 1MiB, to buffer across
+    /// concurrent uploads. Concurrency is lowered to fit; by default, at
+    /// most 500MiB is buffered.
+    #[must_use]
+    pub fn max_buffer_memory(self, max_buffer_memory: Option<NonZeroU64>) -> Self {
+        Self {
+            max_buffer_memory,
+            ..self
+        }
+    }
+
+    /// Specify the most MiB per second to upload files at, on average.
+    #[must_use]
+    pub fn max_rate(self, max_rate: Option<NonZeroU64>) -> Self {
+        Self { max_rate, ..self }
+    }
+
+    /// Report the bytes of each file read for upload to `reporter`.
+    #[must_use]
+    pub fn report_progress(self, reporter: Option<Arc<dyn Reporter>>) -> Self {
+        Self { reporter, ..self }
+    }
+
     /// Upload a file to Azure Blob Store using a fully qualified SAS token.
     ///
     /// Empty files are uploaded as zero-length blobs.
@@ -283,17 +419,31 @@ impl BlobUploader {
     /// Returns an error if:
     /// - The file cannot be opened or read
     /// - The file is too large for Azure Blob Storage
+    /// - A block does not fit in the buffer memory limit
     /// - There is a failure during the upload process
     pub async fn upload_file(self, filename: &Path) -> Result<()> {
         let file = File::open(filename).await?;
         let file_size = file.metadata().await?.len();
 
-        let stream = ProgressStream::new(file, file_size).await?;
+        let rate_limit = self
+            .max_rate
+            .map(|rate| RateLimit::new(rate.saturating_mul(ONE_MIB_NZ)));
+        let progress = self.reporter.clone().map(|reporter| {
+            let mut tracker = Tracker::new(Some(reporter), Operation::Upload);
+            tracker.add_total(file_size);
+            Arc::new(Mutex::new(tracker))
+        });
+        let stream = ProgressStream::new(file, progress.clone(), rate_limit).await?;
         let stream: Box<dyn SeekableStream> = Box::new(stream);
         let content: RequestContent<Bytes, NoFormat> = Body::from(stream).into();
 
         let options = if let Some(file_size) = NonZeroU64::new(file_size) {
-            let plan = upload_parameters(file_size, self.block_size, self.concurrency)?;
+            let plan = upload_parameters(
+                file_size,
+                self.block_size,
+                self.concurrency,
+                self.max_buffer_memory,
+            )?;
             BlobClientUploadOptions {
                 parallel: Some(plan.concurrency),
                 partition_size: Some(plan.block_size),
@@ -307,6 +457,20 @@ impl BlobUploader {
 
         self.client.upload(content, Some(options)).await?;
 
+        if let Some(mut progress) = progress.as_ref().and_then(|p| p.lock().ok()) {
+            progress.finish();
+        }
+        Ok(())
+    }
+
+    /// Upload `data` held in memory, such as a small archive, as a single
+    /// request.
+    ///
+    /// # Errors
+    /// Returns an error if there is a failure during the upload process.
+    pub async fn upload_bytes(self, data: Vec<u8>) -> Result<()> {
+        let content: RequestContent<Bytes, NoFormat> = Bytes::from(data).into();
+        self.client.upload(content, None).await?;
         Ok(())
     }
 }
@@ -340,7 +504,7 @@ mod tests {
     #[test]
     fn small_files_use_minimum_block_size_and_default_concurrency() -> Result<()> {
         assert_eq!(
-            upload_parameters(bytes_from_mib(400)?, None, None)?,
+            upload_parameters(bytes_from_mib(400)?, None, None, None)?,
             UploadPlan {
                 block_size: BLOB_MIN_BLOCK_SIZE,
                 concurrency: DEFAULT_CONCURRENCY,
@@ -352,7 +516,7 @@ mod tests {
     #[test]
     fn user_block_size_is_clamped_to_minimum() -> Result<()> {
         assert_eq!(
-            upload_parameters(bytes_from_mib(300)?, Some(NonZeroU64::MIN), None)?,
+            upload_parameters(bytes_from_mib(300)?, Some(NonZeroU64::MIN), None, None)?,
             UploadPlan {
                 block_size: BLOB_MIN_BLOCK_SIZE,
                 concurrency: DEFAULT_CONCURRENCY,
@@ -368,6 +532,7 @@ mod tests {
                 bytes_from_gib(30)?,
                 Some(non_zero(100)?),
                 Some(non_zero_usize(3)?),
+                None,
             )?,
             UploadPlan {
                 block_size: bytes_from_mib(100)?,
@@ -390,7 +555,7 @@ mod tests {
         )?;
         let expected_block_size = non_zero(file_size.get().div_ceil(max_blocks))?;
         assert_eq!(
-            upload_parameters(file_size, None, None)?,
+            upload_parameters(file_size, None, None, None)?,
             UploadPlan {
                 block_size: expected_block_size,
                 concurrency: DEFAULT_CONCURRENCY,
@@ -402,7 +567,7 @@ mod tests {
     #[test]
     fn huge_blocks_still_use_at_least_one_uploader() -> Result<()> {
         assert_eq!(
-            upload_parameters(bytes_from_gib(30)?, Some(non_zero(600)?), None)?,
+            upload_parameters(bytes_from_gib(30)?, Some(non_zero(600)?), None, None)?,
             UploadPlan {
                 block_size: bytes_from_mib(600)?,
                 concurrency: NonZeroUsize::MIN,
@@ -411,6 +576,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn buffer_memory_limits_concurrency() -> Result<()> {
+        assert_eq!(
+            upload_parameters(
+                bytes_from_gib(30)?,
+                Some(non_zero(100)?),
+                None,
+                Some(non_zero(350)?),
+            )?,
+            UploadPlan {
+                block_size: bytes_from_mib(100)?,
+                concurrency: non_zero_usize(3)?,
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn blocks_larger_than_buffer_memory_are_rejected() -> Result<()> {
+        assert!(matches!(
+            upload_parameters(
+                bytes_from_gib(30)?,
+                Some(non_zero(600)?),
+                None,
+                Some(non_zero(500)?),
+            ),
+            Err(Error::BufferMemory { blocks: 1, .. })
+        ));
+        Ok(())
+    }
+
     #[test]
     fn files_larger_than_azure_limit_are_rejected() -> Result<()> {
         let oversized_file = non_zero(
@@ -421,7 +617,7 @@ mod tests {
         )?;
 
         assert!(matches!(
-            upload_parameters(oversized_file, None, None),
+            upload_parameters(oversized_file, None, None, None),
             Err(Error::TooLarge)
         ));
         Ok(())
@@ -436,7 +632,19 @@ mod tests {
 
         let file = File::open(&path).await?;
         let file_size = file.metadata().await?.len();
-        let mut stream = ProgressStream::new(file, file_size).await?;
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&written);
+        let mut tracker = Tracker::new(
+            Some(Arc::new(move |progress: &crate::progress::Progress| {
+                if let Ok(mut guard) = sink.lock() {
+                    guard.push(progress.bytes_written);
+                }
+            })),
+            Operation::Upload,
+        );
+        tracker.add_total(file_size);
+        let mut stream =
+            ProgressStream::new(file, Some(Arc::new(Mutex::new(tracker))), None).await?;
 
         assert_eq!(stream.len(), Some(u64::try_from(expected.len())?));
 
@@ -450,6 +658,31 @@ mod tests {
         stream.read_to_end(&mut reread).await?;
         assert_eq!(reread, expected);
 
+        // the reset starts the count again
+        let written = written.lock().map(|w| w.clone()).unwrap_or_default();
+        assert_eq!(written.first(), Some(&8));
+        assert_eq!(written.last(), Some(&file_size));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn progress_stream_holds_the_rate() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("blob-upload.bin");
+        tokio::fs::write(&path, [0_u8; 400]).await?;
+
+        let file = File::open(&path).await?;
+        let rate = RateLimit::new(non_zero(1000)?);
+        let mut stream = ProgressStream::new(file, None, Some(rate)).await?;
+        let start = std::time::Instant::now();
+        let mut chunk = [0_u8; 100];
+        // the first read never waits; the later ones wait for the 100
+        // bytes before them
+        for _ in 0..4 {
+            stream.read_exact(&mut chunk).await?;
+        }
+        assert!(start.elapsed() >= core::time::Duration::from_millis(200));
         Ok(())
     }
 }
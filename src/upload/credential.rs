@@ -0,0 +1,642 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Entra ID credentials for Azure Blob Storage, for uploading to a blob URL
+//! without a SAS token.
+//!
+//! Each credential implements [`TokenCredential`], so it can be handed to
+//! [`BlobClient::new`](azure_storage_blob::BlobClient::new), which caches
+//! the token and refreshes it as it nears expiry.
+
+use crate::errors::FailureKind;
+use async_trait::async_trait;
+use azure_core::{
+    Bytes, Value,
+    credentials::{AccessToken, Secret, TokenCredential, TokenRequestOptions},
+    error::{Error as AzureError, ErrorKind as AzureErrorKind},
+    http::{HttpClient, Method, Request, StatusCode, new_http_client},
+    json::from_json,
+    time::{Duration, OffsetDateTime},
+};
+use core::time::Duration as StdDuration;
+use std::{fmt, path::PathBuf, sync::Arc};
+use tokio::time::{sleep, timeout};
+use url::{Url, form_urlencoded};
+
+/// The Azure Instance Metadata Service endpoint that issues tokens for the
+/// managed identities of a VM.
+pub const IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// The Entra ID authority used unless `AZURE_AUTHORITY_HOST` says
+/// otherwise.
+pub const AUTHORITY_HOST: &str = "https://login.microsoftonline.com/";
+
+const IMDS_API_VERSION: &str = "2018-02-01";
+
+/// Attempts at an IMDS token request. IMDS asks to be retried with backoff
+/// while it is throttling requests or being updated.
+const IMDS_ATTEMPTS: u32 = 5;
+
+/// The wait before the first retry of an IMDS token request, doubled
+/// before each retry after it.
+const IMDS_RETRY_DELAY: StdDuration = StdDuration::from_secs(1);
+
+/// The longest to wait for IMDS to answer a token request, which it never
+/// does off Azure.
+const IMDS_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+const JWT_BEARER_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0} must be set to use this credential")]
+    MissingVariable(&'static str),
+
+    #[error("invalid authority host")]
+    AuthorityHost(#[from] url::ParseError),
+}
+
+impl Error {
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::MissingVariable(_) | Self::AuthorityHost(_) => FailureKind::Usage,
+        }
+    }
+}
+
+type Result<T> = core::result::Result<T, Error>;
+
+/// A token for a managed identity of this VM, from the Azure Instance
+/// Metadata Service.
+///
+/// Each request is given up on after 10 seconds. Requests that fail with
+/// 410, 429, or a server error are retried up to 4 times, waiting 1 second
+/// before the first retry and twice as long before each one after.
+pub struct ImdsCredential {
+    http: Arc<dyn HttpClient>,
+    endpoint: Option<Url>,
+    client_id: Option<String>,
+    retry: Retry,
+}
+
+// How token requests to IMDS are timed out and retried.
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    attempts: u32,
+    delay: StdDuration,
+    timeout: StdDuration,
+}
+
+impl ImdsCredential {
+    /// Request tokens for the VM's system-assigned identity, or for the
+    /// user-assigned identity with `client_id`.
+    #[must_use]
+    pub fn new(client_id: Option<String>) -> Self {
+        Self {
+            http: new_http_client(None),
+            endpoint: None,
+            client_id,
+            retry: Retry {
+                attempts: IMDS_ATTEMPTS,
+                delay: IMDS_RETRY_DELAY,
+                timeout: IMDS_TIMEOUT,
+            },
+        }
+    }
+
+    /// Request tokens from `endpoint` rather than the Azure Instance
+    /// Metadata Service.
+    #[must_use]
+    pub fn endpoint(self, endpoint: Url) -> Self {
+        Self {
+            endpoint: Some(endpoint),
+            ..self
+        }
+    }
+}
+
+impl fmt::Debug for ImdsCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImdsCredential")
+            .field("endpoint", &self.endpoint)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl TokenCredential for ImdsCredential {
+    async fn get_token(
+        &self,
+        scopes: &[&str],
+        _options: Option<TokenRequestOptions<'_>>,
+    ) -> azure_core::Result<AccessToken> {
+        let mut url = match self.endpoint {
+            Some(ref endpoint) => endpoint.clone(),
+            None => Url::parse(IMDS_ENDPOINT)
+                .map_err(|e| AzureError::new(AzureErrorKind::Credential, e))?,
+        };
+        // IMDS takes the resource a token is for, rather than its scopes
+        let resource = single_scope(scopes)?;
+        let resource = resource.strip_suffix(".default").unwrap_or(resource);
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("api-version", IMDS_API_VERSION)
+                .append_pair("resource", resource);
+            if let Some(ref client_id) = self.client_id {
+                query.append_pair("client_id", client_id);
+            }
+        }
+
+        let mut request = Request::new(url, Method::Get);
+        request.insert_header("metadata", "true");
+        let mut delay = self.retry.delay;
+        let mut attempt = 1;
+        loop {
+            let (status, body) = timeout(self.retry.timeout, send(self.http.as_ref(), &request))
+                .await
+                .map_err(|_| {
+                    AzureError::with_message(
+                        AzureErrorKind::Credential,
+                        format!(
+                            "token request to {} timed out after {:?}",
+                            request.url(),
+                            self.retry.timeout
+                        ),
+                    )
+                })??;
+            if attempt >= self.retry.attempts || !is_transient(status) {
+                return parse_token(&request, status, &body);
+            }
+            sleep(delay).await;
+            delay = delay.saturating_mul(2);
+            attempt = attempt.saturating_add(1);
+        }
+    }
+}
+
+/// A token for an app registration, from Entra ID, in exchange for either
+/// a client secret or a federated token such as the one Kubernetes
+/// workload identity projects into a pod.
+pub struct ClientCredential {
+    http: Arc<dyn HttpClient>,
+    token_url: Url,
+    client_id: String,
+    proof: Proof,
+}
+
+// How a client proves its identity.
+enum Proof {
+    Secret(Secret),
+    // a federated token, read afresh for each request as it is rotated
+    TokenFile(PathBuf),
+}
+
+impl ClientCredential {
+    /// Authenticate with the client secret in `AZURE_CLIENT_SECRET`, for the
+    /// app registration `AZURE_CLIENT_ID` in the tenant `AZURE_TENANT_ID`.
+    ///
+    /// # Errors
+    /// Returns an error if a variable is not set, or `AZURE_AUTHORITY_HOST`
+    /// is not a valid URL.
+    pub fn client_secret_from_env() -> Result<Self> {
+        Self::client_secret_from_vars(env_var)
+    }
+
+    /// Authenticate with the federated token in the file named by
+    /// `AZURE_FEDERATED_TOKEN_FILE`, for the app registration
+    /// `AZURE_CLIENT_ID` in the tenant `AZURE_TENANT_ID`, as set by
+    /// Kubernetes workload identity.
+    ///
+    /// # Errors
+    /// Returns an error if a variable is not set, or `AZURE_AUTHORITY_HOST`
+    /// is not a valid URL.
+    pub fn workload_identity_from_env() -> Result<Self> {
+        Self::workload_identity_from_vars(env_var)
+    }
+
+    fn client_secret_from_vars<F>(var: F) -> Result<Self>
+    where
+        F: Fn(&'static str) -> Option<String>,
+    {
+        let secret = require(&var, "AZURE_CLIENT_SECRET")?;
+        Self::from_vars(var, Proof::Secret(Secret::new(secret)))
+    }
+
+    fn workload_identity_from_vars<F>(var: F) -> Result<Self>
+    where
+        F: Fn(&'static str) -> Option<String>,
+    {
+        let token_file = require(&var, "AZURE_FEDERATED_TOKEN_FILE")?;
+        Self::from_vars(var, Proof::TokenFile(PathBuf::from(token_file)))
+    }
+
+    fn from_vars<F>(var: F, proof: Proof) -> Result<Self>
+    where
+        F: Fn(&'static str) -> Option<String>,
+    {
+        let tenant_id = require(&var, "AZURE_TENANT_ID")?;
+        let client_id = require(&var, "AZURE_CLIENT_ID")?;
+        let mut authority = var("AZURE_AUTHORITY_HOST").unwrap_or_else(|| AUTHORITY_HOST.into());
+        if !authority.ends_with('/') {
+            authority.push('/');
+        }
+        let token_url = Url::parse(&authority)?.join(&format!("{tenant_id}/oauth2/v2.0/token"))?;
+        Ok(Self {
+            http: new_http_client(None),
+            token_url,
+            client_id,
+            proof,
+        })
+    }
+
+    // The body of a client credentials grant for `scope`.
+    fn form(&self, scope: &str, assertion: Option<&str>) -> String {
+        let mut form = form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "client_credentials")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("scope", scope);
+        if let Proof::Secret(ref secret) = self.proof {
+            form.append_pair("client_secret", secret.secret());
+        }
+        if let Some(assertion) = assertion {
+            form.append_pair("client_assertion_type", JWT_BEARER_ASSERTION)
+                .append_pair("client_assertion", assertion.trim());
+        }
+        form.finish()
+    }
+}
+
+impl fmt::Debug for ClientCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCredential")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl TokenCredential for ClientCredential {
+    async fn get_token(
+        &self,
+        scopes: &[&str],
+        _options: Option<TokenRequestOptions<'_>>,
+    ) -> azure_core::Result<AccessToken> {
+        let assertion = match self.proof {
+            Proof::Secret(_) => None,
+            Proof::TokenFile(ref path) => {
+                Some(tokio::fs::read_to_string(path).await.map_err(|e| {
+                    AzureError::with_error(
+                        AzureErrorKind::Credential,
+                        e,
+                        format!("unable to read federated token {}", path.display()),
+                    )
+                })?)
+            }
+        };
+
+        let mut request = Request::new(self.token_url.clone(), Method::Post);
+        request.insert_header("content-type", "application/x-www-form-urlencoded");
+        request.set_body(self.form(single_scope(scopes)?, assertion.as_deref()));
+        request_token(self.http.as_ref(), &request).await
+    }
+}
+
+fn env_var(name: &'static str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn require<F>(var: &F, name: &'static str) -> Result<String>
+where
+    F: Fn(&'static str) -> Option<String>,
+{
+    var(name).ok_or(Error::MissingVariable(name))
+}
+
+fn single_scope<'a>(scopes: &[&'a str]) -> azure_core::Result<&'a str> {
+    match *scopes {
+        [scope] => Ok(scope),
+        _ => Err(AzureError::with_message(
+            AzureErrorKind::Credential,
+            "exactly one scope must be requested",
+        )),
+    }
+}
+
+// Whether IMDS asks for a request failing with `status` to be retried.
+fn is_transient(status: StatusCode) -> bool {
+    matches!(status, StatusCode::Gone | StatusCode::TooManyRequests) || status.is_server_error()
+}
+
+// Send a token request, and parse the token from the response.
+async fn request_token(
+    http: &dyn HttpClient,
+    request: &Request,
+) -> azure_core::Result<AccessToken> {
+    let (status, body) = send(http, request).await?;
+    parse_token(request, status, &body)
+}
+
+async fn send(http: &dyn HttpClient, request: &Request) -> azure_core::Result<(StatusCode, Bytes)> {
+    let response = http.execute_request(request).await?;
+    let status = response.status();
+    Ok((status, response.into_body().collect().await?))
+}
+
+// Parse the token from the response to `request`, which both IMDS and
+// Entra ID return as JSON with `access_token` and either `expires_on`
+// (seconds since the epoch) or `expires_in` (seconds from now).
+fn parse_token(
+    request: &Request,
+    status: StatusCode,
+    body: &[u8],
+) -> azure_core::Result<AccessToken> {
+    if !status.is_success() {
+        return Err(AzureError::with_message(
+            AzureErrorKind::Credential,
+            format!(
+                "token request to {} failed with status {status}: {}",
+                request.url(),
+                String::from_utf8_lossy(body)
+            ),
+        ));
+    }
+
+    let invalid = |field: &str| {
+        AzureError::with_message(
+            AzureErrorKind::Credential,
+            format!("token response from {} has no valid {field}", request.url()),
+        )
+    };
+    let json: Value = from_json(body)?;
+    let token = json
+        .get("access_token")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("access_token"))?;
+    let expires_on = if let Some(expires_on) = json.get("expires_on").and_then(seconds) {
+        OffsetDateTime::from_unix_timestamp(expires_on).map_err(|_| invalid("expires_on"))?
+    } else {
+        let expires_in = json
+            .get("expires_in")
+            .and_then(seconds)
+            .ok_or_else(|| invalid("expires_in"))?;
+        OffsetDateTime::now_utc().saturating_add(Duration::seconds(expires_in))
+    };
+    Ok(AccessToken::new(token.to_string(), expires_on))
+}
+
+// A count of seconds, which token endpoints give as either a number or a
+// string.
+fn seconds(value: &Value) -> Option<i64> {
+    value
+        .as_i64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientCredential, Error, ImdsCredential, Retry};
+    use azure_core::{credentials::TokenCredential as _, time::OffsetDateTime};
+    use core::time::Duration;
+    use std::collections::HashMap;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+    use url::Url;
+
+    type TestResult = Result<(), Box<dyn core::error::Error + Send + Sync>>;
+
+    const SCOPE: &str = "https://storage.azure.com/.default";
+
+    // A token endpoint that answers one request with `status` and `body`,
+    // and returns the request it received.
+    async fn token_endpoint(
+        status: &'static str,
+        body: &'static str,
+    ) -> std::io::Result<(Url, JoinHandle<std::io::Result<String>>)> {
+        let (url, server) = token_endpoints(vec![(status, body)]).await?;
+        let server = tokio::spawn(async move {
+            let mut requests = server.await.map_err(std::io::Error::other)??;
+            Ok(requests.pop().unwrap_or_default())
+        });
+        Ok((url, server))
+    }
+
+    // A token endpoint that answers a request with each status and body of
+    // `responses` in turn, and returns the requests it received.
+    async fn token_endpoints(
+        responses: Vec<(&'static str, &'static str)>,
+    ) -> std::io::Result<(Url, JoinHandle<std::io::Result<Vec<String>>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))
+            .map_err(std::io::Error::other)?;
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                requests.push(answer(&listener, status, body).await?);
+            }
+            Ok(requests)
+        });
+        Ok((url, server))
+    }
+
+    // Answer the next request to `listener` with `status` and `body`,
+    // returning the request.
+    async fn answer(listener: &TcpListener, status: &str, body: &str) -> std::io::Result<String> {
+        let (mut socket, _) = listener.accept().await?;
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let read = socket.read(&mut buf).await?;
+            request.extend_from_slice(buf.get(..read).unwrap_or_default());
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, content)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|header| header.0.eq_ignore_ascii_case("content-length"))
+                    .and_then(|header| header.1.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if content.len() >= length {
+                    break;
+                }
+            }
+            if read == 0 {
+                break;
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        socket.write_all(response.as_bytes()).await?;
+        socket.shutdown().await?;
+        Ok(String::from_utf8_lossy(&request).into_owned())
+    }
+
+    fn vars(pairs: &[(&'static str, String)]) -> impl Fn(&'static str) -> Option<String> {
+        let vars: HashMap<_, _> = pairs.iter().cloned().collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[tokio::test]
+    async fn imds_requests_a_token_for_a_managed_identity() -> TestResult {
+        let (url, server) = token_endpoint(
+            "200 OK",
+            r#"{"access_token":"imds-token","expires_on":"1900000000","resource":"https://storage.azure.com/","token_type":"Bearer"}"#,
+        )
+        .await?;
+
+        let credential =
+            ImdsCredential::new(Some("client".to_string())).endpoint(url.join("token")?);
+        let token = credential.get_token(&[SCOPE], None).await?;
+        assert_eq!(token.token.secret(), "imds-token");
+        assert_eq!(
+            token.expires_on,
+            OffsetDateTime::from_unix_timestamp(1_900_000_000)?
+        );
+
+        let request = server.await??.to_ascii_lowercase();
+        assert!(request.starts_with(
+            "get /token?api-version=2018-02-01&resource=https%3a%2f%2fstorage.azure.com%2f&client_id=client "
+        ));
+        assert!(request.contains("\r\nmetadata: true\r\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn token_endpoint_failures_are_credential_errors() -> TestResult {
+        let (url, server) =
+            token_endpoint("400 Bad Request", r#"{"error":"invalid_request"}"#).await?;
+
+        let credential = ImdsCredential::new(None).endpoint(url);
+        let result = credential.get_token(&[SCOPE], None).await;
+        assert!(result.is_err_and(|e| {
+            matches!(*e.kind(), azure_core::error::ErrorKind::Credential)
+                && e.to_string().contains("invalid_request")
+        }));
+        server.await??;
+        Ok(())
+    }
+
+    // A credential against `endpoint` that retries without waiting.
+    fn imds(endpoint: Url, timeout: Duration) -> ImdsCredential {
+        ImdsCredential {
+            retry: Retry {
+                attempts: 3,
+                delay: Duration::ZERO,
+                timeout,
+            },
+            ..ImdsCredential::new(None).endpoint(endpoint)
+        }
+    }
+
+    #[tokio::test]
+    async fn imds_retries_transient_failures() -> TestResult {
+        let token = r#"{"access_token":"imds-token","expires_on":"1900000000"}"#;
+        let (url, server) = token_endpoints(vec![
+            ("410 Gone", ""),
+            ("503 Service Unavailable", ""),
+            ("200 OK", token),
+        ])
+        .await?;
+        let credential = imds(url, Duration::from_secs(10));
+        let result = credential.get_token(&[SCOPE], None).await?;
+        assert_eq!(result.token.secret(), "imds-token");
+        assert_eq!(server.await??.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn imds_gives_up_after_its_attempts() -> TestResult {
+        let (url, server) = token_endpoints(vec![
+            ("429 Too Many Requests", ""),
+            ("429 Too Many Requests", ""),
+            ("429 Too Many Requests", r#"{"error":"throttled"}"#),
+        ])
+        .await?;
+        let throttled = imds(url, Duration::from_secs(10))
+            .get_token(&[SCOPE], None)
+            .await;
+        assert!(throttled.is_err_and(|e| e.to_string().contains("throttled")));
+        assert_eq!(server.await??.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn imds_requests_time_out() -> TestResult {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let result = imds(url, Duration::from_millis(100))
+            .get_token(&[SCOPE], None)
+            .await;
+        assert!(result.is_err_and(|e| {
+            matches!(*e.kind(), azure_core::error::ErrorKind::Credential)
+                && e.to_string().contains("timed out")
+        }));
+        drop(listener);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn workload_identity_exchanges_the_federated_token() -> TestResult {
+        let (url, server) = token_endpoint(
+            "200 OK",
+            r#"{"token_type":"Bearer","expires_in":3599,"access_token":"entra-token"}"#,
+        )
+        .await?;
+        let dir = tempfile::tempdir()?;
+        let token_file = dir.path().join("token");
+        std::fs::write(&token_file, "federated.jwt\n")?;
+
+        let credential = ClientCredential::workload_identity_from_vars(vars(&[
+            ("AZURE_TENANT_ID", "tenant".to_string()),
+            ("AZURE_CLIENT_ID", "client".to_string()),
+            (
+                "AZURE_FEDERATED_TOKEN_FILE",
+                token_file.display().to_string(),
+            ),
+            ("AZURE_AUTHORITY_HOST", url.to_string()),
+        ]))?;
+        let before = OffsetDateTime::now_utc();
+        let token = credential.get_token(&[SCOPE], None).await?;
+        assert_eq!(token.token.secret(), "entra-token");
+        assert!(token.expires_on > before);
+
+        let request = server.await??;
+        assert!(request.starts_with("POST /tenant/oauth2/v2.0/token "));
+        let body = request.split_once("\r\n\r\n").map_or("", |parts| parts.1);
+        assert_eq!(
+            body,
+            "grant_type=client_credentials&client_id=client\
+             &scope=https%3A%2F%2Fstorage.azure.com%2F.default\
+             &client_assertion_type=urn%3Aietf%3Aparams%3Aoauth%3Aclient-assertion-type%3Ajwt-bearer\
+             &client_assertion=federated.jwt"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_credentials_require_their_variables() {
+        assert!(
+            ClientCredential::client_secret_from_vars(vars(&[
+                ("AZURE_TENANT_ID", "tenant".to_string()),
+                ("AZURE_CLIENT_SECRET", "secret".to_string()),
+            ]))
+            .is_err_and(|e| matches!(e, Error::MissingVariable("AZURE_CLIENT_ID")))
+        );
+        assert!(
+            ClientCredential::workload_identity_from_vars(vars(&[
+                ("AZURE_TENANT_ID", "tenant".to_string()),
+                ("AZURE_CLIENT_ID", "client".to_string()),
+            ]))
+            .is_err_and(|e| matches!(e, Error::MissingVariable("AZURE_FEDERATED_TOKEN_FILE")))
+        );
+    }
+}
@@ -1,10 +1,16 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT License.
 
-use crate::upload::status::Status;
+use crate::{
+    errors::FailureKind,
+    progress::{Operation, Reporter, Tracker},
+};
 use futures::stream::StreamExt as _;
 use reqwest::{Body, Client};
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use url::Url;
@@ -25,7 +31,22 @@ pub enum Error {
     UnexpectedStatusCode { status: u16 },
 }
 
-/// Upload a file via HTTP PUT
+impl Error {
+    /// What kind of failure this is, which determines the exit code.
+    #[must_use]
+    pub fn failure_kind(&self) -> FailureKind {
+        match *self {
+            Self::Io { ref source, .. } => FailureKind::from_io(source),
+            Self::Request(ref e) => e.status().map_or(FailureKind::UploadFailed, |status| {
+                FailureKind::from_upload_status(status.as_u16())
+            }),
+            Self::UnexpectedStatusCode { status } => FailureKind::from_upload_status(status),
+        }
+    }
+}
+
+/// Upload a file via HTTP PUT, reporting the bytes read for upload to
+/// `reporter`.
 ///
 /// # Errors
 /// Returns an error if:
@@ -33,7 +54,11 @@ pub enum Error {
 /// - There is a failure making the HTTP request
 /// - The server returns an unexpected status code
 #[cfg(feature = "put")]
-pub async fn put(filename: &Path, url: &Url) -> Result<(), Error> {
+pub async fn put(
+    filename: &Path,
+    url: &Url,
+    reporter: Option<Arc<dyn Reporter>>,
+) -> Result<(), Error> {
     let file = File::open(&filename).await.map_err(|source| Error::Io {
         path: filename.to_owned(),
         source,
@@ -48,10 +73,17 @@ pub async fn put(filename: &Path, url: &Url) -> Result<(), Error> {
         })?
         .len();
 
-    let status = Status::new(Some(size));
+    let progress = reporter.map(|reporter| {
+        let mut tracker = Tracker::new(Some(reporter), Operation::Upload);
+        tracker.add_total(size);
+        Arc::new(Mutex::new(tracker))
+    });
+    let read = progress.clone();
     let stream = FramedRead::new(file, BytesCodec::new()).inspect(move |x| {
-        if let Ok(ref bytes) = *x {
-            status.inc(bytes.len());
+        if let Ok(ref bytes) = *x
+            && let Some(mut tracker) = read.as_ref().and_then(|p| p.lock().ok())
+        {
+            tracker.written(u64::try_from(bytes.len()).unwrap_or(u64::MAX));
         }
     });
     let body = Body::wrap_stream(stream);
@@ -71,5 +103,8 @@ pub async fn put(filename: &Path, url: &Url) -> Result<(), Error> {
         });
     }
 
+    if let Some(mut progress) = progress.as_ref().and_then(|p| p.lock().ok()) {
+        progress.finish();
+    }
     Ok(())
 }
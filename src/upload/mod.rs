@@ -4,10 +4,14 @@
 #[cfg(feature = "blobstore")]
 pub mod blobstore;
 
+#[cfg(feature = "blobstore")]
+pub mod credential;
+
+#[cfg(feature = "blobstore")]
+pub mod reader;
+
 #[cfg(feature = "blobstore")]
 pub mod stream;
 
 #[cfg(feature = "put")]
 pub mod http;
-
-mod status;
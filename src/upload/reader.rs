@@ -0,0 +1,506 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Read an Azure Block Blob as a seekable byte stream.
+//!
+//! The blob is split into fixed-size chunks, each fetched with a ranged GET.
+//! When a read lands in chunk `N`, chunks `N+1 .. N+concurrency` are
+//! already being fetched in parallel on the tokio runtime, so sequential
+//! readers (such as snapshot conversion) rarely wait on the network. A seek
+//! outside the in-flight window discards the readahead and restarts it at
+//! the new position. At most `concurrency + 1` chunks are held in memory.
+
+use crate::upload::blobstore::Error;
+use async_trait::async_trait;
+use azure_core::Bytes;
+use azure_storage_blob::{
+    BlobClient,
+    models::{BlobClientDownloadOptions, BlobClientGetPropertiesResultHeaders as _},
+};
+use core::{
+    num::{NonZeroU64, NonZeroUsize},
+    ops::Range,
+};
+use std::{
+    collections::VecDeque,
+    io::{Read, Result as IoResult, Seek, SeekFrom},
+    sync::Arc,
+};
+use tokio::{runtime::Handle, task::JoinHandle};
+use url::Url;
+
+type Result<T> = core::result::Result<T, Error>;
+
+const ONE_MIB_NZ: NonZeroU64 = NonZeroU64::new(1024 * 1024).expect("ONE_MIB must be non-zero");
+
+/// Default size of each ranged GET, in MiB.
+const DEFAULT_BLOCK_SIZE_MIB: NonZeroU64 =
+    NonZeroU64::new(8).expect("default block size must be non-zero");
+
+/// Default number of chunks fetched ahead of the reader.
+const DEFAULT_READ_CONCURRENCY: NonZeroUsize =
+    NonZeroUsize::new(4).expect("default read concurrency must be non-zero");
+
+/// Abstraction over the two blob operations this module uses, so tests can
+/// substitute an in-memory fake without standing up Azure.
+#[expect(
+    clippy::redundant_pub_crate,
+    reason = "appears in the signature of pub(crate) BlobReader::with_fetcher"
+)]
+#[async_trait]
+pub(crate) trait RangeFetcher: Send + Sync + 'static {
+    async fn len(&self) -> Result<u64>;
+    async fn fetch(&self, range: Range<u64>) -> Result<Bytes>;
+}
+
+/// Live `RangeFetcher` backed by `azure_storage_blob`.
+struct SdkFetcher {
+    client: Arc<BlobClient>,
+}
+
+#[async_trait]
+impl RangeFetcher for SdkFetcher {
+    async fn len(&self) -> Result<u64> {
+        self.client
+            .get_properties(None)
+            .await?
+            .content_length()?
+            .ok_or_else(|| Error::Io(std::io::Error::other("blob length is unavailable")))
+    }
+
+    async fn fetch(&self, range: Range<u64>) -> Result<Bytes> {
+        // A partition at least as large as the range keeps the SDK from
+        // splitting the request; parallelism is handled by `BlobReader`.
+        let partition_size =
+            NonZeroUsize::new(usize::try_from(range.end.saturating_sub(range.start))?)
+                .unwrap_or(NonZeroUsize::MIN);
+        let options = BlobClientDownloadOptions {
+            range: Some(range.into()),
+            parallel: Some(NonZeroUsize::MIN),
+            partition_size: Some(partition_size),
+            ..Default::default()
+        };
+        let result = self.client.download(Some(options)).await?;
+        Ok(result.body.collect().await?)
+    }
+}
+
+struct Chunk {
+    index: u64,
+    data: Bytes,
+}
+
+/// Read a blob with ranged GETs, presenting it as [`Read`] + [`Seek`].
+///
+/// ```rust,no_run
+/// use avml::{BlobReader, Format, image::Image};
+/// # use avml::Result;
+/// # use std::num::{NonZeroU64, NonZeroUsize};
+/// # use url::Url;
+/// # async fn convert() -> Result<()> {
+/// let sas_url = Url::parse("https://contoso.com/container_name/blob_name?sas_token_here=1")
+///     .expect("url parsing failed");
+/// let reader = BlobReader::new(&sas_url)?
+///     .block_size(NonZeroU64::new(16))
+///     .concurrency(NonZeroUsize::new(8));
+/// tokio::task::spawn_blocking(move || {
+///     let mut image = Image::from_streams(Format::Lime, reader, std::io::sink());
+///     image.convert_blocks()
+/// })
+/// .await
+/// .expect("join failed")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Runtime requirements
+///
+/// Like [`crate::BlockBlobStream`], `new` must be invoked from inside a
+/// tokio runtime, and the reader must be driven from a thread that is *not*
+/// a runtime worker (i.e., from `spawn_blocking`).
+pub struct BlobReader {
+    fetcher: Arc<dyn RangeFetcher>,
+    handle: Handle,
+    block_size: NonZeroU64,
+    concurrency: NonZeroUsize,
+    len: Option<u64>,
+    pos: u64,
+    current: Option<Chunk>,
+    pending: VecDeque<(u64, JoinHandle<Result<Bytes>>)>,
+}
+
+impl BlobReader {
+    /// Create a new ``BlobReader`` from a SAS URL.
+    ///
+    /// The URL must point at a specific blob and carry a SAS token with
+    /// read permission. No requests are made until the first read or seek.
+    ///
+    /// # Errors
+    /// Propagates any error returned by
+    /// [`BlobClient::new`](azure_storage_blob::BlobClient::new).
+    pub fn new(sas: &Url) -> Result<Self> {
+        let blob_client = BlobClient::new(sas.clone(), None, None)?;
+        Ok(Self::with_blob_client(blob_client))
+    }
+
+    /// Create a ``BlobReader`` with a ``BlobClient`` from ``azure_storage_blob``.
+    #[must_use]
+    pub fn with_blob_client(client: BlobClient) -> Self {
+        Self::with_fetcher(Arc::new(SdkFetcher {
+            client: Arc::new(client),
+        }))
+    }
+
+    pub(crate) fn with_fetcher(fetcher: Arc<dyn RangeFetcher>) -> Self {
+        Self {
+            fetcher,
+            handle: Handle::current(),
+            block_size: DEFAULT_BLOCK_SIZE_MIB.saturating_mul(ONE_MIB_NZ),
+            concurrency: DEFAULT_READ_CONCURRENCY,
+            len: None,
+            pos: 0,
+            current: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Specify a positive ranged GET size in multiples of 1MiB.
+    #[must_use]
+    pub fn block_size(self, block_size: Option<NonZeroU64>) -> Self {
+        let block_size = block_size.map_or(self.block_size, |x| x.saturating_mul(ONE_MIB_NZ));
+        self.with_block_size_bytes(block_size)
+    }
+
+    fn with_block_size_bytes(mut self, block_size: NonZeroU64) -> Self {
+        // Chunk indices are relative to the block size, so anything already
+        // fetched is no longer addressable.
+        self.discard_readahead();
+        self.current = None;
+        self.block_size = block_size;
+        self
+    }
+
+    /// Specify how many ranged GETs may be in flight ahead of the reader.
+    #[must_use]
+    pub fn concurrency(mut self, concurrency: Option<NonZeroUsize>) -> Self {
+        if let Some(concurrency) = concurrency {
+            self.concurrency = concurrency;
+        }
+        self
+    }
+
+    /// Total length of the blob in bytes. Like reads, this must be called
+    /// from a blocking thread the first time, as it fetches the blob
+    /// properties.
+    ///
+    /// # Errors
+    /// Returns an error if the blob properties cannot be retrieved.
+    pub fn len(&mut self) -> Result<u64> {
+        if let Some(len) = self.len {
+            return Ok(len);
+        }
+        let fetcher = self.fetcher.clone();
+        let len = self.handle.block_on(async move { fetcher.len().await })?;
+        self.len = Some(len);
+        Ok(len)
+    }
+
+    /// Returns `true` if the blob has a length of zero.
+    ///
+    /// # Errors
+    /// Returns an error if the blob properties cannot be retrieved.
+    pub fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    fn chunk_range(&self, index: u64, len: u64) -> Option<Range<u64>> {
+        let start = index.checked_mul(self.block_size.get())?;
+        (start < len).then(|| start..start.saturating_add(self.block_size.get()).min(len))
+    }
+
+    /// Keep up to `concurrency` chunks in flight, continuing from the last
+    /// pending chunk (or `start` if nothing is pending).
+    fn fill_readahead(&mut self, start: u64, len: u64) {
+        let mut next = self
+            .pending
+            .back()
+            .map_or(start, |&(index, _)| index.saturating_add(1));
+        while self.pending.len() < self.concurrency.get() {
+            let Some(range) = self.chunk_range(next, len) else {
+                break;
+            };
+            let fetcher = self.fetcher.clone();
+            let task = self.handle.spawn(async move {
+                let expected = range.end.saturating_sub(range.start);
+                let data = fetcher.fetch(range).await?;
+                if u64::try_from(data.len())? != expected {
+                    return Err(Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "ranged GET returned a short body",
+                    )));
+                }
+                Ok(data)
+            });
+            self.pending.push_back((next, task));
+            next = next.saturating_add(1);
+        }
+    }
+
+    fn discard_readahead(&mut self) {
+        for (_, task) in self.pending.drain(..) {
+            task.abort();
+        }
+    }
+
+    fn load_chunk(&mut self, index: u64, len: u64) -> Result<&Bytes> {
+        if self.current.as_ref().is_none_or(|c| c.index != index) {
+            // Drop readahead that the reader has moved past. If the seek
+            // left the in-flight window entirely, this empties the queue
+            // and the window restarts at `index`.
+            while self.pending.front().is_some_and(|&(i, _)| i != index) {
+                if let Some((_, task)) = self.pending.pop_front() {
+                    task.abort();
+                }
+            }
+            self.fill_readahead(index, len);
+            let (_, task) = self
+                .pending
+                .pop_front()
+                .ok_or_else(|| Error::Io(std::io::Error::other("missing readahead chunk")))?;
+            let data = self
+                .handle
+                .block_on(task)
+                .map_err(|e| Error::Io(std::io::Error::other(e.to_string())))??;
+            self.current = Some(Chunk { index, data });
+            self.fill_readahead(index.saturating_add(1), len);
+        }
+
+        self.current
+            .as_ref()
+            .map(|c| &c.data)
+            .ok_or_else(|| Error::Io(std::io::Error::other("missing current chunk")))
+    }
+}
+
+impl Drop for BlobReader {
+    fn drop(&mut self) {
+        self.discard_readahead();
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = self.len().map_err(std::io::Error::other)?;
+        if self.pos >= len {
+            return Ok(0);
+        }
+
+        let block_size = self.block_size.get();
+        let index = self.pos.checked_div(block_size).unwrap_or(0);
+        let offset = usize::try_from(self.pos.checked_rem(block_size).unwrap_or(0))
+            .map_err(std::io::Error::other)?;
+        let chunk = self.load_chunk(index, len).map_err(std::io::Error::other)?;
+
+        let available = chunk.get(offset..).unwrap_or_default();
+        let count = available.len().min(buf.len());
+        if let (Some(dst), Some(src)) = (buf.get_mut(..count), available.get(..count)) {
+            dst.copy_from_slice(src);
+        }
+        self.pos = self
+            .pos
+            .saturating_add(u64::try_from(count).map_err(std::io::Error::other)?);
+        Ok(count)
+    }
+}
+
+impl Seek for BlobReader {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self
+                .len()
+                .map_err(std::io::Error::other)?
+                .checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.pos = target;
+        Ok(target)
+    }
+
+    fn stream_position(&mut self) -> IoResult<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(
+        clippy::expect_used,
+        reason = "tests assert on pre-known shapes and value counts"
+    )]
+
+    use super::*;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// In-memory `RangeFetcher` that records every requested range.
+    struct FakeFetcher {
+        data: Bytes,
+        requests: Mutex<Vec<Range<u64>>>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl FakeFetcher {
+        fn new(data: Vec<u8>) -> Self {
+            Self {
+                data: Bytes::from(data),
+                requests: Mutex::new(Vec::new()),
+                in_flight: AtomicUsize::new(0),
+                max_in_flight: AtomicUsize::new(0),
+            }
+        }
+
+        fn requests(&self) -> Vec<Range<u64>> {
+            self.requests
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clone()
+        }
+    }
+
+    #[async_trait]
+    impl RangeFetcher for FakeFetcher {
+        async fn len(&self) -> Result<u64> {
+            Ok(u64::try_from(self.data.len())?)
+        }
+
+        async fn fetch(&self, range: Range<u64>) -> Result<Bytes> {
+            let now = self
+                .in_flight
+                .fetch_add(1, Ordering::SeqCst)
+                .saturating_add(1);
+            self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            self.requests
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push(range.clone());
+            let start = usize::try_from(range.start)?;
+            let end = usize::try_from(range.end)?;
+            Ok(self.data.slice(start..end))
+        }
+    }
+
+    fn nz(n: u64) -> NonZeroU64 {
+        NonZeroU64::new(n).expect("test constant non-zero")
+    }
+
+    fn nz_usize(n: usize) -> NonZeroUsize {
+        NonZeroUsize::new(n).expect("test constant non-zero")
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| u8::try_from(i % 251).expect("modulo constrains byte"))
+            .collect()
+    }
+
+    async fn with_reader<F, T>(fetcher: Arc<FakeFetcher>, block_size: u64, f: F) -> T
+    where
+        F: FnOnce(&mut BlobReader) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let reader = BlobReader::with_fetcher(fetcher)
+            .with_block_size_bytes(nz(block_size))
+            .concurrency(Some(nz_usize(3)));
+        tokio::task::spawn_blocking(move || {
+            let mut reader = reader;
+            f(&mut reader)
+        })
+        .await
+        .expect("spawn_blocking join")
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sequential_read_returns_whole_blob() {
+        let expected = payload(1000);
+        let fetcher = Arc::new(FakeFetcher::new(expected.clone()));
+
+        let actual = with_reader(fetcher.clone(), 64, |reader| {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).expect("read blob");
+            buf
+        })
+        .await;
+
+        assert_eq!(actual, expected);
+        let mut requests = fetcher.requests();
+        requests.sort_by_key(|r| r.start);
+        assert_eq!(requests.len(), 16, "each chunk fetched exactly once");
+        assert_eq!(requests.last(), Some(&(960..1000)), "final chunk is short");
+        assert!(fetcher.max_in_flight.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn seek_restarts_readahead_at_new_position() {
+        let expected = payload(1000);
+        let fetcher = Arc::new(FakeFetcher::new(expected.clone()));
+
+        let (tail, head, end) = with_reader(fetcher.clone(), 100, |reader| {
+            let mut tail = [0_u8; 10];
+            reader.seek(SeekFrom::End(-10)).expect("seek from end");
+            reader.read_exact(&mut tail).expect("read tail");
+
+            let mut head = [0_u8; 10];
+            reader.seek(SeekFrom::Start(95)).expect("seek to start");
+            reader.read_exact(&mut head).expect("read across chunks");
+
+            let end = reader.stream_position().expect("query position");
+            (tail, head, end)
+        })
+        .await;
+
+        assert_eq!(tail.as_slice(), expected.get(990..).expect("tail in range"));
+        assert_eq!(
+            head.as_slice(),
+            expected.get(95..105).expect("head in range")
+        );
+        assert_eq!(end, 105);
+        assert!(
+            fetcher.requests().contains(&(0..100)),
+            "backwards seek refetches the earlier chunk"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn read_past_end_returns_eof() {
+        let fetcher = Arc::new(FakeFetcher::new(payload(10)));
+
+        let (count, invalid) = with_reader(fetcher.clone(), 4, |reader| {
+            reader.seek(SeekFrom::Start(50)).expect("seek past end");
+            let count = reader.read(&mut [0_u8; 4]).expect("read at eof");
+            let invalid = reader.seek(SeekFrom::Current(-100));
+            (count, invalid)
+        })
+        .await;
+
+        assert_eq!(count, 0);
+        assert!(
+            matches!(invalid, Err(ref e) if e.kind() == std::io::ErrorKind::InvalidInput),
+            "got: {invalid:?}"
+        );
+        assert!(fetcher.requests().is_empty(), "no ranges past the end");
+    }
+}
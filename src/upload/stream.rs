@@ -5,44 +5,71 @@
 //!
 //! Bytes are buffered into fixed-size blocks. Each full block is staged via
 //! [`BlockBlobClient::stage_block`]. Concurrency across staged blocks is
-//! bounded by a [`Semaphore`]. After the snapshot writer is finished, the
-//! caller invokes [`BlockBlobStream::finalize`] which awaits any in-flight
-//! stage operations and commits the block list. On failure, the caller
-//! invokes [`BlockBlobStream::abort`], which awaits in-flight tasks but
-//! does not commit; uncommitted blocks are discarded by Azure on its own
-//! timeline.
-
-use crate::upload::blobstore::Error;
+//! bounded by a [`Semaphore`], whose permits [`BlockBlobStream::adaptive`]
+//! adjusts as throughput is measured, and [`BlockBlobStream::max_rate`]
+//! holds staging to an average rate. After the snapshot writer is
+//! finished, the caller invokes [`BlockBlobStream::finalize`] which awaits
+//! any in-flight stage operations and commits the block list. On failure,
+//! the caller invokes [`BlockBlobStream::abort`], which awaits in-flight
+//! tasks but does not commit; uncommitted blocks are discarded by Azure on
+//! its own timeline.
+
+use crate::{
+    progress::{Operation, Reporter, Tracker},
+    throttle::RateLimit,
+    upload::blobstore::Error,
+};
 use async_trait::async_trait;
 use azure_core::{
     Bytes,
+    credentials::TokenCredential,
     http::{NoFormat, RequestContent, XmlFormat},
 };
 use azure_storage_blob::{
-    BlockBlobClient,
+    BlobClient, BlockBlobClient,
     models::{
         BlockBlobClientCommitBlockListOptions, BlockBlobClientStageBlockOptions, BlockLookupList,
     },
 };
-use core::num::NonZeroUsize;
+use core::num::{NonZeroU64, NonZeroUsize};
 use core::{
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use std::{
     io::{Result as IoResult, Write},
     sync::{Arc, Mutex},
+    time::Instant,
 };
 use tokio::{
     io::AsyncWrite,
     runtime::Handle,
-    sync::{Semaphore, mpsc},
+    sync::{OwnedSemaphorePermit, Semaphore, mpsc},
     task::JoinHandle,
 };
 use tokio_util::io::SyncIoBridge;
+use url::Url;
 
 type Result<T> = core::result::Result<T, Error>;
 
+type SharedTracker = Arc<Mutex<Option<Tracker>>>;
+
+type SharedTuning = Arc<Mutex<Option<Tuning>>>;
+
+type SharedRateLimit = Arc<Mutex<Option<RateLimit>>>;
+
+/// The most `stage_block` calls adaptive tuning keeps in flight, however
+/// large the buffer memory limit.
+const MAX_ADAPTIVE_CONCURRENCY: usize = 64;
+
+/// The fewest blocks staged in a window of throughput measurement.
+const MIN_WINDOW_BLOCKS: usize = 4;
+
+/// Windows to hold a concurrency that works before probing for more
+/// throughput, as the network may have changed.
+const PROBE_AFTER_WINDOWS: usize = 8;
+
 /// Block IDs as a fixed 8-byte big-endian representation of a u64 counter.
 /// Azure requires all block IDs within a single commit to have identical
 /// byte length; using the raw `to_be_bytes()` representation guarantees
@@ -131,8 +158,8 @@ type ReservationFuture = Pin<
 /// via a bounded mpsc.
 ///
 /// `poll_write` returns `Pending` when the channel is full, which gives
-/// the producer real backpressure: the bound is `concurrency`, matching
-/// the semaphore inside the uploader.
+/// the producer real backpressure: the bound is the channel depth, which
+/// is `concurrency` unless a buffer memory limit calls for less.
 struct BlockBlobAsyncWriter {
     sender: Option<mpsc::Sender<UploaderMsg>>,
     buf: Vec<u8>,
@@ -337,11 +364,13 @@ impl AsyncWrite for BlockBlobAsyncWriter {
 
 /// Public handle for streaming a memory snapshot into a Block Blob.
 ///
-/// Construct with [`BlockBlobStream::new`]. Drive the sync writer
-/// (returned by [`Self::writer`]) from a blocking context — typically
-/// inside [`tokio::task::spawn_blocking`]. After writing finishes,
-/// call [`Self::finish_writes`] from the same blocking context to flush
-/// the trailing partial block; then call [`Self::finalize`] (or
+/// Construct with [`BlockBlobStream::new`]. Either take the async writer
+/// with [`Self::async_writer`] and hand it to
+/// [`Snapshot::create_async`](crate::Snapshot::create_async), or drive the
+/// sync writer (returned by [`Self::writer`]) from a blocking context —
+/// typically inside [`tokio::task::spawn_blocking`] — and then call
+/// [`Self::finish_writes`] from the same blocking context to flush the
+/// trailing partial block. Then call [`Self::finalize`] (or
 /// [`Self::abort`]) from async context to await uploads and commit (or
 /// discard) the block list.
 ///
@@ -353,9 +382,31 @@ impl AsyncWrite for BlockBlobAsyncWriter {
 /// internal `block_on` deadlocks the current-thread runtime that the
 /// avml binary uses.
 pub struct BlockBlobStream {
-    bridge: SyncIoBridge<BlockBlobAsyncWriter>,
+    /// `None` once the async writer is taken.
+    bridge: Option<SyncIoBridge<BlockBlobAsyncWriter>>,
+    taken: TakenWriter,
     uploader: Option<JoinHandle<UploaderResult>>,
     stager: Arc<dyn BlockStager>,
+    progress: SharedTracker,
+    tuning: SharedTuning,
+    rate_limit: SharedRateLimit,
+    block_size: NonZeroUsize,
+    concurrency: NonZeroUsize,
+    channel_depth: NonZeroUsize,
+    max_buffer_memory: Option<NonZeroU64>,
+}
+
+/// The sync writer of a [`BlockBlobStream`] whose async writer was taken.
+struct TakenWriter;
+
+impl Write for TakenWriter {
+    fn write(&mut self, _: &[u8]) -> IoResult<usize> {
+        Err(std::io::Error::other("the async writer was taken"))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
 }
 
 /// Azure's per-blob block count limit. Public for callers (e.g. the
@@ -363,9 +414,31 @@ pub struct BlockBlobStream {
 pub const BLOB_MAX_BLOCKS: u64 = 50_000;
 
 impl BlockBlobStream {
-    /// Construct a streaming uploader against a live block blob.
-    #[must_use]
+    /// Construct a streaming uploader against the block blob at `url`,
+    /// which is either a SAS URL or, with a `credential`, the plain URL of
+    /// the blob.
+    ///
+    /// # Errors
+    /// Propagates any error returned by
+    /// [`BlobClient::new`](azure_storage_blob::BlobClient::new), for example
+    /// if the URL shape is not supported by the Azure SDK.
     pub fn new(
+        url: &Url,
+        credential: Option<Arc<dyn TokenCredential>>,
+        block_size: NonZeroUsize,
+        concurrency: NonZeroUsize,
+    ) -> Result<Self> {
+        Ok(Self::with_block_blob_client(
+            block_blob_client(url, credential)?,
+            block_size,
+            concurrency,
+        ))
+    }
+
+    /// Construct a streaming uploader with a ``BlockBlobClient`` from
+    /// ``azure_storage_blob``.
+    #[must_use]
+    pub fn with_block_blob_client(
         client: BlockBlobClient,
         block_size: NonZeroUsize,
         concurrency: NonZeroUsize,
@@ -379,45 +452,209 @@ impl BlockBlobStream {
         )
     }
 
+    /// Construct a streaming uploader against a live block blob that
+    /// buffers at most `max_buffer_memory` bytes of blocks: the one being
+    /// filled, those queued for the uploader, and those being staged.
+    /// Concurrency, and the depth of the queue, are lowered to fit.
+    ///
+    /// # Errors
+    /// Returns [`Error::BufferMemory`] if `max_buffer_memory` cannot hold
+    /// the three blocks needed to stream at all, or any error constructing
+    /// the client as [`Self::new`] does.
+    pub fn with_max_buffer_memory(
+        url: &Url,
+        credential: Option<Arc<dyn TokenCredential>>,
+        block_size: NonZeroUsize,
+        concurrency: NonZeroUsize,
+        max_buffer_memory: NonZeroU64,
+    ) -> Result<Self> {
+        Self::with_block_blob_client_and_max_buffer_memory(
+            block_blob_client(url, credential)?,
+            block_size,
+            concurrency,
+            max_buffer_memory,
+        )
+    }
+
+    /// Construct a streaming uploader with a ``BlockBlobClient`` from
+    /// ``azure_storage_blob`` that buffers at most `max_buffer_memory`
+    /// bytes of blocks, as [`Self::with_max_buffer_memory`] does.
+    ///
+    /// # Errors
+    /// Returns [`Error::BufferMemory`] if `max_buffer_memory` cannot hold
+    /// the three blocks needed to stream at all.
+    pub fn with_block_blob_client_and_max_buffer_memory(
+        client: BlockBlobClient,
+        block_size: NonZeroUsize,
+        concurrency: NonZeroUsize,
+        max_buffer_memory: NonZeroU64,
+    ) -> Result<Self> {
+        Self::with_stager_and_max_buffer_memory(
+            Arc::new(SdkStager {
+                client: Arc::new(client),
+            }),
+            block_size,
+            concurrency,
+            max_buffer_memory,
+        )
+    }
+
     pub(crate) fn with_stager(
         stager: Arc<dyn BlockStager>,
         block_size: NonZeroUsize,
         concurrency: NonZeroUsize,
     ) -> Self {
-        Self::with_stager_and_max_blocks(stager, block_size, concurrency, BLOB_MAX_BLOCKS)
+        Self::with_stager_and_max_blocks(
+            stager,
+            block_size,
+            concurrency,
+            concurrency,
+            BLOB_MAX_BLOCKS,
+        )
+    }
+
+    pub(crate) fn with_stager_and_max_buffer_memory(
+        stager: Arc<dyn BlockStager>,
+        block_size: NonZeroUsize,
+        concurrency: NonZeroUsize,
+        max_buffer_memory: NonZeroU64,
+    ) -> Result<Self> {
+        let (concurrency, channel_depth) = buffer_plan(block_size, concurrency, max_buffer_memory)?;
+        let stream = Self::with_stager_and_max_blocks(
+            stager,
+            block_size,
+            concurrency,
+            channel_depth,
+            BLOB_MAX_BLOCKS,
+        );
+        Ok(Self {
+            max_buffer_memory: Some(max_buffer_memory),
+            ..stream
+        })
     }
 
     pub(crate) fn with_stager_and_max_blocks(
         stager: Arc<dyn BlockStager>,
         block_size: NonZeroUsize,
         concurrency: NonZeroUsize,
+        channel_depth: NonZeroUsize,
         max_blocks: u64,
     ) -> Self {
         let handle = Handle::current();
         let error_slot = Arc::new(Mutex::new(None));
-        let (tx, rx) = mpsc::channel::<UploaderMsg>(concurrency.get());
+        let (tx, rx) = mpsc::channel::<UploaderMsg>(channel_depth.get());
+        let progress = Arc::new(Mutex::new(None));
+        let tuning = Arc::new(Mutex::new(None));
+        let rate_limit = Arc::new(Mutex::new(None));
 
         let uploader = handle.spawn(run_uploader(
             stager.clone(),
             rx,
             Arc::new(Semaphore::new(concurrency.get())),
             error_slot.clone(),
+            progress.clone(),
+            tuning.clone(),
+            rate_limit.clone(),
         ));
 
         let writer = BlockBlobAsyncWriter::new(tx, block_size, max_blocks, error_slot);
         let bridge = SyncIoBridge::new_with_handle(writer, handle);
 
         Self {
-            bridge,
+            bridge: Some(bridge),
+            taken: TakenWriter,
             uploader: Some(uploader),
             stager,
+            progress,
+            tuning,
+            rate_limit,
+            block_size,
+            concurrency,
+            channel_depth,
+            max_buffer_memory: None,
+        }
+    }
+
+    /// Tune the number of in-flight `stage_block` calls to the throughput
+    /// measured as blocks are staged, starting from the concurrency the
+    /// stream was constructed with. Concurrency rises while throughput
+    /// does, and falls when it stops paying off or latency climbs, but
+    /// never so far that buffered blocks would exceed the buffer memory
+    /// limit given to [`Self::with_max_buffer_memory`]. Without a limit,
+    /// concurrency never rises above where it started. Block size, and so
+    /// block IDs and the block count, are unaffected.
+    ///
+    /// Must be called before any writes.
+    #[must_use]
+    pub fn adaptive(self) -> Self {
+        let start = self.concurrency.get();
+        let max = self.max_adaptive_concurrency();
+        if let Ok(mut tuning) = self.tuning.lock() {
+            *tuning = Some(Tuning {
+                controller: Controller::new(start, max, Instant::now()),
+                permits: start,
+            });
+        }
+        self
+    }
+
+    // Besides the blocks in flight, a block may be buffered by the writer
+    // and one in each slot of the channel to the uploader. The buffer plan
+    // already fit the starting concurrency within the limit.
+    fn max_adaptive_concurrency(&self) -> usize {
+        let start = self.concurrency.get();
+        let Some(limit) = self.max_buffer_memory else {
+            return start;
+        };
+        let block_size = u64::try_from(self.block_size.get()).unwrap_or(u64::MAX);
+        let blocks =
+            usize::try_from(limit.get().checked_div(block_size).unwrap_or(0)).unwrap_or(usize::MAX);
+        blocks
+            .saturating_sub(self.channel_depth.get().saturating_add(1))
+            .min(MAX_ADAPTIVE_CONCURRENCY)
+            .max(start)
+    }
+
+    /// Stage blocks at no more than `bytes_per_second` on average, waiting
+    /// before each block until those before it have taken their share of
+    /// time.
+    ///
+    /// Must be called before any writes.
+    #[must_use]
+    pub fn max_rate(self, bytes_per_second: NonZeroU64) -> Self {
+        if let Ok(mut rate_limit) = self.rate_limit.lock() {
+            *rate_limit = Some(RateLimit::new(bytes_per_second));
         }
+        self
+    }
+
+    /// Report each block staged, and the commit of the block list, to
+    /// `reporter`.
+    #[must_use]
+    pub fn report_progress(self, reporter: Arc<dyn Reporter>) -> Self {
+        if let Ok(mut progress) = self.progress.lock() {
+            *progress = Some(Tracker::new(Some(reporter), Operation::Upload));
+        }
+        self
     }
 
     /// Returns the sync writer to feed into the snapshot pipeline.
-    /// Must be driven from a blocking thread.
+    /// Must be driven from a blocking thread. Every write fails once the
+    /// async writer is taken.
     pub fn writer(&mut self) -> &mut dyn Write {
-        &mut self.bridge
+        match self.bridge {
+            Some(ref mut bridge) => bridge,
+            None => &mut self.taken,
+        }
+    }
+
+    /// Takes the writer as an [`AsyncWrite`], to feed into the snapshot
+    /// pipeline from async context. Shutting it down flushes the partial
+    /// trailing block, so [`Self::finish_writes`] is not needed.
+    ///
+    /// Returns `None` if the async writer was already taken.
+    pub fn async_writer(&mut self) -> Option<impl AsyncWrite + Unpin + Send + 'static> {
+        self.bridge.take().map(SyncIoBridge::into_inner)
     }
 
     /// Flush any partial trailing block. Must be called from the same
@@ -428,7 +665,7 @@ impl BlockBlobStream {
     /// returns one (e.g., the uploader task already exited due to an
     /// upload failure).
     pub fn finish_writes(&mut self) -> IoResult<()> {
-        self.bridge.shutdown()
+        self.bridge.as_mut().map_or(Ok(()), SyncIoBridge::shutdown)
     }
 
     /// Await all in-flight `stage_block` calls and commit the block list.
@@ -445,7 +682,13 @@ impl BlockBlobStream {
         let mut indices = result.completed;
         indices.sort_unstable();
         let block_ids: Vec<Vec<u8>> = indices.into_iter().map(block_id).collect();
-        self.stager.commit_block_list(block_ids).await
+        self.stager.commit_block_list(block_ids).await?;
+        if let Ok(mut progress) = self.progress.lock()
+            && let Some(mut progress) = progress.take()
+        {
+            progress.finish();
+        }
+        Ok(())
     }
 
     /// Close the writer side and await all in-flight `stage_block` calls
@@ -470,8 +713,16 @@ impl BlockBlobStream {
     fn close_for_abort(self) -> Option<JoinHandle<UploaderResult>> {
         let Self {
             bridge: _closed_bridge,
+            taken: _,
             uploader,
             stager: _,
+            progress: _,
+            tuning: _,
+            rate_limit: _,
+            block_size: _,
+            concurrency: _,
+            channel_depth: _,
+            max_buffer_memory: _,
         } = self;
         uploader
     }
@@ -491,31 +742,87 @@ impl BlockBlobStream {
     }
 }
 
+fn block_blob_client(
+    url: &Url,
+    credential: Option<Arc<dyn TokenCredential>>,
+) -> Result<BlockBlobClient> {
+    Ok(BlobClient::new(url.clone(), credential, None)?.block_blob_client())
+}
+
+/// Split the blocks that fit in `max_buffer_memory` between those in
+/// flight and the depth of the channel feeding them, after the one block
+/// the writer fills. Concurrency is kept as requested where it fits.
+fn buffer_plan(
+    block_size: NonZeroUsize,
+    concurrency: NonZeroUsize,
+    max_buffer_memory: NonZeroU64,
+) -> Result<(NonZeroUsize, NonZeroUsize)> {
+    let block_size = u64::try_from(block_size.get())?;
+    let blocks = usize::try_from(max_buffer_memory.get().checked_div(block_size).unwrap_or(0))
+        .unwrap_or(usize::MAX);
+    // at least one block being filled, one queued, and one in flight
+    if blocks < 3 {
+        return Err(Error::BufferMemory {
+            max_buffer_memory: max_buffer_memory.get(),
+            block_size,
+            blocks: 3,
+        });
+    }
+    let available = blocks.saturating_sub(1);
+    let concurrency = NonZeroUsize::new(available.saturating_sub(1))
+        .map_or(NonZeroUsize::MIN, |max| concurrency.min(max));
+    let channel_depth = NonZeroUsize::new(available.saturating_sub(concurrency.get()))
+        .unwrap_or(NonZeroUsize::MIN)
+        .min(concurrency);
+    Ok((concurrency, channel_depth))
+}
+
 async fn run_uploader(
     stager: Arc<dyn BlockStager>,
     mut rx: mpsc::Receiver<UploaderMsg>,
     semaphore: Arc<Semaphore>,
     error_slot: Arc<Mutex<Option<Error>>>,
+    progress: SharedTracker,
+    tuning: SharedTuning,
+    rate_limit: SharedRateLimit,
 ) -> UploaderResult {
     let mut in_flight: Vec<JoinHandle<core::result::Result<u64, (u64, Error)>>> = Vec::new();
 
-    while let Some(msg) = rx.recv().await {
+    loop {
+        // Acquire a permit (bounded in-flight) before taking a block from
+        // the channel, so no block waits outside it. Held by the worker
+        // task until stage_block completes.
+        let Ok(permit) = semaphore.clone().acquire_owned().await else {
+            break;
+        };
+        let Some(msg) = rx.recv().await else {
+            break;
+        };
         match msg {
             UploaderMsg::Stage { index, data } => {
-                // Acquire a permit (bounded in-flight). Held by the worker
-                // task until stage_block completes.
-                let Ok(permit) = semaphore.clone().acquire_owned().await else {
-                    break;
-                };
+                let wait = rate_limit.lock().ok().and_then(|mut limit| {
+                    let len = u64::try_from(data.len()).unwrap_or(u64::MAX);
+                    limit.as_mut().map(|limit| limit.reserve(len))
+                });
+                if let Some(wait) = wait.filter(|wait| !wait.is_zero()) {
+                    tokio::time::sleep(wait).await;
+                }
                 let stager = stager.clone();
+                let progress = progress.clone();
+                let tuning = tuning.clone();
+                let semaphore = semaphore.clone();
                 let id = block_id(index);
                 let worker = tokio::spawn(async move {
-                    let _permit = permit;
-                    stager
-                        .stage_block(id, data)
-                        .await
-                        .map(|()| index)
-                        .map_err(|e| (index, e))
+                    let len = u64::try_from(data.len()).unwrap_or(u64::MAX);
+                    let started = Instant::now();
+                    stager.stage_block(id, data).await.map_err(|e| (index, e))?;
+                    tune(&tuning, &semaphore, permit, len, started.elapsed());
+                    if let Ok(mut progress) = progress.lock()
+                        && let Some(progress) = progress.as_mut()
+                    {
+                        progress.written(len);
+                    }
+                    Ok(index)
                 });
                 in_flight.push(worker);
             }
@@ -552,6 +859,188 @@ async fn run_uploader(
     }
 }
 
+/// Adaptive tuning of a stream's concurrency, and the permits of its
+/// semaphore that exist to carry it out.
+struct Tuning {
+    controller: Controller,
+    permits: usize,
+}
+
+// Record a block staged, and release `permit`, or forget it if the
+// controller wants fewer blocks in flight.
+fn tune(
+    tuning: &SharedTuning,
+    semaphore: &Semaphore,
+    permit: OwnedSemaphorePermit,
+    bytes: u64,
+    latency: Duration,
+) {
+    let Ok(mut tuning) = tuning.lock() else {
+        return;
+    };
+    let Some(tuning) = tuning.as_mut() else {
+        return;
+    };
+    let limit = tuning.controller.record(bytes, latency, Instant::now());
+    if tuning.permits > limit {
+        permit.forget();
+        tuning.permits = tuning.permits.saturating_sub(1);
+    } else if tuning.permits < limit {
+        semaphore.add_permits(limit.saturating_sub(tuning.permits));
+        tuning.permits = limit;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    /// Holding for this many windows.
+    Hold(usize),
+}
+
+/// Blocks staged since a window of measurement started.
+struct Window {
+    start: Instant,
+    bytes: u64,
+    blocks: usize,
+    latency: Duration,
+}
+
+impl Window {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            bytes: 0,
+            blocks: 0,
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Chooses how many `stage_block` calls to keep in flight by hill
+/// climbing on the throughput of successive windows of blocks: it steps
+/// concurrency the way that last raised throughput, returns to the last
+/// concurrency when a step does not pay off, and probes upward again after
+/// holding for a while. It steps down whenever latency has doubled, as
+/// blocks are then queueing rather than moving. The first window after a
+/// change is not measured, as it mixes blocks staged before and after.
+struct Controller {
+    limit: usize,
+    max: usize,
+    direction: Direction,
+    window: Window,
+    // the limit and throughput, in bytes per second, of the last window
+    last: Option<(usize, u64)>,
+    // the lowest mean latency of any window
+    best_latency: Option<Duration>,
+    // whether the window holds blocks started before the limit changed
+    settling: bool,
+}
+
+impl Controller {
+    fn new(limit: usize, max: usize, now: Instant) -> Self {
+        Self {
+            limit: limit.clamp(1, max.max(1)),
+            max: max.max(1),
+            direction: Direction::Up,
+            window: Window::new(now),
+            last: None,
+            best_latency: None,
+            settling: false,
+        }
+    }
+
+    /// Record a block of `bytes` staged in `latency`, returning the
+    /// number of blocks to keep in flight.
+    fn record(&mut self, bytes: u64, latency: Duration, now: Instant) -> usize {
+        self.window.bytes = self.window.bytes.saturating_add(bytes);
+        self.window.blocks = self.window.blocks.saturating_add(1);
+        self.window.latency = self.window.latency.saturating_add(latency);
+        if self.window.blocks < self.limit.max(MIN_WINDOW_BLOCKS) {
+            return self.limit;
+        }
+        if self.settling {
+            self.settling = false;
+            self.window = Window::new(now);
+            return self.limit;
+        }
+
+        let elapsed = now.saturating_duration_since(self.window.start).as_nanos();
+        let throughput = u128::from(self.window.bytes)
+            .saturating_mul(1_000_000_000)
+            .checked_div(elapsed)
+            .map_or(u64::MAX, |throughput| {
+                u64::try_from(throughput).unwrap_or(u64::MAX)
+            });
+        let mean_latency = self
+            .window
+            .latency
+            .checked_div(u32::try_from(self.window.blocks).unwrap_or(u32::MAX))
+            .unwrap_or_default();
+        let best_latency = self
+            .best_latency
+            .map_or(mean_latency, |best| best.min(mean_latency));
+        self.best_latency = Some(best_latency);
+        // queueing at the service or on the network, rather than more work
+        // getting done
+        let congested = mean_latency > best_latency.saturating_mul(2);
+
+        let limit = self.limit;
+        self.adjust(throughput, congested);
+        self.settling = self.limit != limit;
+        self.window = Window::new(now);
+        self.limit
+    }
+
+    fn adjust(&mut self, throughput: u64, congested: bool) {
+        let Some((last_limit, last_throughput)) = self.last.replace((self.limit, throughput))
+        else {
+            self.step(Direction::Up);
+            return;
+        };
+        let margin = last_throughput / 10;
+        let better = throughput > last_throughput.saturating_add(margin);
+        let worse = throughput < last_throughput.saturating_sub(margin);
+
+        match self.direction {
+            Direction::Up if better => self.step(Direction::Up),
+            // fewer blocks in flight for the same throughput saves memory
+            Direction::Down if !worse => self.step(Direction::Down),
+            Direction::Up => {
+                self.limit = last_limit;
+                self.direction = Direction::Hold(0);
+            }
+            Direction::Down => {
+                // the link needs this many blocks in flight at its current
+                // latency, so take that latency as the new baseline
+                self.limit = last_limit;
+                self.direction = Direction::Hold(0);
+                self.best_latency = None;
+            }
+            Direction::Hold(_) if congested => self.step(Direction::Down),
+            Direction::Hold(windows) if windows.saturating_add(1) >= PROBE_AFTER_WINDOWS => {
+                self.step(Direction::Up);
+            }
+            Direction::Hold(windows) => self.direction = Direction::Hold(windows.saturating_add(1)),
+        }
+    }
+
+    fn step(&mut self, direction: Direction) {
+        let limit = match direction {
+            Direction::Up => self.limit.saturating_add(1).min(self.max),
+            Direction::Down => self.limit.saturating_sub(1).max(1),
+            Direction::Hold(_) => self.limit,
+        };
+        self.direction = if limit == self.limit {
+            Direction::Hold(0)
+        } else {
+            direction
+        };
+        self.limit = limit;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![expect(
@@ -690,6 +1179,7 @@ mod tests {
             stager,
             nz(block_size),
             nz(concurrency),
+            nz(concurrency),
             max_blocks,
         )
     }
@@ -914,6 +1404,7 @@ mod tests {
             .source(Some(Source::Raw(source_path)))
             .format(format)
             .create_to_writer(writer)
+            .map(drop)
             .map_err(|err| std::io::Error::other(err.to_string()))
         })
         .await;
@@ -938,6 +1429,32 @@ mod tests {
         assert_snapshot_streams_end_to_end(Format::AvmlCompressed).await;
     }
 
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn snapshot_streams_through_the_async_writer() {
+        let fixture = SnapshotFixture::new();
+        let stager = Arc::new(FakeStager::new());
+        let blob_block_size = 1_000_000;
+        let mut stream = build_stream(stager.clone(), blob_block_size, 4);
+
+        let writer = stream.async_writer().expect("async writer not yet taken");
+        assert!(stream.async_writer().is_none());
+        assert!(stream.writer().write_all(b"late").is_err());
+        Snapshot::new(
+            Path::new("unused-streaming-test-destination"),
+            fixture.memory_ranges.clone(),
+        )
+        .source(Some(Source::Raw(fixture.source_path.clone())))
+        .create_async(writer)
+        .await
+        .expect("snapshot writes and shuts down blob stream");
+        stream.finish_writes().expect("nothing left to flush");
+        stream.finalize().await.expect("finalize commits blocks");
+
+        let lime = committed_stream(&stager, blob_block_size);
+        assert_lime_payload_matches(&lime, &fixture.expected_ranges, &fixture.payload);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn finalize_with_zero_writes_commits_empty_block_list() {
         let stager = Arc::new(FakeStager::new());
@@ -979,6 +1496,48 @@ mod tests {
         assert_eq!(ids, &sorted, "committed ids are sorted ascending");
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn reports_bytes_uploaded() {
+        let stager = Arc::new(FakeStager::new());
+        let reports = Arc::new(StdMutex::new(Vec::new()));
+        let sink = reports.clone();
+        let stream = build_stream(stager.clone(), 4, 3).report_progress(Arc::new(
+            move |progress: &crate::progress::Progress| {
+                sink.lock()
+                    .expect("reports lock")
+                    .push((progress.bytes_written, progress.done));
+            },
+        ));
+
+        let payload: Vec<u8> = (0..6).collect();
+        let (stream, result) = run_write(stream, move |w| w.write_all(&payload)).await;
+        result.expect("write + shutdown");
+        stream.finalize().await.expect("finalize");
+
+        // blocks may finish staging in either order
+        let reports = reports.lock().expect("reports lock").clone();
+        assert_eq!(reports.len(), 3, "one report per block, then done");
+        assert!(matches!(reports[0], (2 | 4, false)), "{reports:?}");
+        assert_eq!(&reports[1..], &[(6, false), (6, true)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn max_rate_spaces_out_staging() {
+        let stager = Arc::new(FakeStager::new());
+        let rate = NonZeroU64::new(1000).expect("test constant non-zero");
+        let stream = build_stream(stager.clone(), 100, 4).max_rate(rate);
+
+        let start = Instant::now();
+        let (stream, result) = run_write(stream, |w| w.write_all(&[1; 400])).await;
+        result.expect("write + shutdown");
+        stream.finalize().await.expect("finalize");
+
+        // the first block never waits; the later ones wait for the 100
+        // bytes before each of them
+        assert_eq!(stager.locked_staged().len(), 4);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn trailing_partial_block_is_staged_on_shutdown() {
         let stager = Arc::new(FakeStager::new());
@@ -1130,6 +1689,145 @@ mod tests {
         assert!(observed <= 4, "must not exceed configured concurrency");
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn adaptive_concurrency_stays_within_the_buffer_memory_limit() {
+        let stager = Arc::new(FakeStager::new());
+        // one byte blocks: one buffered by the writer, one in the channel,
+        // and room for two in flight
+        let limit = NonZeroU64::new(4).expect("test constant non-zero");
+        let stream =
+            BlockBlobStream::with_stager_and_max_buffer_memory(stager.clone(), nz(1), nz(2), limit)
+                .expect("limit fits")
+                .adaptive();
+        assert_eq!(stream.max_adaptive_concurrency(), 2);
+
+        let payload: Vec<u8> = (0..8).collect();
+        let (stream, result) = run_write(stream, move |w| w.write_all(&payload)).await;
+        result.expect("write + shutdown");
+        stream.finalize().await.expect("finalize");
+
+        let observed = stager.max_concurrent_stages.load(Ordering::SeqCst);
+        assert!(
+            observed <= 2,
+            "the limit leaves room for two blocks in flight"
+        );
+        assert_eq!(stager.stage_call_count.load(Ordering::SeqCst), 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn max_buffer_memory_lowers_concurrency() {
+        let stager = Arc::new(FakeStager::new());
+        // one byte blocks: one buffered by the writer, leaving four to
+        // split between the channel and those in flight
+        let limit = NonZeroU64::new(5).expect("test constant non-zero");
+        let stream =
+            BlockBlobStream::with_stager_and_max_buffer_memory(stager.clone(), nz(1), nz(8), limit)
+                .expect("limit fits");
+        assert_eq!(stream.concurrency, nz(3));
+        assert_eq!(stream.channel_depth, nz(1));
+
+        let payload: Vec<u8> = (0..16).collect();
+        let (stream, result) = run_write(stream, move |w| w.write_all(&payload)).await;
+        result.expect("write + shutdown");
+        stream.finalize().await.expect("finalize");
+
+        assert!(stager.max_concurrent_stages.load(Ordering::SeqCst) <= 3);
+        assert_eq!(stager.stage_call_count.load(Ordering::SeqCst), 16);
+    }
+
+    #[test]
+    fn max_buffer_memory_must_fit_three_blocks() {
+        let limit = NonZeroU64::new(8 * 1024).expect("test constant non-zero");
+        assert!(matches!(
+            buffer_plan(nz(4 * 1024), nz(4), limit),
+            Err(Error::BufferMemory { blocks: 3, .. })
+        ));
+        assert_eq!(
+            buffer_plan(nz(4 * 1024), nz(4), limit.saturating_add(4 * 1024)).ok(),
+            Some((nz(1), nz(1)))
+        );
+        assert_eq!(
+            buffer_plan(
+                nz(1024),
+                nz(4),
+                NonZeroU64::new(100 * 1024).expect("non-zero")
+            )
+            .ok(),
+            Some((nz(4), nz(4)))
+        );
+    }
+
+    // Stage `blocks` one MiB blocks through a link that carries at most
+    // `capacity` blocks at a time at 10 MiB/s each, so latency grows with
+    // every block in flight past `capacity`. Returns the highest
+    // concurrency the controller chose.
+    fn simulate(
+        controller: &mut Controller,
+        now: &mut Instant,
+        capacity: usize,
+        blocks: usize,
+    ) -> usize {
+        const BLOCK: u64 = 1024 * 1024;
+        const RATE: u64 = 10 * 1024 * 1024;
+        let mut highest = controller.limit;
+        for _ in 0..blocks {
+            let in_flight = controller.limit;
+            let throughput = u64::try_from(in_flight.min(capacity))
+                .expect("test value fits u64")
+                .saturating_mul(RATE);
+            let interval = Duration::from_nanos(
+                (BLOCK * 1_000_000_000)
+                    .checked_div(throughput)
+                    .expect("non-zero throughput"),
+            );
+            let latency =
+                interval.saturating_mul(u32::try_from(in_flight).expect("test value fits u32"));
+            *now = now.checked_add(interval).expect("simulated time fits");
+            highest = highest.max(controller.record(BLOCK, latency, *now));
+        }
+        highest
+    }
+
+    #[test]
+    fn controller_climbs_to_the_link_capacity() {
+        let mut now = Instant::now();
+        let mut controller = Controller::new(2, 32, now);
+        let highest = simulate(&mut controller, &mut now, 6, 2_000);
+        assert!(
+            highest <= 7,
+            "stops climbing once throughput does: {highest}"
+        );
+        assert!(
+            (6..=7).contains(&controller.limit),
+            "settles at the link capacity: {}",
+            controller.limit
+        );
+    }
+
+    #[test]
+    fn controller_never_exceeds_its_maximum() {
+        let mut now = Instant::now();
+        let mut controller = Controller::new(1, 4, now);
+        let highest = simulate(&mut controller, &mut now, 100, 2_000);
+        assert_eq!(highest, 4);
+        assert_eq!(controller.limit, 4);
+    }
+
+    #[test]
+    fn controller_backs_off_when_the_link_congests() {
+        let mut now = Instant::now();
+        let mut controller = Controller::new(2, 32, now);
+        simulate(&mut controller, &mut now, 6, 2_000);
+        assert!(controller.limit >= 6);
+
+        simulate(&mut controller, &mut now, 2, 2_000);
+        assert!(
+            (2..=3).contains(&controller.limit),
+            "returns to what the link carries: {}",
+            controller.limit
+        );
+    }
+
     #[test]
     fn block_id_round_trip() {
         for i in [0_u64, 1, 100, u64::MAX] {
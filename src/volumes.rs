@@ -0,0 +1,319 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+//! Snapshot output split across size-capped files.
+//!
+//! [`Volumes`] learns where each record ends from the flush that [`Image`]
+//! does after every record when `flush_records` is set. Only the header of
+//! each record is held back, until it shows how large the record can be and
+//! so which volume it goes in; the rest is written as it comes. Records
+//! never span volumes, so every volume is a valid snapshot on its own.
+//!
+//! [`Image`]: crate::image::Image
+
+#[cfg(target_family = "unix")]
+use crate::disk_usage;
+use crate::{
+    image::{HEADER_LEN, Header, max_record_len, open_dst},
+    io::digest::to_hex,
+    snapshot::{Error, Result},
+    split::{remove_volumes_after, volume_path},
+};
+use core::{num::NonZeroU64, ops::Range};
+use sha2::{Digest as _, Sha256};
+#[cfg(not(target_family = "unix"))]
+use std::env::consts::OS;
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+struct Volume {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    hash: Sha256,
+}
+
+impl Volume {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(buf)?;
+        self.hash.update(buf);
+        self.written = self
+            .written
+            .saturating_add(u64::try_from(buf.len()).unwrap_or(u64::MAX));
+        Ok(())
+    }
+}
+
+/// A snapshot destination that rolls over to `base.001`, `base.002`, and so
+/// on, starting a new volume whenever the next record could take the
+/// current one past `max_size` bytes.
+///
+/// As a compressed record's size is only known once written, the most it
+/// can take decides where it goes, so a volume may end short of
+/// `max_size`. A record larger than `max_size` gets a volume to itself.
+/// Once every record is written, [`Volumes::finish`] writes `base.parts`,
+/// listing every volume with its SHA-256 in the format used by `sha256sum`,
+/// and removes any later volumes left by an earlier, larger snapshot.
+pub struct Volumes {
+    base: PathBuf,
+    max_size: NonZeroU64,
+    max_disk_usage: Option<NonZeroU64>,
+    max_disk_usage_percentage: Option<f64>,
+    // memory not yet written to a completed volume
+    remaining: u64,
+    // the start of the header of the next record, until it is complete
+    header: Vec<u8>,
+    current: Volume,
+    completed: Vec<(PathBuf, String)>,
+}
+
+impl Volumes {
+    /// Create the first volume for a snapshot of `memory_ranges`, checking
+    /// that it fits within the disk usage limits.
+    pub fn create(
+        base: &Path,
+        max_size: NonZeroU64,
+        memory_ranges: &[Range<u64>],
+        max_disk_usage: Option<NonZeroU64>,
+        max_disk_usage_percentage: Option<f64>,
+    ) -> Result<Self> {
+        let remaining = memory_ranges
+            .iter()
+            .map(|range| range.end.saturating_sub(range.start))
+            .fold(0, u64::saturating_add);
+        let current = open_volume(base, 1)?;
+        let volumes = Self {
+            base: base.to_path_buf(),
+            max_size,
+            max_disk_usage,
+            max_disk_usage_percentage,
+            remaining,
+            header: Vec::with_capacity(HEADER_LEN),
+            current,
+            completed: Vec::new(),
+        };
+        volumes.check_disk_usage()?;
+        Ok(volumes)
+    }
+
+    /// Start the record `header`, first starting a new volume if the record
+    /// might not fit in the current one.
+    fn start_record(&mut self) -> Result<()> {
+        let header = Header::read(self.header.as_slice())?;
+        let fits = self
+            .current
+            .written
+            .checked_add(max_record_len(&header))
+            .is_some_and(|total| total <= self.max_size.get());
+        if self.current.written > 0 && !fits {
+            self.next_volume()?;
+        }
+        self.current.write(&self.header).map_err(Error::Disk)
+    }
+
+    fn next_volume(&mut self) -> Result<()> {
+        let number = self.completed.len().saturating_add(2);
+        let next = open_volume(&self.base, number)?;
+        let done = core::mem::replace(&mut self.current, next);
+        self.remaining = self.remaining.saturating_sub(done.written);
+        self.completed.push(close_volume(done)?);
+        self.check_disk_usage()
+    }
+
+    /// Check that the current volume, which is assumed to be filled up to
+    /// `max_size` or with the rest of memory, fits on its disk.
+    #[cfg(target_family = "unix")]
+    fn check_disk_usage(&self) -> Result<()> {
+        disk_usage::check_estimate(
+            &self.current.path,
+            self.remaining.min(self.max_size.get()),
+            self.max_disk_usage,
+            self.max_disk_usage_percentage,
+        )
+    }
+
+    /// Check disk usage of the current volume
+    ///
+    /// On non-Unix platforms, this operation is a no-op.
+    #[cfg(not(target_family = "unix"))]
+    fn check_disk_usage(&self) -> Result<()> {
+        if self.max_disk_usage.is_some() || self.max_disk_usage_percentage.is_some() {
+            return Err(Error::UnsupportedPlatform { os: OS });
+        }
+        Ok(())
+    }
+
+    /// Write the list of volumes, and remove any volumes after the last,
+    /// returning each volume with its SHA-256.
+    pub fn finish(mut self) -> Result<Vec<(PathBuf, String)>> {
+        self.completed.push(close_volume(self.current)?);
+        remove_volumes_after(&self.base, self.completed.len()).map_err(Error::Disk)?;
+
+        let mut parts_path = OsString::from(self.base.as_os_str());
+        parts_path.push(".parts");
+        let mut parts = String::new();
+        for volume in &self.completed {
+            let (ref path, ref digest) = *volume;
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            let _ = writeln!(parts, "{digest}  {}", name.to_string_lossy());
+        }
+        let mut dst = open_dst(Path::new(&parts_path))?;
+        dst.write_all(parts.as_bytes()).map_err(Error::Disk)?;
+        Ok(self.completed)
+    }
+}
+
+impl Write for Volumes {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.header.len() >= HEADER_LEN {
+            self.current.write(buf)?;
+            return Ok(buf.len());
+        }
+        let needed = HEADER_LEN.saturating_sub(self.header.len());
+        let taken = buf.get(..needed).unwrap_or(buf);
+        self.header.extend_from_slice(taken);
+        if self.header.len() >= HEADER_LEN {
+            self.start_record().map_err(std::io::Error::other)?;
+        }
+        Ok(taken.len())
+    }
+
+    // the end of a record
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.header.is_empty() && self.header.len() < HEADER_LEN {
+            return Err(std::io::Error::other("record ended within its header"));
+        }
+        self.header.clear();
+        Ok(())
+    }
+}
+
+fn open_volume(base: &Path, number: usize) -> Result<Volume> {
+    let path = volume_path(base, number);
+    let file = open_dst(&path)?;
+    Ok(Volume {
+        path,
+        file,
+        written: 0,
+        hash: Sha256::new(),
+    })
+}
+
+fn close_volume(mut volume: Volume) -> Result<(PathBuf, String)> {
+    volume.file.flush().map_err(Error::Disk)?;
+    Ok((volume.path, to_hex(&volume.hash.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Volumes;
+    use crate::{
+        image::{Block, BlockData, BlockReader, Format, Image},
+        io::digest::to_hex,
+        snapshot::{Error, Result},
+    };
+    use core::{fmt::Write as _, num::NonZeroU64, ops::Range};
+    use rand::{Rng as _, SeedableRng as _, rngs::SmallRng};
+    use sha2::{Digest as _, Sha256};
+    use std::{fs, io::Cursor};
+    use tempfile::tempdir;
+
+    const RANGES: [Range<u64>; 2] = [0x0..0x3000, 0x8000..0xa000];
+    // room for two records of a 32-byte header and a page each
+    const MAX_SIZE: NonZeroU64 = NonZeroU64::new(2 * (32 + 0x1000)).expect("size is non-zero");
+
+    fn read(path: &std::path::Path) -> Result<Vec<u8>> {
+        fs::read(path).map_err(Error::Disk)
+    }
+
+    #[test]
+    fn rolls_over_at_record_boundaries() -> Result<()> {
+        let dir = tempdir().map_err(Error::Disk)?;
+        let base = dir.path().join("memory.lime");
+        let memory = (0..0xa000_u32)
+            .map(|i| u8::try_from(i >> 12).unwrap_or_default().wrapping_add(1))
+            .collect::<Vec<_>>();
+
+        let mut volumes = Volumes::create(&base, MAX_SIZE, &RANGES, None, None)?;
+        let mut image = Image::from_streams(Format::Lime, Cursor::new(memory), &mut volumes);
+        image.flush_records = true;
+        let blocks = RANGES
+            .iter()
+            .flat_map(|range| range.clone().step_by(0x1000))
+            .map(|start| Block {
+                offset: start,
+                range: start..start.saturating_add(0x1000),
+            })
+            .collect::<Vec<_>>();
+        image.write_blocks(&blocks)?;
+        volumes.finish()?;
+
+        let names = ["memory.lime.001", "memory.lime.002", "memory.lime.003"];
+        let mut expected_parts = String::new();
+        let mut starts = Vec::new();
+        for name in names {
+            let volume = read(&dir.path().join(name))?;
+            let _ = writeln!(
+                expected_parts,
+                "{}  {name}",
+                to_hex(&Sha256::digest(&volume))
+            );
+            for block in BlockReader::new(volume.as_slice()) {
+                let BlockData { range, .. } = block?;
+                starts.push(range.start);
+            }
+        }
+        assert_eq!(starts, vec![0x0, 0x1000, 0x2000, 0x8000, 0x9000]);
+        assert!(!dir.path().join("memory.lime.004").exists());
+        assert_eq!(
+            read(&dir.path().join("memory.lime.parts"))?,
+            expected_parts.into_bytes()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_volumes_stay_within_max_size() -> Result<()> {
+        let dir = tempdir().map_err(Error::Disk)?;
+        let base = dir.path().join("memory.avml");
+        fs::write(dir.path().join("memory.avml.006"), b"stale").map_err(Error::Disk)?;
+        // incompressible, so each record is larger than the memory it holds
+        let mut memory = vec![0; 0xa000];
+        SmallRng::seed_from_u64(0).fill_bytes(&mut memory);
+
+        let mut volumes = Volumes::create(&base, MAX_SIZE, &RANGES, None, None)?;
+        let mut image =
+            Image::from_streams(Format::AvmlCompressed, Cursor::new(memory), &mut volumes);
+        image.flush_records = true;
+        let blocks = RANGES
+            .iter()
+            .flat_map(|range| range.clone().step_by(0x1000))
+            .map(|start| Block {
+                offset: start,
+                range: start..start.saturating_add(0x1000),
+            })
+            .collect::<Vec<_>>();
+        image.write_blocks(&blocks)?;
+        let completed = volumes.finish()?;
+        // the most a record can take leaves room for only one per volume
+        assert_eq!(completed.len(), 5);
+
+        let mut starts = Vec::new();
+        for volume in &completed {
+            let data = read(&volume.0)?;
+            assert!(u64::try_from(data.len()).is_ok_and(|len| len <= MAX_SIZE.get()));
+            for block in BlockReader::new(data.as_slice()) {
+                let BlockData { range, .. } = block?;
+                starts.push(range.start);
+            }
+        }
+        assert_eq!(starts, vec![0x0, 0x1000, 0x2000, 0x8000, 0x9000]);
+        assert!(!dir.path().join("memory.avml.006").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License.
+
+#![cfg(feature = "blobstore")]
+
+use avml::{BlobReader, Format, image::Image};
+use core::{
+    error::Error,
+    num::{NonZeroU64, NonZeroUsize},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// Serve `HEAD` and ranged `GET` requests for a single in-memory blob, the
+/// subset of the Blob REST API that `BlobReader` relies on.
+async fn serve_blob(
+    listener: TcpListener,
+    blob: Arc<Vec<u8>>,
+    ranged_gets: Arc<AtomicUsize>,
+) -> std::io::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let blob = blob.clone();
+        let ranged_gets = ranged_gets.clone();
+        tokio::spawn(async move { drop(serve_connection(socket, &blob, &ranged_gets).await) });
+    }
+}
+
+async fn serve_connection(
+    socket: TcpStream,
+    blob: &[u8],
+    ranged_gets: &AtomicUsize,
+) -> std::io::Result<()> {
+    let mut socket = BufReader::new(socket);
+    loop {
+        let mut request_line = String::new();
+        if socket.read_line(&mut request_line).await? == 0 {
+            return Ok(());
+        }
+        let mut range = None;
+        loop {
+            let mut line = String::new();
+            socket.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("range")
+            {
+                range = value.trim().strip_prefix("bytes=").and_then(|r| {
+                    let (start, end) = r.split_once('-')?;
+                    Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+                });
+            }
+        }
+
+        let total = blob.len();
+        let response = if request_line.starts_with("HEAD ") {
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {total}\r\nx-ms-blob-type: BlockBlob\r\nETag: \"0x1\"\r\n\r\n"
+            )
+            .into_bytes()
+        } else if let Some((start, end)) = range {
+            ranged_gets.fetch_add(1, Ordering::SeqCst);
+            let end = end.min(total.saturating_sub(1));
+            let body = blob.get(start..=end).unwrap_or_default();
+            let mut response = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {start}-{end}/{total}\r\nx-ms-blob-type: BlockBlob\r\nETag: \"0x1\"\r\n\r\n",
+                body.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(body);
+            response
+        } else {
+            b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n".to_vec()
+        };
+        socket.get_mut().write_all(&response).await?;
+    }
+}
+
+fn encode(raw: &[u8], format: Format) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut image = Image::from_streams(format, raw, Vec::new());
+    let mut start = 0_u64;
+    loop {
+        let len = image.copy_raw_block(start)?;
+        if len == 0 {
+            break;
+        }
+        start = start.saturating_add(len);
+    }
+    Ok(image.dst)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn convert_reads_compressed_blob_with_ranged_gets() -> Result<(), Box<dyn Error>> {
+    let raw: Vec<u8> = (0..3_000_000_u32)
+        .map(|i| u8::try_from(i.wrapping_mul(2_654_435_761) >> 24).unwrap_or_default())
+        .collect();
+    let compressed = encode(&raw, Format::AvmlCompressed)?;
+    let expected = encode(&raw, Format::Lime)?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let ranged_gets = Arc::new(AtomicUsize::new(0));
+    let server = tokio::spawn(serve_blob(
+        listener,
+        Arc::new(compressed.clone()),
+        ranged_gets.clone(),
+    ));
+
+    let url = format!("http://{addr}/devstoreaccount1/container/blob?sig=fake").parse()?;
+    let reader = BlobReader::new(&url)?
+        .block_size(NonZeroU64::new(1))
+        .concurrency(NonZeroUsize::new(2));
+
+    let converted = tokio::task::spawn_blocking(move || {
+        let mut image = Image::from_streams(Format::Lime, reader, Vec::new());
+        image.convert_blocks().map(|()| image.dst)
+    })
+    .await??;
+    server.abort();
+
+    assert_eq!(converted, expected);
+    let chunks = compressed.len().div_ceil(1024 * 1024);
+    assert_eq!(
+        ranged_gets.load(Ordering::SeqCst),
+        chunks,
+        "each 1 MiB chunk is fetched exactly once"
+    );
+    Ok(())
+}
@@ -42,7 +42,7 @@ async fn abort_after_snapshot_error_without_shutdown_completes() -> Result<(), B
     let client = BlobClient::new(url, None, None)?.block_blob_client();
     let block_size = NonZeroUsize::new(4).ok_or("block size must be nonzero")?;
     let concurrency = NonZeroUsize::new(2).ok_or("concurrency must be nonzero")?;
-    let stream = BlockBlobStream::new(client, block_size, concurrency);
+    let stream = BlockBlobStream::with_block_blob_client(client, block_size, concurrency);
 
     let (stream, result) = run_failed_write_without_shutdown(stream).await?;
     assert!(result.is_err(), "test must simulate a snapshot error");